@@ -21,7 +21,7 @@ contract StateMachineDeploy is Script {
         vm.createSelectFork("sepolia");
         vm.startBroadcast();
         verifier = new StateMachineVerifier(gateway, fixture.vkey);
-        stateMachine = new StateMachine(address(verifier), fixture.old_phi);
+        stateMachine = new StateMachine(address(verifier), fixture.old_phi, vm.envOr("AUDITOR", bytes32(0)), vm.envOr("EPOCH_LENGTH", uint256(100)));
         vm.stopBroadcast();
     }
 
@@ -0,0 +1,136 @@
+// SPDX-License-Identifier: MIT
+// Generated from the `sol!` block in lib/src/lib.rs. Do not edit by hand, run
+// `cargo run -p state-machine-lib --bin sol-gen` instead.
+pragma solidity ^0.8.20;
+
+struct PublicValuesDeposit {
+    SlotWrite[] slots;
+    PendingWrite[] pending;
+    ShardDelta[] shards;
+    address asset;
+    uint256 amount;
+    int256 supply_delta;
+    bytes32 pkey;
+    bool top_up;
+    uint64 shard;
+    uint64 index;
+    bytes32 old_free_list_hash;
+    bytes32 new_free_list_hash;
+}
+
+struct PendingWrite {
+    uint64 shard;
+    uint64 index;
+    bytes32 account;
+    bytes32 old_t;
+    bytes32 old_v;
+    bytes32 new_t;
+    bytes32 new_v;
+}
+
+struct ShardDelta {
+    uint64 shard;
+    bytes delta;
+}
+
+struct SlotWrite {
+    uint64 shard;
+    uint64 index;
+    bytes32 old_t;
+    bytes32 old_v;
+    bytes32 new_t;
+    bytes32 new_v;
+    bytes32 old_account;
+    bytes32 new_account;
+}
+
+struct PublicValuesSend {
+    SlotWrite[] slots;
+    PendingWrite[] pending;
+    ShardDelta[] shards;
+    address asset;
+    bytes32 pkey_sender;
+    bytes32 pkey_receiver;
+    uint256 fee;
+    int256 supply_delta;
+    address relayer;
+    bytes32 note_ephemeral;
+    bytes note_ciphertext;
+    bytes32 note_tag;
+    bytes32 auditor;
+    bytes auditor_ciphertext;
+    bytes32 auditor_tag;
+}
+
+struct PublicValuesSendMany {
+    SlotWrite[] slots;
+    PendingWrite[] pending;
+    ShardDelta[] shards;
+    address asset;
+    bytes32 pkey_sender;
+    bytes32 auditor;
+    SendManyReceiver[] receivers;
+}
+
+struct SendManyReceiver {
+    bytes32 pkey;
+    bytes32 note_ephemeral;
+    bytes note_ciphertext;
+    bytes32 note_tag;
+    bytes auditor_ciphertext;
+    bytes32 auditor_tag;
+}
+
+struct PublicValuesWithdraw {
+    SlotWrite[] slots;
+    PendingWrite[] pending;
+    ShardDelta[] shards;
+    address asset;
+    uint256 amount;
+    address recipient;
+    bytes32 pkey;
+    uint256 fee;
+    int256 supply_delta;
+    address relayer;
+}
+
+struct PublicValuesRotate {
+    SlotWrite[] slots;
+    PendingWrite[] pending;
+    ShardDelta[] shards;
+    address asset;
+    bytes32 pkey;
+    bytes32 new_pkey;
+}
+
+struct PublicValuesClose {
+    SlotWrite[] slots;
+    PendingWrite[] pending;
+    ShardDelta[] shards;
+    address asset;
+    bytes32 pkey;
+    uint64 shard;
+    uint64 index;
+    bytes32 old_free_list_hash;
+    bytes32 new_free_list_hash;
+}
+
+struct PublicValuesAttest {
+    bytes phi;
+    uint64 shard;
+    uint64 index;
+    bytes32 account;
+    bytes32 t;
+    address asset;
+    uint256 supply;
+    bytes32 pkey;
+    uint256 threshold;
+    bytes32 nonce;
+}
+
+struct PublicValuesRollover {
+    SlotWrite[] slots;
+    PendingWrite[] pending;
+    ShardDelta[] shards;
+    uint64 shard;
+}
@@ -2,47 +2,227 @@
 pragma solidity ^0.8.20;
 
 import { StateMachineVerifier } from "./StateMachineVerifier.sol";
+import { IERC20 } from "./IERC20.sol";
+import { SlotWrite, PendingWrite, ShardDelta, PublicValuesDeposit, PublicValuesSend, PublicValuesSendMany, PublicValuesWithdraw, PublicValuesRotate, PublicValuesClose, PublicValuesAttest, PublicValuesRollover } from "./PublicValues.sol";
 
 contract StateMachine {
     StateMachineVerifier verifier;
-    bytes internal phi;
-    mapping(bytes32 => bytes32) public publicKeyToRandomness;
+    // The ledger is split into shards, each committed by its own phi, stored as a G1 point in the
+    // 128-byte encoding of the EIP-2537 precompiles. A shard that has never been written to holds
+    // the phi of an empty shard.
+    bytes internal emptyPhi;
+    mapping(uint64 => bytes) internal phis;
+    // The ciphertext and pending transfers of every slot, by shard and index, zero while empty.
+    // Actions do not pin the phis: each names the values the slots it writes must hold, and adds
+    // what it changes to the phis, so actions writing different slots land in any order.
+    struct Ciphertext {
+        bytes32 t;
+        bytes32 v;
+    }
+    mapping(uint64 => mapping(uint64 => Ciphertext)) public ciphertexts;
+    // The registry key of the account in every slot, zero while empty: the public key for ETH, and
+    // sha256 of the public key and the token address for any other asset. Every write names the
+    // account it expects, so a ciphertext cannot be spent or credited as another asset's.
+    mapping(uint64 => mapping(uint64 => bytes32)) public accounts;
+    // Whether an account is open in some slot, so a key opens at most one account per asset.
+    mapping(bytes32 => bool) public openAccounts;
+    // Transfers in are held apart from the spendable balances, encrypted to the account like its
+    // ciphertext, until its shard is rolled over. No phi commits them.
+    mapping(uint64 => mapping(uint64 => Ciphertext)) public pendingTransfers;
+    // How many slots of each shard have pending transfers. A rollover has to clear all of them.
+    mapping(uint64 => uint64) public pendingSlots;
+    // Each shard's pending transfers are rolled over at most once per epoch of `epochLength`
+    // blocks, so a proof made after the rollover stays valid until the next epoch.
+    uint256 public epochLength;
+    mapping(uint64 => mapping(uint256 => bool)) public rolledOver;
+    // sha256 of the next slot never used and then the closed slots, each as a little-endian
+    // uint64, the closed slots in the order they will be reused from the end. Nothing is used or
+    // closed at deploy, so it starts as the hash of a zero index.
+    bytes32 public freeListHash = sha256(new bytes(8));
+    // The public key every transfer amount is escrowed to, zero for none.
+    bytes32 public auditor;
+    // Deposits less everything paid out, by asset. The contract always holds at least this much.
+    mapping(address => uint256) public totalSupply;
+
+    event AccountClosed(bytes32 indexed pkey, uint64 index);
+    event KeyRotated(bytes32 indexed pkey, bytes32 indexed new_pkey);
+    event RolledOver(uint64 indexed shard, uint256 epoch);
 
-    constructor(address _verifier, bytes memory _phi) {
+    constructor(address _verifier, bytes memory _phi, bytes32 _auditor, uint256 _epochLength) {
+        require(_epochLength > 0, "epoch length must be greater than 0");
         verifier = StateMachineVerifier(_verifier);
-        phi = _phi;
+        emptyPhi = _phi;
+        auditor = _auditor;
+        epochLength = _epochLength;
+    }
+
+    function epoch() public view returns (uint256) {
+        return block.number / epochLength;
+    }
+
+    function getCurrentState(uint64 shard) public view returns (bytes memory) {
+        if (phis[shard].length == 0) {
+            return emptyPhi;
+        }
+        return phis[shard];
+    }
+
+    // Adds two G1 points with the EIP-2537 precompile, which needs the Prague hard fork.
+    function g1Add(bytes memory a, bytes memory b) internal view returns (bytes memory) {
+        (bool ok, bytes memory sum) = address(0x0b).staticcall(bytes.concat(a, b));
+        require(ok && sum.length == 128, "G1 addition failed");
+        return sum;
+    }
+
+    // Checks every slot an action writes still holds the value and account it was proven against,
+    // writes the new values, and adds the deltas to the phis of their shards.
+    function applyWrites(SlotWrite[] memory slots, PendingWrite[] memory pending, ShardDelta[] memory shards) internal {
+        // Pending transfers are checked against the account before a close clears it
+        for (uint256 i = 0; i < pending.length; i++) {
+            bytes32 account = accounts[pending[i].shard][pending[i].index];
+            require(account != 0 && account == pending[i].account, "pending transfers of another account");
+            Ciphertext storage p = pendingTransfers[pending[i].shard][pending[i].index];
+            require(p.t == pending[i].old_t && p.v == pending[i].old_v, "pending transfers do not match");
+            if (p.t == 0 && pending[i].new_t != 0) {
+                pendingSlots[pending[i].shard]++;
+            } else if (p.t != 0 && pending[i].new_t == 0) {
+                pendingSlots[pending[i].shard]--;
+            }
+            p.t = pending[i].new_t;
+            p.v = pending[i].new_v;
+        }
+        for (uint256 i = 0; i < slots.length; i++) {
+            Ciphertext storage c = ciphertexts[slots[i].shard][slots[i].index];
+            require(c.t == slots[i].old_t && c.v == slots[i].old_v, "slot does not match");
+            require(accounts[slots[i].shard][slots[i].index] == slots[i].old_account, "slot holds another account");
+            c.t = slots[i].new_t;
+            c.v = slots[i].new_v;
+            if (slots[i].new_account != slots[i].old_account) {
+                delete openAccounts[slots[i].old_account];
+                if (slots[i].new_account != 0) {
+                    require(!openAccounts[slots[i].new_account], "account already open");
+                    openAccounts[slots[i].new_account] = true;
+                }
+                accounts[slots[i].shard][slots[i].index] = slots[i].new_account;
+            }
+        }
+        for (uint256 i = 0; i < shards.length; i++) {
+            phis[shards[i].shard] = g1Add(getCurrentState(shards[i].shard), shards[i].delta);
+        }
+    }
+
+    // Moves the total supply of `asset` by the delta the program committed, which must be what the
+    // action pulled in less what it paid out. The program commits the delta rather than the total,
+    // since the total depends on every action before this one.
+    function settleSupply(address asset, int256 delta, uint256 pulledIn, uint256 paidOut) internal {
+        require(delta == int256(pulledIn) - int256(paidOut), "supply delta does not match");
+        require(delta >= 0 || uint256(-delta) <= totalSupply[asset], "payout exceeds the total supply");
+        totalSupply[asset] = delta >= 0 ? totalSupply[asset] + uint256(delta) : totalSupply[asset] - uint256(-delta);
+    }
+
+    // Whether the contract holds at least the total supply of `asset`. Only a bug could make it
+    // false, so monitors can alert on it.
+    function solvent(address asset) public view returns (bool) {
+        uint256 held = asset == address(0) ? address(this).balance : IERC20(asset).balanceOf(address(this));
+        return held >= totalSupply[asset];
     }
 
-    function getCurrentState() public view returns (bytes memory) {
-        return phi;
+    // Pays out `amount` of `asset` held by the contract, the zero address being ETH.
+    function pay(address asset, address to, uint256 amount) internal {
+        if (amount == 0) {
+            return;
+        }
+        if (asset == address(0)) {
+            require(amount <= address(this).balance, "insufficient balance");
+            payable(to).transfer(amount);
+        } else {
+            require(IERC20(asset).transfer(to, amount), "token transfer failed");
+        }
     }
 
+    // ETH is deposited as msg.value, any other asset is pulled from the caller, who must have
+    // approved the amount.
     function deposit(bytes calldata _publicValues, bytes calldata _proofBytes) public payable {
-        (bytes memory old_phi, bytes memory next_phi, uint256 amount, bytes32 pkey, bytes32 v) = verifier.verifyStateMachineDepositProof(_publicValues, _proofBytes);
-        require(keccak256(old_phi) == keccak256((phi)), "current state does not match");
-        require(amount == msg.value, "amount must be greater than 0");
-        phi = next_phi;
-        publicKeyToRandomness[pkey] = v;
+        PublicValuesDeposit memory values = verifier.verifyStateMachineDepositProof(_publicValues, _proofBytes);
+        if (values.asset == address(0)) {
+            require(values.amount == msg.value, "amount must be greater than 0");
+        } else {
+            require(msg.value == 0, "token deposits carry no value");
+        }
+        // A top-up is held as pending like a transfer. Opening an account takes a slot off the
+        // free list, so it is only applied against the free list it was proven against.
+        applyWrites(values.slots, values.pending, values.shards);
+        if (!values.top_up) {
+            require(values.old_free_list_hash == freeListHash, "free list does not match");
+            freeListHash = values.new_free_list_hash;
+        }
+        settleSupply(values.asset, values.supply_delta, values.amount, 0);
+        if (values.asset != address(0)) {
+            require(IERC20(values.asset).transferFrom(msg.sender, address(this), values.amount), "token transfer failed");
+        }
     }
 
     function send(bytes calldata _publicValues, bytes calldata _proofBytes) public {
-        (bytes memory old_phi, bytes memory next_phi) = verifier.verifyStateMachineSendProof(_publicValues, _proofBytes);
-        require(keccak256(old_phi) == keccak256((phi)), "current state does not match");
-        phi = next_phi;
+        PublicValuesSend memory values = verifier.verifyStateMachineSendProof(_publicValues, _proofBytes);
+        require(values.auditor == auditor, "amount not escrowed to the auditor");
+        applyWrites(values.slots, values.pending, values.shards);
+        settleSupply(values.asset, values.supply_delta, 0, values.fee);
+        pay(values.asset, values.relayer, values.fee);
+    }
+
+    function sendMany(bytes calldata _publicValues, bytes calldata _proofBytes) public {
+        PublicValuesSendMany memory values = verifier.verifyStateMachineSendManyProof(_publicValues, _proofBytes);
+        require(values.auditor == auditor, "amount not escrowed to the auditor");
+        applyWrites(values.slots, values.pending, values.shards);
     }
 
     function withdraw(bytes calldata _publicValues, bytes calldata _proofBytes) public {
-        (bytes memory old_phi, bytes memory next_phi, uint256 amount, address recipient) = verifier.verifyStateMachineWithdrawProof(_publicValues, _proofBytes);
-        require(keccak256(old_phi) == keccak256((phi)), "current state does not match");
-        require(amount <= address(this).balance, "insufficient balance");
-        payable(recipient).transfer(amount);
-        phi = next_phi;
+        PublicValuesWithdraw memory values = verifier.verifyStateMachineWithdrawProof(_publicValues, _proofBytes);
+        applyWrites(values.slots, values.pending, values.shards);
+        settleSupply(values.asset, values.supply_delta, 0, values.amount + values.fee);
+        pay(values.asset, values.recipient, values.amount);
+        pay(values.asset, values.relayer, values.fee);
     }
 
     function rotate(bytes calldata _publicValues, bytes calldata _proofBytes) public {
-        (bytes memory old_phi, bytes memory next_phi, bytes32 pubkey, bytes32 new_t) = verifier.verifyStateMachineRotateProof(_publicValues, _proofBytes);
-        require(keccak256(old_phi) == keccak256((phi)), "current state does not match");
-        publicKeyToRandomness[pubkey] = new_t;
-        phi = next_phi;
+        PublicValuesRotate memory values = verifier.verifyStateMachineRotateProof(_publicValues, _proofBytes);
+        applyWrites(values.slots, values.pending, values.shards);
+        if (values.new_pkey != values.pkey) {
+            emit KeyRotated(values.pkey, values.new_pkey);
+        }
+    }
+
+    function close(bytes calldata _publicValues, bytes calldata _proofBytes) public {
+        PublicValuesClose memory values = verifier.verifyStateMachineCloseProof(_publicValues, _proofBytes);
+        require(values.old_free_list_hash == freeListHash, "free list does not match");
+        // The pending transfers are written from zero to zero, so a transfer in landing first
+        // stops the close.
+        applyWrites(values.slots, values.pending, values.shards);
+        freeListHash = values.new_free_list_hash;
+        emit AccountClosed(values.pkey, values.index);
+    }
+
+    // Checks an attestation against the current state: the phi of the account's shard, the slot's
+    // account and t, which the phi does not commit, and the total supply of the asset. Nothing is
+    // written, so the counterparty still compares the nonce with the one it asked for.
+    function checkAttestation(bytes calldata _publicValues, bytes calldata _proofBytes) public view returns (PublicValuesAttest memory) {
+        PublicValuesAttest memory values = verifier.verifyStateMachineAttestProof(_publicValues, _proofBytes);
+        require(keccak256(values.phi) == keccak256(getCurrentState(values.shard)), "phi does not match");
+        require(accounts[values.shard][values.index] == values.account, "slot holds another account");
+        require(ciphertexts[values.shard][values.index].t == values.t, "slot does not match");
+        require(values.supply == totalSupply[values.asset], "total supply does not match");
+        return values;
+    }
+
+    // Anyone can roll a shard over, once per epoch, but only by clearing every slot with pending
+    // transfers. A transfer in landing after the rollover was proven makes it fail.
+    function rollover(bytes calldata _publicValues, bytes calldata _proofBytes) public {
+        PublicValuesRollover memory values = verifier.verifyStateMachineRolloverProof(_publicValues, _proofBytes);
+        uint256 current = epoch();
+        require(!rolledOver[values.shard][current], "already rolled over this epoch");
+        rolledOver[values.shard][current] = true;
+        applyWrites(values.slots, values.pending, values.shards);
+        require(pendingSlots[values.shard] == 0, "rollover leaves pending transfers");
+        emit RolledOver(values.shard, current);
     }
-}
\ No newline at end of file
+}
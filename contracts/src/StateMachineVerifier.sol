@@ -2,33 +2,7 @@
 pragma solidity ^0.8.20;
 
 import {ISP1Verifier} from "@sp1-contracts/ISP1Verifier.sol";
-
-struct PublicValuesDeposit {
-    bytes old_phi;
-    bytes next_phi;
-    uint256 amount;
-    bytes32 pkey;
-    bytes32 t;
-}
-
-struct PublicValuesSend {
-    bytes old_phi;
-    bytes next_phi;
-}
-
-struct PublicValuesWithdraw {
-    bytes old_phi;
-    bytes next_phi;
-    uint256 amount;
-    address recipient;
-}
-
-struct PublicValuesRotate {
-    bytes old_phi;
-    bytes next_phi;
-    bytes32 new_t;
-    bytes32 pkey;
-}
+import {PublicValuesDeposit, PublicValuesSend, PublicValuesSendMany, PublicValuesWithdraw, PublicValuesRotate, PublicValuesClose, PublicValuesAttest, PublicValuesRollover} from "./PublicValues.sol";
 
 contract StateMachineVerifier {
     /// @notice The address of the SP1 verifier contract.
@@ -49,40 +23,74 @@ contract StateMachineVerifier {
     function verifyStateMachineDepositProof(bytes calldata _publicValues, bytes calldata _proofBytes)
         public
         view
-        returns (bytes memory, bytes memory, uint256, bytes32, bytes32)
+        returns (PublicValuesDeposit memory)
     {
         ISP1Verifier(verifier).verifyProof(stateMachineProgramVKey, _publicValues, _proofBytes);
-        PublicValuesDeposit memory publicValues = abi.decode(_publicValues, (PublicValuesDeposit));
-        return (publicValues.old_phi, publicValues.next_phi, publicValues.amount, publicValues.pkey, publicValues.t);
+        return abi.decode(_publicValues, (PublicValuesDeposit));
     }
 
     function verifyStateMachineSendProof(bytes calldata _publicValues, bytes calldata _proofBytes)
         public
         view
-        returns (bytes memory, bytes memory)
+        returns (PublicValuesSend memory)
+    {
+        ISP1Verifier(verifier).verifyProof(stateMachineProgramVKey, _publicValues, _proofBytes);
+        return abi.decode(_publicValues, (PublicValuesSend));
+    }
+
+    function verifyStateMachineSendManyProof(bytes calldata _publicValues, bytes calldata _proofBytes)
+        public
+        view
+        returns (PublicValuesSendMany memory)
     {
         ISP1Verifier(verifier).verifyProof(stateMachineProgramVKey, _publicValues, _proofBytes);
-        PublicValuesSend memory publicValues = abi.decode(_publicValues, (PublicValuesSend));
-        return (publicValues.old_phi, publicValues.next_phi);
+        return abi.decode(_publicValues, (PublicValuesSendMany));
     }
 
     function verifyStateMachineWithdrawProof(bytes calldata _publicValues, bytes calldata _proofBytes)
         public
         view
-        returns (bytes memory, bytes memory, uint256, address)
+        returns (PublicValuesWithdraw memory)
     {
         ISP1Verifier(verifier).verifyProof(stateMachineProgramVKey, _publicValues, _proofBytes);
-        PublicValuesWithdraw memory publicValues = abi.decode(_publicValues, (PublicValuesWithdraw));
-        return (publicValues.old_phi, publicValues.next_phi, publicValues.amount, publicValues.recipient);
+        return abi.decode(_publicValues, (PublicValuesWithdraw));
     }
 
     function verifyStateMachineRotateProof(bytes calldata _publicValues, bytes calldata _proofBytes)
         public
         view
-        returns (bytes memory, bytes memory, bytes32, bytes32)
+        returns (PublicValuesRotate memory)
+    {
+        ISP1Verifier(verifier).verifyProof(stateMachineProgramVKey, _publicValues, _proofBytes);
+        return abi.decode(_publicValues, (PublicValuesRotate));
+    }
+
+    function verifyStateMachineCloseProof(bytes calldata _publicValues, bytes calldata _proofBytes)
+        public
+        view
+        returns (PublicValuesClose memory)
+    {
+        ISP1Verifier(verifier).verifyProof(stateMachineProgramVKey, _publicValues, _proofBytes);
+        return abi.decode(_publicValues, (PublicValuesClose));
+    }
+
+    /// @notice Attestations change nothing, so the caller checks them against the current state,
+    ///         with `StateMachine.checkAttestation`, and `nonce` with the one it asked for.
+    function verifyStateMachineAttestProof(bytes calldata _publicValues, bytes calldata _proofBytes)
+        public
+        view
+        returns (PublicValuesAttest memory)
+    {
+        ISP1Verifier(verifier).verifyProof(stateMachineProgramVKey, _publicValues, _proofBytes);
+        return abi.decode(_publicValues, (PublicValuesAttest));
+    }
+
+    function verifyStateMachineRolloverProof(bytes calldata _publicValues, bytes calldata _proofBytes)
+        public
+        view
+        returns (PublicValuesRollover memory)
     {
         ISP1Verifier(verifier).verifyProof(stateMachineProgramVKey, _publicValues, _proofBytes);
-        PublicValuesRotate memory publicValues = abi.decode(_publicValues, (PublicValuesRotate));
-        return (publicValues.old_phi, publicValues.next_phi, publicValues.pkey, publicValues.new_t);
+        return abi.decode(_publicValues, (PublicValuesRollover));
     }
 }
\ No newline at end of file
@@ -0,0 +1,32 @@
+//! Writes the Solidity public values structs generated from the Rust definitions.
+//!
+//! You can run this script from the repository root using the following command:
+//! ```shell
+//! cargo run -p state-machine-lib --bin sol-gen
+//! ```
+//! or, to fail if the checked-in file is out of date:
+//! ```shell
+//! cargo run -p state-machine-lib --bin sol-gen -- --check
+//! ```
+
+use state_machine_lib::solidity::{solidity_structs, SOLIDITY_PATH};
+use std::path::PathBuf;
+
+fn main() {
+    let check = std::env::args().any(|arg| arg == "--check");
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("..").join(SOLIDITY_PATH);
+    let generated = solidity_structs();
+
+    if check {
+        let current = std::fs::read_to_string(&path).unwrap_or_default();
+        if current != generated {
+            eprintln!("Error: {} is out of date with the `sol!` structs in lib/src/lib.rs.", SOLIDITY_PATH);
+            eprintln!("Run `cargo run -p state-machine-lib --bin sol-gen` to regenerate it.");
+            std::process::exit(1);
+        }
+        println!("{} is up to date.", SOLIDITY_PATH);
+    } else {
+        std::fs::write(&path, generated).expect("failed to write Solidity structs");
+        println!("Wrote {}", path.display());
+    }
+}
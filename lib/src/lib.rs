@@ -1,9 +1,28 @@
 use alloy_sol_types::sol;
 use kzg_rs::KzgError;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sp1_bls12_381::{Scalar, G1Affine, G2Affine};
 use std::collections::HashMap;
 
+pub mod note;
+pub mod public_values;
+pub mod replay;
+pub mod solidity;
+pub mod spend;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod writes;
+
+pub use public_values::{
+    DecodedAttest, DecodedClose, DecodedDeposit, DecodedPendingWrite, DecodedPublicValues, DecodedReceiver, DecodedRollover,
+    DecodedRotate, DecodedSend, DecodedSendMany, DecodedShardDelta, DecodedSlotWrite, DecodedWithdraw, DecodedWrites,
+};
+pub use note::{audit_transfers, scan_notes, AuditedTransfer, Note, ReceivedNote};
+pub use replay::replay;
+pub use spend::{view_key, Authorization, Signature};
+pub use writes::Writes;
+
 fn compute_lagrange_basis(tau: Scalar, domain: Vec<Scalar>) -> Result<Vec<G1Affine>, KzgError> {
     let mut basis: Vec<G1Affine> = Vec::new();
     let g1 = G1Affine::generator();
@@ -21,6 +40,25 @@ fn compute_lagrange_basis(tau: Scalar, domain: Vec<Scalar>) -> Result<Vec<G1Affi
 
 // TODO: define struct G1Affine for phi
 
+/// The asset id of ETH. Any other asset is the address of its ERC-20 token.
+pub const NATIVE_ASSET: [u8; 20] = [0u8; 20];
+
+/// The registry key of the account `pkey` holds in `asset`. A key has one account, and so one slot,
+/// per asset. ETH accounts are keyed by the public key alone, other assets by SHA-256 over the
+/// public key and the token address.
+pub fn account_key(pkey: &Scalar, asset: &[u8; 20]) -> [u8; 32] {
+    if *asset == NATIVE_ASSET {
+        return pkey.to_bytes();
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(pkey.to_bytes());
+    hasher.update(asset);
+    hasher.finalize().into()
+}
+
+/// The ledger is split into shards of `degree` slots, each committed by its own phi over the same
+/// Lagrange basis. Slots are numbered across shards, so slot `s` is index `s % degree` of shard
+/// `s / degree`, and the phis of a ledger are passed around as a slice indexed by shard.
 #[derive(Debug,Clone,Serialize,Deserialize)]
 pub struct PublicParams {
     pub degree: usize,
@@ -30,11 +68,17 @@ pub struct PublicParams {
     pub g1_points: Vec<G1Affine>,
     pub g2_points: Vec<G2Affine>,
     pub g1_lagrange_basis: Vec<G1Affine>,
-    pub idx: usize,
-    pub v: Vec<Scalar>,
+    pub idx: usize, // the next slot never used
+    pub v: Vec<Scalar>, // by slot, over every shard
     pub t: Vec<Scalar>,
-    pub pkeys: Vec<Scalar>,
-    pub index_of: HashMap<[u8; 32], usize>,
+    pub pending_t: Vec<Scalar>, // by slot, transfers in since the last rollover, encrypted like t and v
+    pub pending_v: Vec<Scalar>, // zero for none
+    pub pkeys: Vec<Scalar>, // by slot, zero when the slot is empty
+    pub assets: Vec<[u8; 20]>, // by slot
+    pub index_of: HashMap<[u8; 32], usize>, // by account_key
+    pub free: Vec<usize>, // closed slots, reused last in first out
+    pub auditor: Option<Scalar>, // every transfer amount is also encrypted to this public key
+    pub supply: HashMap<[u8; 20], u64>, // by asset, deposits less everything the contract paid out
 }
 
 impl PublicParams {
@@ -50,8 +94,14 @@ impl PublicParams {
             idx: 0,
             v: vec![Scalar::zero(); degree],
             t: vec![Scalar::zero(); degree],
-            pkeys: Vec::new(),
+            pending_t: vec![Scalar::zero(); degree],
+            pending_v: vec![Scalar::zero(); degree],
+            pkeys: vec![Scalar::zero(); degree],
+            assets: vec![NATIVE_ASSET; degree],
             index_of: HashMap::new(),
+            free: Vec::new(),
+            auditor: None,
+            supply: HashMap::new(),
         }
     }
 
@@ -83,10 +133,110 @@ impl PublicParams {
             idx: 0,
             v: vec![Scalar::zero(); degree],
             t: vec![Scalar::zero(); degree],
-            pkeys: Vec::new(),
+            pending_t: vec![Scalar::zero(); degree],
+            pending_v: vec![Scalar::zero(); degree],
+            pkeys: vec![Scalar::zero(); degree],
+            assets: vec![NATIVE_ASSET; degree],
             index_of: HashMap::new(),
+            free: Vec::new(),
+            auditor: None,
+            supply: HashMap::new(),
+        }
+    }
+
+    /// Escrows every transfer amount to `auditor`, the public key of an auditor who can then read
+    /// them without being able to spend.
+    pub fn with_auditor(mut self, auditor: Scalar) -> PublicParams {
+        self.auditor = Some(auditor);
+        self
+    }
+
+    /// The total supply of `asset`, which the contract must hold at least as much of.
+    pub fn supply(&self, asset: &[u8; 20]) -> u64 {
+        self.supply.get(asset).copied().unwrap_or(0)
+    }
+
+    /// The number of shards opened so far.
+    pub fn shards(&self) -> usize {
+        self.v.len() / self.degree
+    }
+
+    /// The `(shard, index)` address of a slot.
+    pub fn locate(&self, slot: usize) -> (usize, usize) {
+        (slot / self.degree, slot % self.degree)
+    }
+
+    /// Appends an empty shard, whose phi is the identity.
+    pub fn open_shard(&mut self) {
+        let len = self.v.len() + self.degree;
+        self.v.resize(len, Scalar::zero());
+        self.t.resize(len, Scalar::zero());
+        self.pending_t.resize(len, Scalar::zero());
+        self.pending_v.resize(len, Scalar::zero());
+        self.pkeys.resize(len, Scalar::zero());
+        self.assets.resize(len, NATIVE_ASSET);
+    }
+
+    /// The slot of the account `pkey` holds in `asset`. The slot must record that key and asset,
+    /// so a registry pointing at another account's slot is caught before anything is written.
+    pub fn slot_of(&self, pkey: &Scalar, asset: &[u8; 20]) -> Result<usize, String> {
+        match self.index_of.get(&account_key(pkey, asset)) {
+            Some(idx) if *idx < self.v.len() && self.pkeys[*idx] == *pkey && self.assets[*idx] == *asset => Ok(*idx),
+            Some(_) => Err("Account does not match its slot".to_string()),
+            None => Err("Public key not found".to_string()),
+        }
+    }
+
+    /// The registry key of the account in `slot`, as the contract stores it, zero while empty.
+    pub fn account(&self, slot: usize) -> [u8; 32] {
+        match self.pkeys.get(slot) {
+            Some(pkey) if *pkey != Scalar::zero() => account_key(pkey, &self.assets[slot]),
+            _ => [0u8; 32],
         }
     }
+
+    /// Whether anything was transferred to `slot` since its shard was last rolled over.
+    pub fn has_pending(&self, slot: usize) -> bool {
+        self.pending_t[slot] != Scalar::zero()
+    }
+
+    /// The total pending for `slot`, decrypted with the view key `sk` by trying every value up to
+    /// `max`. Pending transfers are encrypted to the account like its balance, so only the key
+    /// holder can read them.
+    pub fn pending_amount(&self, sk: [u64; 4], slot: usize, max: u64) -> Result<u64, String> {
+        if !self.has_pending(slot) {
+            return Ok(0);
+        }
+        ElGamal::new(self.g)
+            .solve(sk, self.pending_t[slot], self.pending_v[slot], max)
+            .map_err(|_| format!("Pending amount is larger than {}", max))
+    }
+
+    /// SHA-256 over the next unused slot and then the free list, each slot as a u64 little-endian.
+    /// The contract keeps this hash so that deposits and closes cannot make up their own free list,
+    /// nor open a slot other than the next one when it is empty.
+    pub fn free_list_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update((self.idx as u64).to_le_bytes());
+        for idx in self.free.iter() {
+            hasher.update((*idx as u64).to_le_bytes());
+        }
+        hasher.finalize().into()
+    }
+
+    /// SHA-256 over the degree and every SRS point, so a proof can be tied to the setup it was
+    /// generated against without shipping the points themselves.
+    pub fn srs_fingerprint(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update((self.degree as u64).to_le_bytes());
+        for point in self.g1_points.iter().chain(self.g1_lagrange_basis.iter()) {
+            hasher.update(point.to_compressed());
+        }
+        for point in self.g2_points.iter() {
+            hasher.update(point.to_compressed());
+        }
+        hasher.finalize().into()
+    }
 }
 
 #[derive(Debug)]
@@ -141,121 +291,526 @@ impl ElGamal {
         }
         Err("Decryption failed".to_string())
     }
+
+    /// Recovers `m` by trying every value up to `max`, for when the key holder does not know their
+    /// balance, e.g. after receiving a transfer.
+    pub fn solve(&self, sk: [u64; 4], c1: Scalar, c2: Scalar, max: u64) -> Result<u64, String> {
+        let g_m = c2 * c1.pow(&sk).invert().unwrap();
+        let mut g_x = Scalar::one();
+        for x in 0..=max {
+            if g_x == g_m {
+                return Ok(x);
+            }
+            g_x *= self.g;
+        }
+        Err(format!("Balance is larger than {}", max))
+    }
+}
+
+/// The order of the multiplicative group of the scalar field, little-endian. ElGamal exponents
+/// (secret keys, randomness and additives) only matter modulo this value.
+pub const EXPONENT_ORDER: [u64; 4] = [
+    0xffff_ffff_0000_0000,
+    0x53bd_a402_fffe_5bfe,
+    0x3339_d808_09a1_d805,
+    0x73ed_a753_299d_7d48,
+];
+
+fn exponent_lt_order(e: &[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if e[i] != EXPONENT_ORDER[i] {
+            return e[i] < EXPONENT_ORDER[i];
+        }
+    }
+    false
 }
 
-pub fn deposit(pp: &mut PublicParams, pk_a: Scalar, r_a: [u64; 4], m_a: u64 , phi: G1Affine) -> Result<G1Affine, String> {
-    if pp.idx >= pp.degree {
-        return Err("Deposit failed".to_string());
+fn sub_order(e: [u64; 4]) -> [u64; 4] {
+    let mut out = [0u64; 4];
+    let mut borrow = 0u64;
+    for i in 0..4 {
+        let (d, b1) = e[i].overflowing_sub(EXPONENT_ORDER[i]);
+        let (d, b2) = d.overflowing_sub(borrow);
+        out[i] = d;
+        borrow = (b1 || b2) as u64;
+    }
+    out
+}
+
+/// Reads a scalar as an exponent, reduced modulo `EXPONENT_ORDER`.
+pub fn scalar_to_exponent(s: &Scalar) -> [u64; 4] {
+    let bytes = s.to_bytes();
+    let mut e = [0u64; 4];
+    for (i, limb) in e.iter_mut().enumerate() {
+        *limb = u64::from_le_bytes(bytes[i * 8..(i + 1) * 8].try_into().unwrap());
+    }
+    if exponent_lt_order(&e) { e } else { sub_order(e) }
+}
+
+/// Adds two reduced exponents modulo `EXPONENT_ORDER`.
+pub fn add_exponents(a: [u64; 4], b: [u64; 4]) -> [u64; 4] {
+    let mut sum = [0u64; 4];
+    let mut carry = 0u128;
+    for i in 0..4 {
+        let s = a[i] as u128 + b[i] as u128 + carry;
+        sum[i] = s as u64;
+        carry = s >> 64;
+    }
+    // Both inputs are below the order, which is below 2^255, so the sum never overflows.
+    if exponent_lt_order(&sum) { sum } else { sub_order(sum) }
+}
+
+/// Copies the phis of a ledger, checking there is one per shard.
+fn shard_phis(pp: &PublicParams, phi: &[G1Affine]) -> Result<Vec<G1Affine>, String> {
+    if phi.len() != pp.shards() {
+        return Err(format!("Expected {} shard phis, got {}", pp.shards(), phi.len()));
+    }
+    Ok(phi.to_vec())
+}
+
+/// Adds a deposit of `amount` to the total supply of `asset`.
+pub(crate) fn mint(pp: &mut PublicParams, asset: [u8; 20], amount: u64) -> Result<(), String> {
+    let supply = pp.supply(&asset).checked_add(amount).ok_or("Total supply overflows".to_string())?;
+    pp.supply.insert(asset, supply);
+    Ok(())
+}
+
+/// Takes `amount` paid out by the contract, to a recipient or a relayer, off the total supply.
+pub(crate) fn burn(pp: &mut PublicParams, asset: [u8; 20], amount: u64) -> Result<(), String> {
+    let supply = pp.supply(&asset).checked_sub(amount).ok_or("Payout exceeds the total supply".to_string())?;
+    pp.supply.insert(asset, supply);
+    Ok(())
+}
+
+/// Moves the total supply of `asset` by the `delta` an action committed to.
+pub(crate) fn settle_supply(pp: &mut PublicParams, asset: [u8; 20], delta: i128) -> Result<(), String> {
+    let amount = u64::try_from(delta.unsigned_abs()).map_err(|_| "Supply delta overflows".to_string())?;
+    if delta < 0 { burn(pp, asset, amount) } else { mint(pp, asset, amount) }
+}
+
+/// Moves the phi of the shard holding `slot` by `delta` times the slot's Lagrange basis point.
+fn shift_phi(pp: &PublicParams, phi: &mut [G1Affine], slot: usize, delta: Scalar) {
+    let (shard, index) = pp.locate(slot);
+    phi[shard] = phi[shard].add_affine(&G1Affine::from(pp.g1_lagrange_basis[index] * delta));
+}
+
+/// Debits `amount` from the ciphertext of `slot`, encrypted to `pkey`, and re-randomizes it under
+/// `random`: `(t, v)` becomes `(t * g^random, v * pkey^random / g^amount)`. The quotient of the new
+/// and old ciphertexts is then a fresh encryption of the debit, which only the key holder can read.
+fn debit(pp: &mut PublicParams, phi: &mut [G1Affine], slot: usize, pkey: Scalar, amount: u64, random: [u64; 4]) {
+    let factor = pkey.pow(&random) * pp.g.pow(&[amount, 0, 0, 0]).invert().unwrap();
+    let delta = pp.v[slot] * (factor - Scalar::one());
+    shift_phi(pp, phi, slot, delta);
+    pp.t[slot] *= pp.g.pow(&random);
+    pp.v[slot] *= factor;
+}
+
+/// Credits `amount` to the pending transfers of `slot`, encrypted to its key `pkey` under `random`,
+/// leaving its spendable ciphertext and the phi of its shard alone until the next rollover. Credits
+/// are multiplied in component by component, so the pending pair decrypts to their sum.
+fn credit(pp: &mut PublicParams, slot: usize, pkey: Scalar, amount: u64, random: [u64; 4]) {
+    let (t, v) = ElGamal::new(pp.g).encrypt(pkey, amount, random);
+    if pp.has_pending(slot) {
+        pp.pending_t[slot] *= t;
+        pp.pending_v[slot] *= v;
+    } else {
+        pp.pending_t[slot] = t;
+        pp.pending_v[slot] = v;
+    }
+}
+
+/// Deposits `m_a` of `asset` to `pk_a`. A key without an account in `asset` is given the last
+/// closed slot, or else the next unused one, opening a new shard when the last one is full,
+/// encrypted under `r_a`. A key that already has an account in `asset` is topped up like a transfer:
+/// `m_a` is encrypted under `r_a` and credited to its pending transfers, so its phi is left as is.
+/// Either way `m_a` is added to the total supply of `asset`.
+pub fn deposit(pp: &mut PublicParams, pk_a: Scalar, asset: [u8; 20], r_a: [u64; 4], m_a: u64 , phi: &[G1Affine]) -> Result<Vec<G1Affine>, String> {
+    let mut next_phi = shard_phis(pp, phi)?;
+    if pp.index_of.contains_key(&account_key(&pk_a, &asset)) {
+        let idx = pp.slot_of(&pk_a, &asset)?;
+        mint(pp, asset, m_a)?;
+        credit(pp, idx, pk_a, m_a, r_a);
+        return Ok(next_phi);
+    }
+    // Everything that can fail is checked before the ledger changes
+    let idx = pp.free.last().copied().unwrap_or(pp.idx);
+    if idx < pp.v.len() && pp.pkeys[idx] != Scalar::zero() {
+        return Err("The slot to open is not empty".to_string());
+    }
+    mint(pp, asset, m_a)?;
+    if pp.free.pop().is_none() {
+        if pp.idx == pp.v.len() {
+            pp.open_shard();
+            next_phi.push(G1Affine::identity());
+        }
+        pp.idx += 1;
     }
     let el_gamal = ElGamal::new(pp.g);
     let (t, v) = el_gamal.encrypt(pk_a, m_a, r_a);
-    pp.t[pp.idx] = t;
-    pp.v[pp.idx] = v;
-    pp.pkeys.push(pk_a);
-    pp.index_of.insert(pk_a.to_bytes(), pp.idx);
-    let e = G1Affine::from(v * pp.g1_lagrange_basis[pp.idx]);
-    let next_phi = phi.add_affine(&e);
-    pp.idx += 1;
+    pp.t[idx] = t;
+    pp.v[idx] = v;
+    pp.pkeys[idx] = pk_a;
+    pp.assets[idx] = asset;
+    pp.index_of.insert(account_key(&pk_a, &asset), idx);
+    shift_phi(pp, &mut next_phi, idx, v);
     Ok(next_phi)
 }
 
-pub fn withdraw(pp: &mut PublicParams, sk: [u64; 4], r: [u64; 4], balance: u64,  amount: u64, phi: G1Affine, recipient: [u8; 20]) -> Result<G1Affine, String> {
+/// Takes `amount` plus a relayer `fee` out of the account and the total supply, both paid out by
+/// the contract. The account's ciphertext is re-randomized under `random`.
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw(pp: &mut PublicParams, sk: [u64; 4], asset: [u8; 20], balance: u64,  amount: u64, fee: u64, random: [u64; 4], phi: &[G1Affine], recipient: [u8; 20]) -> Result<Vec<G1Affine>, String> {
+    let mut next_phi = shard_phis(pp, phi)?;
     let el_gamal = ElGamal::new(pp.g);
-    let g_r = pp.g.pow(&r);
     let pk = el_gamal.from_skey(sk);
-    let idx = match pp.index_of.get(&pk.to_bytes()) {
-        Some(idx) => *idx,
-        None => return Err("Public key not found".to_string())
-    };
-    if idx >= pp.degree || pp.t[idx] != g_r {
+    let idx = pp.slot_of(&pk, &asset)?;
+    if idx >= pp.v.len() {
         return Err("Withdraw failed".to_string());
     }
     let c1 = pp.t[idx];
     let c2 = pp.v[idx];
-    let m = el_gamal.decrypt(sk, c1, c2, balance).unwrap();
-    if amount > m {
+    let m = el_gamal.decrypt(sk, c1, c2, balance)?;
+    let total = amount.checked_add(fee).ok_or("Amount and fee overflow".to_string())?;
+    if total > m {
         return Err("Withdraw exceeds balance".to_string());
     }
-    let delta = c2 * (pp.g.pow(&[amount, 0, 0, 0]).invert().unwrap() - Scalar::one());
-    let multiplier = G1Affine::from(pp.g1_lagrange_basis[idx] * delta);
-    let next_phi = phi.add_affine(&multiplier);
-    pp.v[idx] *= pp.g.pow(&[amount, 0, 0, 0]).invert().unwrap();
+    burn(pp, asset, total)?;
+    debit(pp, &mut next_phi, idx, pk, total, random);
     let _ = recipient;
     Ok(next_phi)
 }
 
-pub fn send(pp: &mut PublicParams, sk_sender: [u64; 4], pk_receiver: Scalar, balance: u64, amount: u64, phi: G1Affine) -> Result<G1Affine, String> {
+/// Moves `amount` of `asset` from the sender to the pending transfers of the receiver, who may be in
+/// another shard, encrypted to the receiver under `random`. Both must have an account in `asset`.
+/// The sender is also debited a relayer `fee`, which the contract pays out, so it leaves the total
+/// supply, and the sender's ciphertext is re-randomized under `debit_random`.
+#[allow(clippy::too_many_arguments)]
+pub fn send(pp: &mut PublicParams, sk_sender: [u64; 4], pk_receiver: Scalar, asset: [u8; 20], balance: u64, amount: u64, fee: u64, random: [u64; 4], debit_random: [u64; 4], phi: &[G1Affine]) -> Result<Vec<G1Affine>, String> {
+    let mut next_phi = shard_phis(pp, phi)?;
     let el_gamal = ElGamal::new(pp.g);
     let pk_sender = el_gamal.from_skey(sk_sender);
-    let idx_sender = match pp.index_of.get(&pk_sender.to_bytes()) {
-        Some(idx) => *idx,
-        None => return Err("Public key not found!".to_string())
-    };
-    let idx_receiver = match pp.index_of.get(&pk_receiver.to_bytes()) {
-        Some(idx) => *idx,
-        None => return Err("Public key not found".to_string())
-    };
-    println!("idx_sender: {:?}", idx_sender);
-    println!("idx_receiver: {:?}", idx_receiver);
-    if idx_sender >= pp.degree || idx_receiver >= pp.degree {
+    let idx_sender = pp.slot_of(&pk_sender, &asset)?;
+    let idx_receiver = pp.slot_of(&pk_receiver, &asset)?;
+    if idx_sender >= pp.v.len() || idx_receiver >= pp.v.len() {
         return Err("Send failed".to_string());
     }
-    let m = el_gamal.decrypt(sk_sender, pp.t[idx_sender], pp.v[idx_sender], balance).unwrap();
-    if amount > m {
+    let m = el_gamal.decrypt(sk_sender, pp.t[idx_sender], pp.v[idx_sender], balance)?;
+    let total = amount.checked_add(fee).ok_or("Amount and fee overflow".to_string())?;
+    if total > m {
         return Err("Send exceeds balance".to_string());
     }
-    let delta_sender = pp.v[idx_sender] * (pp.g.pow(&[amount, 0, 0, 0]).invert().unwrap() - Scalar::one());
-    let delta_receiver = pp.v[idx_receiver] * (pp.g.pow(&[amount, 0, 0, 0]) - Scalar::one());
-    let multiplier_sender = G1Affine::from(pp.g1_lagrange_basis[idx_sender] * delta_sender);
-    let multiplier_receiver = G1Affine::from(pp.g1_lagrange_basis[idx_receiver] * delta_receiver);
-    let next_phi = phi.add_affine(&multiplier_sender).add_affine(&multiplier_receiver);
-    pp.v[idx_sender] *= pp.g.pow(&[amount, 0, 0, 0]).invert().unwrap();
-    pp.v[idx_receiver] *= pp.g.pow(&[amount, 0, 0, 0]);
+    burn(pp, asset, fee)?;
+    debit(pp, &mut next_phi, idx_sender, pk_sender, total, debit_random);
+    credit(pp, idx_receiver, pk_receiver, amount, random);
     Ok(next_phi)
 }
 
-pub fn rotate(pp: &mut PublicParams, skey: [u64; 4] , new_additive: [u64; 4], phi: G1Affine) -> Result<(G1Affine, usize), String> {
+/// Moves `amount` of `asset` to the pending transfers of each `(pkey, amount, random)` receiver in one step, debiting the
+/// sender once for the total and re-randomizing its ciphertext under `debit_random`. Receivers must be distinct,
+/// registered in `asset` and other than the sender.
+pub fn send_many(pp: &mut PublicParams, sk_sender: [u64; 4], asset: [u8; 20], receivers: &[(Scalar, u64, [u64; 4])], balance: u64, debit_random: [u64; 4], phi: &[G1Affine]) -> Result<Vec<G1Affine>, String> {
+    let mut next_phi = shard_phis(pp, phi)?;
+    let el_gamal = ElGamal::new(pp.g);
+    let pk_sender = el_gamal.from_skey(sk_sender);
+    let idx_sender = pp.slot_of(&pk_sender, &asset)?;
+    let mut idx_receivers = Vec::new();
+    for (pk_receiver, _, _) in receivers.iter() {
+        let idx = pp.slot_of(pk_receiver, &asset)?;
+        if idx == idx_sender || idx_receivers.contains(&idx) {
+            return Err("Receivers must be distinct from each other and the sender".to_string());
+        }
+        idx_receivers.push(idx);
+    }
+    let total = receivers
+        .iter()
+        .try_fold(0u64, |total, (_, amount, _)| total.checked_add(*amount))
+        .ok_or("Total amount overflows".to_string())?;
+    let m = el_gamal.decrypt(sk_sender, pp.t[idx_sender], pp.v[idx_sender], balance)?;
+    if total > m {
+        return Err("Send exceeds balance".to_string());
+    }
+    debit(pp, &mut next_phi, idx_sender, pk_sender, total, debit_random);
+    for (idx, (pk_receiver, amount, random)) in idx_receivers.into_iter().zip(receivers.iter()) {
+        credit(pp, idx, *pk_receiver, *amount, *random);
+    }
+    Ok(next_phi)
+}
+
+pub fn rotate(pp: &mut PublicParams, skey: [u64; 4], asset: [u8; 20], new_additive: [u64; 4], phi: &[G1Affine]) -> Result<(Vec<G1Affine>, usize), String> {
+    let mut next_phi = shard_phis(pp, phi)?;
     let el_gamal = ElGamal::new(pp.g);
     let pkey = el_gamal.from_skey(skey);
-    let idx = match pp.index_of.get(&pkey.to_bytes()) {
-        Some(idx) => *idx,
-        None => return Err("Public key not found".to_string())
-    };
-    let delta = pp.v[idx] * (pkey.pow(&new_additive) - Scalar::one());
-    let multiplier = G1Affine::from(pp.g1_lagrange_basis[idx] * delta);
-    let next_phi = phi.add_affine(&multiplier);
-    pp.t[idx] *= pp.g.pow(&new_additive);
-    pp.v[idx] *= pkey.pow(&new_additive);
+    let idx = pp.slot_of(&pkey, &asset)?;
+    debit(pp, &mut next_phi, idx, pkey, 0, new_additive);
     Ok((next_phi, idx))
 }
 
+/// Moves the account of `skey` in `asset` to `new_pkey`, which must not have an account in `asset`
+/// yet. The balance, checked against `balance`, is re-encrypted under `new_pkey` with fresh
+/// randomness `new_random`, so the old secret key can no longer decrypt or spend it. The account
+/// keeps its slot. It must have nothing pending, since pending transfers stay encrypted to the old
+/// key and a rollover would multiply them into the new ciphertext.
+pub fn rekey(pp: &mut PublicParams, skey: [u64; 4], asset: [u8; 20], balance: u64, new_pkey: Scalar, new_random: [u64; 4], phi: &[G1Affine]) -> Result<(Vec<G1Affine>, usize), String> {
+    let mut next_phi = shard_phis(pp, phi)?;
+    let el_gamal = ElGamal::new(pp.g);
+    let pkey = el_gamal.from_skey(skey);
+    let idx = pp.slot_of(&pkey, &asset)?;
+    if pp.index_of.contains_key(&account_key(&new_pkey, &asset)) {
+        return Err("The new public key already has an account".to_string());
+    }
+    if pp.has_pending(idx) {
+        return Err("Roll over the pending transfers first".to_string());
+    }
+    let m = el_gamal.decrypt(skey, pp.t[idx], pp.v[idx], balance).map_err(|_| "Balance does not match".to_string())?;
+    let (t, v) = el_gamal.encrypt(new_pkey, m, new_random);
+    shift_phi(pp, &mut next_phi, idx, v - pp.v[idx]);
+    pp.t[idx] = t;
+    pp.v[idx] = v;
+    pp.pkeys[idx] = new_pkey;
+    pp.index_of.remove(&account_key(&pkey, &asset));
+    pp.index_of.insert(account_key(&new_pkey, &asset), idx);
+    Ok((next_phi, idx))
+}
+
+/// Closes the account of `skey` in `asset`, which must hold a zero balance and have nothing pending,
+/// since a transfer still to be rolled over would be lost with the slot. Its ciphertext and
+/// registry entry are cleared and the slot goes onto the free list for a later deposit to reuse.
+pub fn close(pp: &mut PublicParams, skey: [u64; 4], asset: [u8; 20], phi: &[G1Affine]) -> Result<(Vec<G1Affine>, usize), String> {
+    let mut next_phi = shard_phis(pp, phi)?;
+    let el_gamal = ElGamal::new(pp.g);
+    let pkey = el_gamal.from_skey(skey);
+    let idx = pp.slot_of(&pkey, &asset)?;
+    if el_gamal.decrypt(skey, pp.t[idx], pp.v[idx], 0).is_err() {
+        return Err("Balance is not zero".to_string());
+    }
+    if pp.has_pending(idx) {
+        return Err("Roll over the pending transfers first".to_string());
+    }
+    shift_phi(pp, &mut next_phi, idx, -pp.v[idx]);
+    pp.v[idx] = Scalar::zero();
+    pp.t[idx] = Scalar::zero();
+    pp.pkeys[idx] = Scalar::zero();
+    pp.assets[idx] = NATIVE_ASSET;
+    pp.index_of.remove(&account_key(&pkey, &asset));
+    pp.free.push(idx);
+    Ok((next_phi, idx))
+}
+
+/// Multiplies the pending transfers of every slot in `shard` into its spendable ciphertext, both
+/// being encrypted to the same key. Anyone can roll a shard over, since it needs no key, but the
+/// contract only accepts a rollover that clears every slot of the shard, at most once per epoch,
+/// so a proof made after the rollover stays valid until the next one.
+pub fn rollover(pp: &mut PublicParams, shard: usize, phi: &[G1Affine]) -> Result<Vec<G1Affine>, String> {
+    let mut next_phi = shard_phis(pp, phi)?;
+    if shard >= pp.shards() {
+        return Err(format!("Shard {} does not exist", shard));
+    }
+    let slots = shard * pp.degree..(shard + 1) * pp.degree;
+    if !slots.clone().any(|slot| pp.has_pending(slot)) {
+        return Err(format!("Nothing is pending in shard {}", shard));
+    }
+    for slot in slots {
+        if !pp.has_pending(slot) {
+            continue;
+        }
+        let delta = pp.v[slot] * (pp.pending_v[slot] - Scalar::one());
+        shift_phi(pp, &mut next_phi, slot, delta);
+        pp.t[slot] *= pp.pending_t[slot];
+        pp.v[slot] *= pp.pending_v[slot];
+        pp.pending_t[slot] = Scalar::zero();
+        pp.pending_v[slot] = Scalar::zero();
+    }
+    Ok(next_phi)
+}
+
+/// Checks that the account of `skey` in `asset` holds at least `threshold`, and returns its slot.
+/// Nothing is written, so the ciphertexts of the account's shard must commit to its phi for the
+/// balance to be the one on the ledger. Pending transfers do not count until they are rolled over.
+pub fn attest(pp: &PublicParams, skey: [u64; 4], asset: [u8; 20], balance: u64, threshold: u64, phi: &[G1Affine]) -> Result<usize, String> {
+    let phi = shard_phis(pp, phi)?;
+    let el_gamal = ElGamal::new(pp.g);
+    let pkey = el_gamal.from_skey(skey);
+    let idx = pp.slot_of(&pkey, &asset)?;
+    let (shard, _) = pp.locate(idx);
+    let kzg = KZG::new(pp.g1_lagrange_basis.clone());
+    if kzg.commit(pp.v[shard * pp.degree..(shard + 1) * pp.degree].to_vec()).unwrap() != phi[shard] {
+        return Err(format!("Ciphertexts do not commit to the phi of shard {}", shard));
+    }
+    let m = el_gamal.decrypt(skey, pp.t[idx], pp.v[idx], balance)?;
+    if m < threshold {
+        return Err("Balance is below the threshold".to_string());
+    }
+    Ok(idx)
+}
+
 sol! {
+    /// A write to the ciphertext of the slot at `index` in `shard`, which must still hold `old_t`
+    /// and `old_v` when the action is applied. An empty slot holds zeros. `old_account` and
+    /// `new_account` are the registry keys of the account in the slot before and after, zero
+    /// while empty, so a ciphertext cannot be spent as another account's or in another asset.
+    struct SlotWrite {
+        uint64 shard;
+        uint64 index;
+        bytes32 old_t;
+        bytes32 old_v;
+        bytes32 new_t;
+        bytes32 new_v;
+        bytes32 old_account;
+        bytes32 new_account;
+    }
+
+    /// A write to the pending transfers of a slot, an ElGamal pair encrypted to the account like
+    /// its ciphertext, zeros for none. `account` is the registry key of the account in the slot,
+    /// so a transfer is only credited to an open account in the same asset.
+    struct PendingWrite {
+        uint64 shard;
+        uint64 index;
+        bytes32 account;
+        bytes32 old_t;
+        bytes32 old_v;
+        bytes32 new_t;
+        bytes32 new_v;
+    }
+
+    /// What an action adds to the phi of a shard, as a G1 point in the 128-byte encoding of the
+    /// EIP-2537 precompiles, where the identity is all zeros.
+    struct ShardDelta {
+        uint64 shard;
+        bytes delta;
+    }
+
     /// The public values encoded as a struct that can be easily deserialized inside Solidity.
+    ///
+    /// No action pins the phis of the shards it touches. Each commits what it writes instead: the
+    /// ciphertext and pending transfers of every slot it changes, with the values they must hold
+    /// beforehand, and what it adds to the phi of every shard it touches. The
+    /// contract checks the old values slot by slot and adds the deltas, so actions writing
+    /// different slots can be applied in any order, and the new values let the ledger be rebuilt
+    /// by replaying the public values alone. `asset` is the token of the account, the zero address
+    /// for ETH, so the contract knows what to pull in or pay out. `supply_delta` is what the action
+    /// adds to the total supply of `asset`, which like the phi deltas is the same whatever landed
+    /// before, so the contract moves `totalSupply` by it.
+    ///
+    /// `shard` and `index` are where the account is. A deposit opening an account writes its
+    /// slot, and a top-up only its pending transfers.
     struct PublicValuesDeposit {
-        bytes old_phi;
-        bytes next_phi;
+        SlotWrite[] slots;
+        PendingWrite[] pending;
+        ShardDelta[] shards;
+        address asset;
         uint256 amount;
+        int256 supply_delta;
         bytes32 pkey;
-        bytes32 t;
+        bool top_up;
+        uint64 shard;
+        uint64 index;
+        bytes32 old_free_list_hash;
+        bytes32 new_free_list_hash;
     }
 
+    /// A send writes the sender's slot and the receiver's pending transfers. `auditor` is zero
+    /// when the ledger has no auditor, and otherwise the amount is also encrypted to it under the
+    /// note's ephemeral key.
     struct PublicValuesSend {
-        bytes old_phi;
-        bytes next_phi;
+        SlotWrite[] slots;
+        PendingWrite[] pending;
+        ShardDelta[] shards;
+        address asset;
+        bytes32 pkey_sender;
+        bytes32 pkey_receiver;
+        uint256 fee;
+        int256 supply_delta;
+        address relayer;
+        bytes32 note_ephemeral;
+        bytes note_ciphertext;
+        bytes32 note_tag;
+        bytes32 auditor;
+        bytes auditor_ciphertext;
+        bytes32 auditor_tag;
+    }
+
+    struct SendManyReceiver {
+        bytes32 pkey;
+        bytes32 note_ephemeral;
+        bytes note_ciphertext;
+        bytes32 note_tag;
+        bytes auditor_ciphertext;
+        bytes32 auditor_tag;
+    }
+
+    /// A send to many writes the sender's slot and the pending transfers of every receiver.
+    struct PublicValuesSendMany {
+        SlotWrite[] slots;
+        PendingWrite[] pending;
+        ShardDelta[] shards;
+        address asset;
+        bytes32 pkey_sender;
+        bytes32 auditor;
+        SendManyReceiver[] receivers;
     }
 
     struct PublicValuesWithdraw {
-        bytes old_phi;
-        bytes next_phi;
+        SlotWrite[] slots;
+        PendingWrite[] pending;
+        ShardDelta[] shards;
+        address asset;
         uint256 amount;
         address recipient;
+        bytes32 pkey;
+        uint256 fee;
+        int256 supply_delta;
+        address relayer;
     }
 
     struct PublicValuesRotate {
-        bytes old_phi;
-        bytes next_phi;
+        SlotWrite[] slots;
+        PendingWrite[] pending;
+        ShardDelta[] shards;
+        address asset;
         bytes32 pkey;
-        bytes32 new_t;
+        bytes32 new_pkey;
+    }
+
+    /// A close also lists the account's pending transfers as written from zero to zero, so it
+    /// cannot land after a transfer in that would be lost with the slot.
+    struct PublicValuesClose {
+        SlotWrite[] slots;
+        PendingWrite[] pending;
+        ShardDelta[] shards;
+        address asset;
+        bytes32 pkey;
+        uint64 shard;
+        uint64 index;
+        bytes32 old_free_list_hash;
+        bytes32 new_free_list_hash;
+    }
+
+    /// An attestation that the account of `pkey` in `asset` holds at least `threshold`, made
+    /// against the current `phi` of its shard and changing nothing. `nonce` is picked by the
+    /// counterparty the attestation is for, so it cannot be replayed to anyone else. `phi` is in
+    /// the encoding the contract stores it in. The phi only commits `v`, so the account's slot,
+    /// registry key and `t` are committed too. `supply` is the total supply of `asset` in the
+    /// ledger it was made against.
+    struct PublicValuesAttest {
+        bytes phi;
+        uint64 shard;
+        uint64 index;
+        bytes32 account;
+        bytes32 t;
+        address asset;
+        uint256 supply;
+        bytes32 pkey;
+        uint256 threshold;
+        bytes32 nonce;
+    }
+
+    /// The pending transfers of every slot in `shard` that had any, multiplied into the spendable
+    /// ciphertexts.
+    struct PublicValuesRollover {
+        SlotWrite[] slots;
+        PendingWrite[] pending;
+        ShardDelta[] shards;
+        uint64 shard;
     }
 }
 
@@ -263,6 +818,9 @@ sol! {
 pub struct Deposit {
     pub amount: u64,
     pub pkey: Scalar,
+    /// The token deposited, `NATIVE_ASSET` for ETH.
+    pub asset: [u8; 20],
+    /// Encrypts `amount`, into a new account or the pending transfers of an existing one.
     pub random: [u64; 4],
 }
 
@@ -270,29 +828,162 @@ pub struct Deposit {
 pub struct Send {
     pub balance_sender: u64,
     pub amount: u64,
-    pub skey_sender: [u64; 4],
+    /// The sender's view key, with the spend key's signature over the send.
+    pub auth_sender: Authorization,
     pub pkey_receiver: Scalar,
+    pub asset: [u8; 20],
+    /// Taken from the sender on top of `amount` and paid to `relayer` in the same asset.
+    pub fee: u64,
+    pub relayer: [u8; 20],
+    /// The ephemeral exponent of the note telling the receiver the amount, fresh for every send.
+    pub note_random: [u64; 4],
+    /// Encrypts `amount` into the receiver's pending transfers, fresh for every send.
+    pub transfer_random: [u64; 4],
+    /// Re-randomizes the sender's ciphertext, fresh for every send, so the debit cannot be read off it.
+    pub debit_random: [u64; 4],
+    pub memo: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SendMany {
+    pub balance_sender: u64,
+    pub auth_sender: Authorization,
+    pub asset: [u8; 20],
+    pub receivers: Vec<Receiver>,
+    /// Re-randomizes the sender's ciphertext, fresh for every send.
+    pub debit_random: [u64; 4],
+}
+
+/// One receiver of a `SendMany`, with its own note.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Receiver {
+    pub pkey: Scalar,
+    pub amount: u64,
+    pub note_random: [u64; 4],
+    pub transfer_random: [u64; 4],
+    pub memo: Vec<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Withdraw {
     pub balance: u64,
     pub amount: u64,
-    pub skey: [u64; 4],
-    pub random: [u64; 4],
+    pub auth: Authorization,
+    pub asset: [u8; 20],
     pub recipient: [u8; 20],
+    /// Taken from the balance on top of `amount` and paid to `relayer`.
+    pub fee: u64,
+    pub relayer: [u8; 20],
+    /// Re-randomizes the account's ciphertext, fresh for every withdrawal.
+    pub debit_random: [u64; 4],
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Rotate {
-    pub skey: [u64; 4],
+    pub auth: Authorization,
+    pub asset: [u8; 20],
+    /// Unused when `rekey` is set.
     pub new_additive: [u64; 4],
+    /// Moves the account to a new key pair instead of re-randomizing it under the same key.
+    pub rekey: Option<Rekey>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Rekey {
+    pub balance: u64,
+    /// The public key of the new view key, itself derived from a new spend key.
+    pub new_pkey: Scalar,
+    pub new_random: [u64; 4],
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Close {
+    pub auth: Authorization,
+    pub asset: [u8; 20],
+}
+
+/// Proves the balance is at least `threshold` without moving any of it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Attest {
+    pub balance: u64,
+    pub auth: Authorization,
+    pub asset: [u8; 20],
+    pub threshold: u64,
+    /// Picked by the counterparty, binding the proof to their request.
+    pub nonce: [u8; 32],
+}
+
+/// Rolls the pending transfers of a shard over. It needs no key, so anyone can prove it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Rollover {
+    pub shard: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Action {
     Deposit(Deposit),
     Send(Send),
+    SendMany(SendMany),
     Withdraw(Withdraw),
     Rotate(Rotate),
-}
\ No newline at end of file
+    Close(Close),
+    Attest(Attest),
+    Rollover(Rollover),
+}
+
+/// The action a set of public values was produced by, without its private inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    Deposit,
+    Send,
+    SendMany,
+    Withdraw,
+    Rotate,
+    Close,
+    Attest,
+    Rollover,
+}
+
+impl Action {
+    pub fn kind(&self) -> ActionKind {
+        match self {
+            Action::Deposit(_) => ActionKind::Deposit,
+            Action::Send(_) => ActionKind::Send,
+            Action::SendMany(_) => ActionKind::SendMany,
+            Action::Withdraw(_) => ActionKind::Withdraw,
+            Action::Rotate(_) => ActionKind::Rotate,
+            Action::Close(_) => ActionKind::Close,
+            Action::Attest(_) => ActionKind::Attest,
+            Action::Rollover(_) => ActionKind::Rollover,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rekey_waits_for_the_pending_transfers_to_be_rolled_over() {
+        let mut pp = PublicParams::setup(4);
+        let el_gamal = ElGamal::new(pp.g);
+        let kzg = KZG::new(pp.g1_lagrange_basis.clone());
+        let mut phi = vec![kzg.commit(vec![Scalar::zero(); pp.degree]).unwrap()];
+        let (sk_a, sk_b, sk_c) = (view_key([1, 2, 3, 4]), view_key([5, 6, 7, 8]), view_key([9, 10, 11, 12]));
+        let (pk_a, pk_b, pk_c) = (el_gamal.from_skey(sk_a), el_gamal.from_skey(sk_b), el_gamal.from_skey(sk_c));
+        phi = deposit(&mut pp, pk_a, NATIVE_ASSET, [0x11, 0, 0, 0], 100, &phi).unwrap();
+        phi = deposit(&mut pp, pk_b, NATIVE_ASSET, [0x22, 0, 0, 0], 200, &phi).unwrap();
+        phi = send(&mut pp, sk_b, pk_a, NATIVE_ASSET, 200, 30, 0, [0x33, 0, 0, 0], [0x44, 0, 0, 0], &phi).unwrap();
+
+        let rejected = rekey(&mut pp.clone(), sk_a, NATIVE_ASSET, 100, pk_c, [0x55, 0, 0, 0], &phi);
+        assert_eq!(rejected, Err("Roll over the pending transfers first".to_string()));
+
+        phi = rollover(&mut pp, 0, &phi).unwrap();
+        let (next_phi, idx) = rekey(&mut pp, sk_a, NATIVE_ASSET, 130, pk_c, [0x55, 0, 0, 0], &phi).unwrap();
+        phi = next_phi;
+        phi = send(&mut pp, sk_b, pk_c, NATIVE_ASSET, 170, 20, 0, [0x66, 0, 0, 0], [0x77, 0, 0, 0], &phi).unwrap();
+        phi = rollover(&mut pp, 0, &phi).unwrap();
+        assert_eq!(el_gamal.decrypt(sk_c, pp.t[idx], pp.v[idx], 150), Ok(150));
+        assert_eq!(kzg.commit(pp.v[..pp.degree].to_vec()).unwrap(), phi[0]);
+    }
+}
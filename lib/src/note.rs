@@ -0,0 +1,219 @@
+//! Encrypted transfer notes. A send only multiplies `g^amount` into the receiver's pending
+//! transfers, so the program also encrypts the amount, with an optional memo, to the receiver's
+//! public key and commits the result. The receiver scans the committed notes and decrypts the ones addressed to
+//! them to keep their balance witness up to date.
+//!
+//! The scheme is hashed ElGamal in the same group as the balances: the sender picks `k`, publishes
+//! `g^k`, and both sides derive an encryption key and a MAC key from the shared `pk^k = (g^k)^sk`
+//! with SHA-256. The plaintext, the amount as u64 little-endian followed by the memo, is XORed with
+//! a SHA-256 counter-mode keystream and authenticated with a SHA-256 tag over the ephemeral key and
+//! the ciphertext.
+//!
+//! When the ledger has an auditor, the program also escrows the amount alone to the auditor's key,
+//! reusing the note's `k`, so the auditor can read every transfer amount without a spending key.
+
+use crate::public_values::serialize_scalar;
+use crate::{DecodedPublicValues, ElGamal};
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use sp1_bls12_381::Scalar;
+
+/// The longest memo a note can carry.
+pub const MAX_MEMO_LEN: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Note {
+    /// The sender's ephemeral key `g^k`.
+    #[serde(serialize_with = "serialize_scalar")]
+    pub ephemeral: Scalar,
+    #[serde(serialize_with = "serialize_bytes")]
+    pub ciphertext: Vec<u8>,
+    #[serde(serialize_with = "serialize_bytes")]
+    pub tag: [u8; 32],
+}
+
+fn serialize_bytes<S: Serializer, B: AsRef<[u8]>>(bytes: &B, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+}
+
+fn derive_keys(shared: &Scalar) -> ([u8; 32], [u8; 32]) {
+    let enc_key = Sha256::new().chain_update(b"zk-state-machine note enc").chain_update(shared.to_bytes()).finalize();
+    let mac_key = Sha256::new().chain_update(b"zk-state-machine note mac").chain_update(shared.to_bytes()).finalize();
+    (enc_key.into(), mac_key.into())
+}
+
+fn apply_keystream(enc_key: &[u8; 32], data: &mut [u8]) {
+    for (counter, chunk) in data.chunks_mut(32).enumerate() {
+        let block = Sha256::new().chain_update(enc_key).chain_update((counter as u64).to_le_bytes()).finalize();
+        for (byte, key) in chunk.iter_mut().zip(block.iter()) {
+            *byte ^= key;
+        }
+    }
+}
+
+fn compute_tag(mac_key: &[u8; 32], ephemeral: &Scalar, ciphertext: &[u8]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(mac_key)
+        .chain_update(ephemeral.to_bytes())
+        .chain_update(ciphertext)
+        .finalize()
+        .into()
+}
+
+impl Note {
+    /// Encrypts `amount` and `memo` to `pk` with the ephemeral exponent `k`, which must be fresh for
+    /// every note.
+    pub fn encrypt(g: Scalar, pk: Scalar, amount: u64, memo: &[u8], k: [u64; 4]) -> Result<Note, String> {
+        if memo.len() > MAX_MEMO_LEN {
+            return Err(format!("Memo is longer than {} bytes", MAX_MEMO_LEN));
+        }
+        let ephemeral = ElGamal::new(g).from_skey(k);
+        let (enc_key, mac_key) = derive_keys(&pk.pow(&k));
+        let mut ciphertext = amount.to_le_bytes().to_vec();
+        ciphertext.extend_from_slice(memo);
+        apply_keystream(&enc_key, &mut ciphertext);
+        let tag = compute_tag(&mac_key, &ephemeral, &ciphertext);
+        Ok(Note { ephemeral, ciphertext, tag })
+    }
+
+    /// Decrypts the note with the receiver's secret key, returning the amount and memo. Fails if the
+    /// note was not addressed to `sk` or has been tampered with.
+    pub fn decrypt(&self, sk: [u64; 4]) -> Result<(u64, Vec<u8>), String> {
+        let (enc_key, mac_key) = derive_keys(&self.ephemeral.pow(&sk));
+        if compute_tag(&mac_key, &self.ephemeral, &self.ciphertext) != self.tag {
+            return Err("Note authentication failed".to_string());
+        }
+        if self.ciphertext.len() < 8 {
+            return Err("Note is too short".to_string());
+        }
+        let mut plaintext = self.ciphertext.clone();
+        apply_keystream(&enc_key, &mut plaintext);
+        let memo = plaintext.split_off(8);
+        Ok((u64::from_le_bytes(plaintext.try_into().unwrap()), memo))
+    }
+}
+
+/// A note addressed to the scanning key, decrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedNote {
+    pub sender: Scalar,
+    /// The token the amount is in, `NATIVE_ASSET` for ETH.
+    pub asset: [u8; 20],
+    pub amount: u64,
+    pub memo: Vec<u8>,
+}
+
+/// Picks the sends to `sk` out of `values` and decrypts their notes, in order. Notes that fail to
+/// decrypt are skipped, since the program only ever commits well-formed ones.
+pub fn scan_notes<'a>(
+    g: Scalar,
+    sk: [u64; 4],
+    values: impl IntoIterator<Item = &'a DecodedPublicValues>,
+) -> Vec<ReceivedNote> {
+    let pkey = ElGamal::new(g).from_skey(sk);
+    values
+        .into_iter()
+        .flat_map(|values| match values {
+            DecodedPublicValues::Send(send) if send.pkey_receiver == pkey => vec![(send.pkey_sender, send.asset, &send.note)],
+            DecodedPublicValues::SendMany(send) => send
+                .receivers
+                .iter()
+                .filter(|receiver| receiver.pkey == pkey)
+                .map(|receiver| (send.pkey_sender, send.asset, &receiver.note))
+                .collect(),
+            _ => vec![],
+        })
+        .filter_map(|(sender, asset, note)| {
+            let (amount, memo) = note.decrypt(sk).ok()?;
+            Some(ReceivedNote { sender, asset, amount, memo })
+        })
+        .collect()
+}
+
+/// A transfer amount escrowed to the auditor, decrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditedTransfer {
+    pub sender: Scalar,
+    pub receiver: Scalar,
+    pub asset: [u8; 20],
+    pub amount: u64,
+}
+
+/// Decrypts the amount of every transfer in `values` with the auditor's secret key, in order.
+/// Unlike a receiver's scan, every transfer must decrypt, since the contract only accepts ones
+/// escrowed to its auditor.
+pub fn audit_transfers<'a>(
+    sk: [u64; 4],
+    values: impl IntoIterator<Item = &'a DecodedPublicValues>,
+) -> Result<Vec<AuditedTransfer>, String> {
+    values
+        .into_iter()
+        .flat_map(|values| match values {
+            DecodedPublicValues::Send(send) => vec![(send.pkey_sender, send.pkey_receiver, send.asset, &send.escrow)],
+            DecodedPublicValues::SendMany(send) => send
+                .receivers
+                .iter()
+                .map(|receiver| (send.pkey_sender, receiver.pkey, send.asset, &receiver.escrow))
+                .collect(),
+            _ => vec![],
+        })
+        .map(|(sender, receiver, asset, escrow)| {
+            let escrow = escrow.as_ref().ok_or("Transfer has no auditor".to_string())?;
+            let (amount, _) = escrow.decrypt(sk).map_err(|_| "Transfer is not escrowed to this auditor".to_string())?;
+            Ok(AuditedTransfer { sender, receiver, asset, amount })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::view_key;
+
+    const G: u64 = 2;
+
+    fn encrypted(memo: &[u8]) -> (Note, [u64; 4]) {
+        let sk = view_key([1, 2, 3, 4]);
+        let pk = ElGamal::new(Scalar::from(G)).from_skey(sk);
+        (Note::encrypt(Scalar::from(G), pk, 1234, memo, [0x99, 0, 0, 0]).unwrap(), sk)
+    }
+
+    #[test]
+    fn a_note_decrypts_to_its_amount_and_memo() {
+        let (note, sk) = encrypted(b"invoice 42");
+        assert_eq!(note.ciphertext.len(), 8 + b"invoice 42".len());
+        assert_eq!(note.decrypt(sk), Ok((1234, b"invoice 42".to_vec())));
+        let (note, sk) = encrypted(&[0xab; MAX_MEMO_LEN]);
+        assert_eq!(note.decrypt(sk), Ok((1234, vec![0xab; MAX_MEMO_LEN])));
+    }
+
+    #[test]
+    fn a_note_does_not_decrypt_under_another_key() {
+        let (note, _) = encrypted(b"");
+        assert_eq!(note.decrypt(view_key([5, 6, 7, 8])), Err("Note authentication failed".to_string()));
+    }
+
+    #[test]
+    fn a_tampered_note_is_rejected() {
+        let (original, sk) = encrypted(b"invoice 42");
+        let mut note = original.clone();
+        note.ciphertext[0] ^= 1;
+        assert_eq!(note.decrypt(sk), Err("Note authentication failed".to_string()));
+        let mut note = original.clone();
+        note.ciphertext.pop();
+        assert_eq!(note.decrypt(sk), Err("Note authentication failed".to_string()));
+        let mut note = original.clone();
+        note.tag[31] ^= 1;
+        assert_eq!(note.decrypt(sk), Err("Note authentication failed".to_string()));
+        let mut note = original;
+        note.ephemeral += Scalar::one();
+        assert_eq!(note.decrypt(sk), Err("Note authentication failed".to_string()));
+    }
+
+    #[test]
+    fn a_memo_longer_than_the_maximum_is_rejected() {
+        let pk = ElGamal::new(Scalar::from(G)).from_skey(view_key([1, 2, 3, 4]));
+        let rejected = Note::encrypt(Scalar::from(G), pk, 1, &[0; MAX_MEMO_LEN + 1], [0x99, 0, 0, 0]);
+        assert_eq!(rejected, Err(format!("Memo is longer than {} bytes", MAX_MEMO_LEN)));
+    }
+}
@@ -0,0 +1,983 @@
+//! Typed decoding of the public values committed by the state machine program.
+//!
+//! The program commits phis, and the deltas it adds to them, as G1 points in the encoding of the
+//! EIP-2537 precompiles the contract adds them with, and keys as little-endian scalars. Decoding
+//! turns them back into curve points and field elements, rejecting anything that the program
+//! could not have produced. Phis outside the public values, as in indexer snapshots, are kept
+//! compressed.
+
+use crate::{
+    ActionKind, Note, PendingWrite, PublicValuesAttest, PublicValuesClose, PublicValuesDeposit, PublicValuesRollover, PublicValuesRotate,
+    PublicValuesSend, PublicValuesSendMany, PublicValuesWithdraw, ShardDelta, SlotWrite,
+};
+use alloy_sol_types::SolType;
+use serde::{Serialize, Serializer};
+use sp1_bls12_381::{G1Affine, Scalar};
+use std::fmt;
+
+/// Decodes a compressed phi, checking that it is on the curve and in the prime-order subgroup.
+pub fn decode_phi(bytes: &[u8]) -> Result<G1Affine, String> {
+    let bytes: [u8; 48] = bytes
+        .try_into()
+        .map_err(|_| format!("Invalid phi length: expected 48 bytes, got {}", bytes.len()))?;
+    let point: G1Affine = Option::from(G1Affine::from_compressed_unchecked(&bytes))
+        .ok_or("Invalid phi encoding".to_string())?;
+    if !bool::from(point.is_on_curve()) {
+        return Err("phi is not on the curve".to_string());
+    }
+    if !bool::from(point.is_torsion_free()) {
+        return Err("phi is not in the prime-order subgroup".to_string());
+    }
+    Ok(point)
+}
+
+/// Encodes a G1 point for the EIP-2537 precompiles: both coordinates big-endian and left-padded
+/// to 64 bytes, and the identity as all zeros.
+pub fn g1_to_evm(point: &G1Affine) -> Vec<u8> {
+    let mut out = vec![0u8; 128];
+    if bool::from(point.is_identity()) {
+        return out;
+    }
+    let bytes = point.to_uncompressed();
+    out[16..64].copy_from_slice(&bytes[..48]);
+    out[80..].copy_from_slice(&bytes[48..]);
+    out
+}
+
+/// Decodes a G1 point in the encoding of the EIP-2537 precompiles, checking that it is on the
+/// curve and in the prime-order subgroup.
+pub fn decode_evm_g1(bytes: &[u8]) -> Result<G1Affine, String> {
+    if bytes.len() != 128 {
+        return Err(format!("Invalid G1 point length: expected 128 bytes, got {}", bytes.len()));
+    }
+    if bytes.iter().all(|b| *b == 0) {
+        return Ok(G1Affine::identity());
+    }
+    if bytes[..16].iter().chain(bytes[64..80].iter()).any(|b| *b != 0) {
+        return Err("G1 point coordinates are not padded".to_string());
+    }
+    let mut uncompressed = [0u8; 96];
+    uncompressed[..48].copy_from_slice(&bytes[16..64]);
+    uncompressed[48..].copy_from_slice(&bytes[80..]);
+    let point: G1Affine = Option::from(G1Affine::from_uncompressed_unchecked(&uncompressed))
+        .ok_or("Invalid G1 point encoding".to_string())?;
+    if !bool::from(point.is_on_curve()) {
+        return Err("G1 point is not on the curve".to_string());
+    }
+    if !bool::from(point.is_torsion_free()) {
+        return Err("G1 point is not in the prime-order subgroup".to_string());
+    }
+    Ok(point)
+}
+
+/// Decodes a canonical little-endian scalar.
+pub fn decode_scalar(bytes: &[u8]) -> Result<Scalar, String> {
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| format!("Invalid scalar length: expected 32 bytes, got {}", bytes.len()))?;
+    Option::from(Scalar::from_bytes(&bytes)).ok_or("Scalar is not canonical".to_string())
+}
+
+pub fn g1_to_hex(point: &G1Affine) -> String {
+    format!("0x{}", hex::encode(point.to_compressed()))
+}
+
+pub fn scalar_to_hex(scalar: &Scalar) -> String {
+    format!("0x{}", hex::encode(scalar.to_bytes()))
+}
+
+fn decode_amount(amount: alloy_sol_types::private::U256) -> Result<u64, String> {
+    amount.try_into().map_err(|_| format!("Amount {} does not fit in u64", amount))
+}
+
+/// Decodes what an action moved the total supply by, which must be `expected`: what the contract
+/// pulled in, less what it paid out.
+fn decode_supply_delta(delta: alloy_sol_types::private::I256, expected: i128) -> Result<i128, String> {
+    let delta = i128::try_from(delta).map_err(|_| format!("Supply delta {} does not fit in i128", delta))?;
+    if delta != expected {
+        return Err(format!("Supply delta {} does not match the {} pulled in and paid out", delta, expected));
+    }
+    Ok(delta)
+}
+
+/// Decodes a relayer fee, which must not be paid to the zero address.
+fn decode_fee(fee: alloy_sol_types::private::U256, relayer: [u8; 20]) -> Result<u64, String> {
+    let fee = decode_amount(fee)?;
+    if fee > 0 && relayer == [0u8; 20] {
+        return Err("A fee is paid to the zero address".to_string());
+    }
+    Ok(fee)
+}
+
+/// Decodes an auditor public key, zero meaning the ledger has no auditor.
+fn decode_auditor(bytes: &[u8]) -> Result<Option<Scalar>, String> {
+    let auditor = decode_scalar(bytes)?;
+    Ok(if auditor == Scalar::zero() { None } else { Some(auditor) })
+}
+
+/// Decodes the copy of a transfer amount encrypted to the auditor, which shares the ephemeral key
+/// of the receiver's note.
+fn decode_escrow(auditor: Option<Scalar>, ephemeral: Scalar, ciphertext: &[u8], tag: [u8; 32]) -> Result<Option<Note>, String> {
+    match auditor {
+        Some(_) => Ok(Some(Note { ephemeral, ciphertext: ciphertext.to_vec(), tag })),
+        None if ciphertext.is_empty() && tag == [0u8; 32] => Ok(None),
+        None => Err("A transfer without an auditor carries an escrowed amount".to_string()),
+    }
+}
+
+fn serialize_g1<S: Serializer>(point: &G1Affine, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&g1_to_hex(point))
+}
+
+pub(crate) fn serialize_scalar<S: Serializer>(scalar: &Scalar, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&scalar_to_hex(scalar))
+}
+
+fn serialize_optional_scalar<S: Serializer>(scalar: &Option<Scalar>, serializer: S) -> Result<S::Ok, S::Error> {
+    match scalar {
+        Some(scalar) => serializer.serialize_str(&scalar_to_hex(scalar)),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn serialize_hash<S: Serializer>(hash: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("0x{}", hex::encode(hash)))
+}
+
+fn serialize_address<S: Serializer>(address: &[u8; 20], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("0x{}", hex::encode(address)))
+}
+
+/// A slot an action wrote, with the ciphertext it held before and after.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedSlotWrite {
+    pub shard: usize,
+    pub index: usize,
+    #[serde(serialize_with = "serialize_scalar")]
+    pub old_t: Scalar,
+    #[serde(serialize_with = "serialize_scalar")]
+    pub old_v: Scalar,
+    #[serde(serialize_with = "serialize_scalar")]
+    pub new_t: Scalar,
+    #[serde(serialize_with = "serialize_scalar")]
+    pub new_v: Scalar,
+    #[serde(serialize_with = "serialize_hash")]
+    pub old_account: [u8; 32],
+    #[serde(serialize_with = "serialize_hash")]
+    pub new_account: [u8; 32],
+}
+
+/// The pending transfers of a slot an action wrote, before and after.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedPendingWrite {
+    pub shard: usize,
+    pub index: usize,
+    #[serde(serialize_with = "serialize_hash")]
+    pub account: [u8; 32],
+    #[serde(serialize_with = "serialize_scalar")]
+    pub old_t: Scalar,
+    #[serde(serialize_with = "serialize_scalar")]
+    pub old_v: Scalar,
+    #[serde(serialize_with = "serialize_scalar")]
+    pub new_t: Scalar,
+    #[serde(serialize_with = "serialize_scalar")]
+    pub new_v: Scalar,
+}
+
+/// What an action adds to the phi of a shard.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedShardDelta {
+    pub shard: usize,
+    #[serde(serialize_with = "serialize_g1")]
+    pub delta: G1Affine,
+}
+
+/// Everything an action writes. Each slot is listed at most once in `slots` and in `pending`, and
+/// `shards` lists every shard of `slots`, once. Pending transfers are not committed by any phi.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedWrites {
+    pub slots: Vec<DecodedSlotWrite>,
+    pub pending: Vec<DecodedPendingWrite>,
+    pub shards: Vec<DecodedShardDelta>,
+}
+
+impl DecodedWrites {
+    fn decode(slots: &[SlotWrite], pending: &[PendingWrite], shards: &[ShardDelta]) -> Result<DecodedWrites, String> {
+        let mut decoded = DecodedWrites { slots: Vec::new(), pending: Vec::new(), shards: Vec::new() };
+        for write in slots.iter() {
+            let (shard, index) = (write.shard as usize, write.index as usize);
+            if decoded.slots.iter().any(|w| (w.shard, w.index) == (shard, index)) {
+                return Err(format!("Slot {} of shard {} is written more than once", index, shard));
+            }
+            decoded.slots.push(DecodedSlotWrite {
+                shard,
+                index,
+                old_t: decode_scalar(write.old_t.as_slice())?,
+                old_v: decode_scalar(write.old_v.as_slice())?,
+                new_t: decode_scalar(write.new_t.as_slice())?,
+                new_v: decode_scalar(write.new_v.as_slice())?,
+                old_account: write.old_account.0,
+                new_account: write.new_account.0,
+            });
+        }
+        for write in pending.iter() {
+            let (shard, index) = (write.shard as usize, write.index as usize);
+            if decoded.pending.iter().any(|w| (w.shard, w.index) == (shard, index)) {
+                return Err(format!("Pending transfers of slot {} of shard {} are written more than once", index, shard));
+            }
+            if write.account.0 == [0u8; 32] {
+                return Err(format!("Pending transfers of slot {} of shard {} are written without an account", index, shard));
+            }
+            decoded.pending.push(DecodedPendingWrite {
+                shard,
+                index,
+                account: write.account.0,
+                old_t: decode_scalar(write.old_t.as_slice())?,
+                old_v: decode_scalar(write.old_v.as_slice())?,
+                new_t: decode_scalar(write.new_t.as_slice())?,
+                new_v: decode_scalar(write.new_v.as_slice())?,
+            });
+        }
+        for delta in shards.iter() {
+            let shard = delta.shard as usize;
+            if decoded.shards.iter().any(|d| d.shard == shard) {
+                return Err(format!("Shard {} is listed more than once", shard));
+            }
+            decoded.shards.push(DecodedShardDelta {
+                shard,
+                delta: decode_evm_g1(&delta.delta)?,
+            });
+        }
+        for write in decoded.slots.iter() {
+            if !decoded.shards.iter().any(|d| d.shard == write.shard) {
+                return Err(format!("Shard {} is written without a delta", write.shard));
+            }
+        }
+        if decoded.shards.iter().any(|d| !decoded.slots.iter().any(|w| w.shard == d.shard)) {
+            return Err("A delta is listed for a shard no slot is written in".to_string());
+        }
+        Ok(decoded)
+    }
+
+    /// Checks how many slots and pending transfers an action writes.
+    fn expect(&self, slots: usize, pending: usize) -> Result<(), String> {
+        if self.slots.len() != slots || self.pending.len() != pending {
+            return Err(format!(
+                "Expected {} slot and {} pending writes, got {} and {}",
+                slots,
+                pending,
+                self.slots.len(),
+                self.pending.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Adds the deltas to `phi`, one per shard, starting any shard past the end from the identity.
+    pub fn apply(&self, phi: &[G1Affine]) -> Vec<G1Affine> {
+        let mut next_phi = phi.to_vec();
+        for d in self.shards.iter() {
+            if d.shard >= next_phi.len() {
+                next_phi.resize(d.shard + 1, G1Affine::identity());
+            }
+            next_phi[d.shard] = next_phi[d.shard].add_affine(&d.delta);
+        }
+        next_phi
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedDeposit {
+    pub writes: DecodedWrites,
+    #[serde(serialize_with = "serialize_address")]
+    pub asset: [u8; 20],
+    pub amount: u64,
+    /// What the deposit added to the total supply, the amount.
+    pub supply_delta: i128,
+    #[serde(serialize_with = "serialize_scalar")]
+    pub pkey: Scalar,
+    /// Whether the deposit topped up an existing account instead of opening one.
+    pub top_up: bool,
+    /// Where the account is.
+    pub shard: usize,
+    pub index: usize,
+    #[serde(serialize_with = "serialize_hash")]
+    pub old_free_list_hash: [u8; 32],
+    #[serde(serialize_with = "serialize_hash")]
+    pub new_free_list_hash: [u8; 32],
+}
+
+impl DecodedDeposit {
+    pub fn decode(bytes: &[u8]) -> Result<DecodedDeposit, String> {
+        let decoded = PublicValuesDeposit::abi_decode(bytes, true).map_err(|e| e.to_string())?;
+        let writes = DecodedWrites::decode(&decoded.slots, &decoded.pending, &decoded.shards)?;
+        let (shard, index) = (decoded.shard as usize, decoded.index as usize);
+        if decoded.top_up {
+            writes.expect(0, 1).map_err(|e| format!("A top-up must only write pending transfers: {}", e))?;
+            if (writes.pending[0].shard, writes.pending[0].index) != (shard, index) {
+                return Err("A top-up writes the pending transfers of another account".to_string());
+            }
+        } else {
+            writes.expect(1, 0).map_err(|e| format!("A deposit opening an account must only write its slot: {}", e))?;
+            if (writes.slots[0].shard, writes.slots[0].index) != (shard, index) {
+                return Err("A deposit writes the slot of another account".to_string());
+            }
+        }
+        let amount = decode_amount(decoded.amount)?;
+        Ok(DecodedDeposit {
+            writes,
+            asset: decoded.asset.into_array(),
+            amount,
+            supply_delta: decode_supply_delta(decoded.supply_delta, amount as i128)?,
+            pkey: decode_scalar(decoded.pkey.as_slice())?,
+            top_up: decoded.top_up,
+            shard,
+            index,
+            old_free_list_hash: decoded.old_free_list_hash.0,
+            new_free_list_hash: decoded.new_free_list_hash.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedSend {
+    /// The sender's slot and the receiver's pending transfers.
+    pub writes: DecodedWrites,
+    #[serde(serialize_with = "serialize_address")]
+    pub asset: [u8; 20],
+    #[serde(serialize_with = "serialize_scalar")]
+    pub pkey_sender: Scalar,
+    #[serde(serialize_with = "serialize_scalar")]
+    pub pkey_receiver: Scalar,
+    /// Debited from the sender on top of the amount and paid to `relayer`.
+    pub fee: u64,
+    /// What the send took off the total supply, the fee.
+    pub supply_delta: i128,
+    #[serde(serialize_with = "serialize_address")]
+    pub relayer: [u8; 20],
+    pub note: Note,
+    /// The ledger's auditor, if it has one.
+    #[serde(serialize_with = "serialize_optional_scalar")]
+    pub auditor: Option<Scalar>,
+    /// The amount encrypted to the auditor.
+    pub escrow: Option<Note>,
+}
+
+impl DecodedSend {
+    pub fn decode(bytes: &[u8]) -> Result<DecodedSend, String> {
+        let decoded = PublicValuesSend::abi_decode(bytes, true).map_err(|e| e.to_string())?;
+        let writes = DecodedWrites::decode(&decoded.slots, &decoded.pending, &decoded.shards)?;
+        writes.expect(1, 1)?;
+        let fee = decode_fee(decoded.fee, decoded.relayer.into_array())?;
+        let note = Note {
+            ephemeral: decode_scalar(decoded.note_ephemeral.as_slice())?,
+            ciphertext: decoded.note_ciphertext.to_vec(),
+            tag: decoded.note_tag.0,
+        };
+        let auditor = decode_auditor(decoded.auditor.as_slice())?;
+        let escrow = decode_escrow(auditor, note.ephemeral, &decoded.auditor_ciphertext, decoded.auditor_tag.0)?;
+        Ok(DecodedSend {
+            writes,
+            asset: decoded.asset.into_array(),
+            pkey_sender: decode_scalar(decoded.pkey_sender.as_slice())?,
+            pkey_receiver: decode_scalar(decoded.pkey_receiver.as_slice())?,
+            fee,
+            supply_delta: decode_supply_delta(decoded.supply_delta, -(fee as i128))?,
+            relayer: decoded.relayer.into_array(),
+            note,
+            auditor,
+            escrow,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedReceiver {
+    #[serde(serialize_with = "serialize_scalar")]
+    pub pkey: Scalar,
+    pub note: Note,
+    /// The amount encrypted to the auditor.
+    pub escrow: Option<Note>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedSendMany {
+    /// The sender's slot and the pending transfers of every receiver.
+    pub writes: DecodedWrites,
+    #[serde(serialize_with = "serialize_address")]
+    pub asset: [u8; 20],
+    #[serde(serialize_with = "serialize_scalar")]
+    pub pkey_sender: Scalar,
+    #[serde(serialize_with = "serialize_optional_scalar")]
+    pub auditor: Option<Scalar>,
+    pub receivers: Vec<DecodedReceiver>,
+}
+
+impl DecodedSendMany {
+    pub fn decode(bytes: &[u8]) -> Result<DecodedSendMany, String> {
+        let decoded = PublicValuesSendMany::abi_decode(bytes, true).map_err(|e| e.to_string())?;
+        if decoded.receivers.is_empty() {
+            return Err("A send to many has no receivers".to_string());
+        }
+        let writes = DecodedWrites::decode(&decoded.slots, &decoded.pending, &decoded.shards)?;
+        writes.expect(1, decoded.receivers.len())?;
+        let auditor = decode_auditor(decoded.auditor.as_slice())?;
+        let receivers = decoded
+            .receivers
+            .iter()
+            .map(|receiver| {
+                let note = Note {
+                    ephemeral: decode_scalar(receiver.note_ephemeral.as_slice())?,
+                    ciphertext: receiver.note_ciphertext.to_vec(),
+                    tag: receiver.note_tag.0,
+                };
+                let escrow = decode_escrow(auditor, note.ephemeral, &receiver.auditor_ciphertext, receiver.auditor_tag.0)?;
+                Ok(DecodedReceiver {
+                    pkey: decode_scalar(receiver.pkey.as_slice())?,
+                    note,
+                    escrow,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(DecodedSendMany {
+            writes,
+            asset: decoded.asset.into_array(),
+            pkey_sender: decode_scalar(decoded.pkey_sender.as_slice())?,
+            auditor,
+            receivers,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedWithdraw {
+    pub writes: DecodedWrites,
+    #[serde(serialize_with = "serialize_address")]
+    pub asset: [u8; 20],
+    pub amount: u64,
+    #[serde(serialize_with = "serialize_address")]
+    pub recipient: [u8; 20],
+    #[serde(serialize_with = "serialize_scalar")]
+    pub pkey: Scalar,
+    /// Debited on top of the amount and paid to `relayer`.
+    pub fee: u64,
+    /// What the withdrawal took off the total supply, the amount and the fee.
+    pub supply_delta: i128,
+    #[serde(serialize_with = "serialize_address")]
+    pub relayer: [u8; 20],
+}
+
+impl DecodedWithdraw {
+    pub fn decode(bytes: &[u8]) -> Result<DecodedWithdraw, String> {
+        let decoded = PublicValuesWithdraw::abi_decode(bytes, true).map_err(|e| e.to_string())?;
+        let writes = DecodedWrites::decode(&decoded.slots, &decoded.pending, &decoded.shards)?;
+        writes.expect(1, 0)?;
+        let (amount, fee) = (decode_amount(decoded.amount)?, decode_fee(decoded.fee, decoded.relayer.into_array())?);
+        Ok(DecodedWithdraw {
+            writes,
+            asset: decoded.asset.into_array(),
+            amount,
+            recipient: decoded.recipient.into_array(),
+            pkey: decode_scalar(decoded.pkey.as_slice())?,
+            fee,
+            supply_delta: decode_supply_delta(decoded.supply_delta, -(amount as i128 + fee as i128))?,
+            relayer: decoded.relayer.into_array(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedRotate {
+    pub writes: DecodedWrites,
+    #[serde(serialize_with = "serialize_address")]
+    pub asset: [u8; 20],
+    #[serde(serialize_with = "serialize_scalar")]
+    pub pkey: Scalar,
+    /// The account's key after the rotation, `pkey` unless it was moved to a new key pair.
+    #[serde(serialize_with = "serialize_scalar")]
+    pub new_pkey: Scalar,
+}
+
+impl DecodedRotate {
+    pub fn decode(bytes: &[u8]) -> Result<DecodedRotate, String> {
+        let decoded = PublicValuesRotate::abi_decode(bytes, true).map_err(|e| e.to_string())?;
+        let writes = DecodedWrites::decode(&decoded.slots, &decoded.pending, &decoded.shards)?;
+        let (pkey, new_pkey) = (decode_scalar(decoded.pkey.as_slice())?, decode_scalar(decoded.new_pkey.as_slice())?);
+        // A rekey also writes the pending transfers, which must be empty
+        writes.expect(1, if new_pkey != pkey { 1 } else { 0 })?;
+        Ok(DecodedRotate {
+            writes,
+            asset: decoded.asset.into_array(),
+            pkey,
+            new_pkey,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedClose {
+    /// The account's slot, cleared, and its pending transfers, read as zero.
+    pub writes: DecodedWrites,
+    #[serde(serialize_with = "serialize_address")]
+    pub asset: [u8; 20],
+    #[serde(serialize_with = "serialize_scalar")]
+    pub pkey: Scalar,
+    /// The slot freed by the close.
+    pub shard: usize,
+    pub index: usize,
+    #[serde(serialize_with = "serialize_hash")]
+    pub old_free_list_hash: [u8; 32],
+    #[serde(serialize_with = "serialize_hash")]
+    pub new_free_list_hash: [u8; 32],
+}
+
+impl DecodedClose {
+    pub fn decode(bytes: &[u8]) -> Result<DecodedClose, String> {
+        let decoded = PublicValuesClose::abi_decode(bytes, true).map_err(|e| e.to_string())?;
+        let writes = DecodedWrites::decode(&decoded.slots, &decoded.pending, &decoded.shards)?;
+        writes.expect(1, 1)?;
+        let (shard, index) = (decoded.shard as usize, decoded.index as usize);
+        let (slot, pending) = (&writes.slots[0], &writes.pending[0]);
+        if (slot.shard, slot.index) != (shard, index) || (pending.shard, pending.index) != (shard, index) {
+            return Err("A close writes the slot of another account".to_string());
+        }
+        if slot.new_t != Scalar::zero() || slot.new_v != Scalar::zero() {
+            return Err("A close must clear the slot".to_string());
+        }
+        let zero = |s: &Scalar| *s == Scalar::zero();
+        if ![pending.old_t, pending.old_v, pending.new_t, pending.new_v].iter().all(zero) {
+            return Err("A close must leave no pending transfers".to_string());
+        }
+        Ok(DecodedClose {
+            writes,
+            asset: decoded.asset.into_array(),
+            pkey: decode_scalar(decoded.pkey.as_slice())?,
+            shard,
+            index,
+            old_free_list_hash: decoded.old_free_list_hash.0,
+            new_free_list_hash: decoded.new_free_list_hash.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedAttest {
+    /// The phi of the shard the balance was read against, unchanged by the attestation.
+    #[serde(serialize_with = "serialize_g1")]
+    pub phi: G1Affine,
+    /// Where the account is, its registry key and the `t` of its ciphertext, which the phi does not
+    /// commit.
+    pub shard: usize,
+    pub index: usize,
+    #[serde(serialize_with = "serialize_hash")]
+    pub account: [u8; 32],
+    #[serde(serialize_with = "serialize_scalar")]
+    pub t: Scalar,
+    #[serde(serialize_with = "serialize_address")]
+    pub asset: [u8; 20],
+    pub supply: u64,
+    #[serde(serialize_with = "serialize_scalar")]
+    pub pkey: Scalar,
+    /// The balance is at least this much.
+    pub threshold: u64,
+    #[serde(serialize_with = "serialize_hash")]
+    pub nonce: [u8; 32],
+}
+
+impl DecodedAttest {
+    pub fn decode(bytes: &[u8]) -> Result<DecodedAttest, String> {
+        let decoded = PublicValuesAttest::abi_decode(bytes, true).map_err(|e| e.to_string())?;
+        Ok(DecodedAttest {
+            phi: decode_evm_g1(&decoded.phi)?,
+            shard: decoded.shard as usize,
+            index: decoded.index as usize,
+            account: decoded.account.0,
+            t: decode_scalar(decoded.t.as_slice())?,
+            asset: decoded.asset.into_array(),
+            supply: decode_amount(decoded.supply)?,
+            pkey: decode_scalar(decoded.pkey.as_slice())?,
+            threshold: decode_amount(decoded.threshold)?,
+            nonce: decoded.nonce.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedRollover {
+    /// Every slot of the shard with pending transfers, and those transfers, cleared.
+    pub writes: DecodedWrites,
+    pub shard: usize,
+}
+
+impl DecodedRollover {
+    pub fn decode(bytes: &[u8]) -> Result<DecodedRollover, String> {
+        let decoded = PublicValuesRollover::abi_decode(bytes, true).map_err(|e| e.to_string())?;
+        let writes = DecodedWrites::decode(&decoded.slots, &decoded.pending, &decoded.shards)?;
+        let shard = decoded.shard as usize;
+        if writes.pending.is_empty() {
+            return Err(format!("Nothing is pending in shard {}", shard));
+        }
+        if writes.pending.iter().any(|w| w.shard != shard) {
+            return Err(format!("A rollover writes outside shard {}", shard));
+        }
+        if writes.pending.iter().any(|w| w.new_t != Scalar::zero() || w.new_v != Scalar::zero()) {
+            return Err("A rollover must clear the pending transfers".to_string());
+        }
+        let rolled = |w: &DecodedSlotWrite| writes.pending.iter().any(|p| (p.shard, p.index) == (w.shard, w.index));
+        if writes.slots.len() != writes.pending.len() || !writes.slots.iter().all(rolled) {
+            return Err("A rollover must write the slot of every pending transfer it clears".to_string());
+        }
+        Ok(DecodedRollover { writes, shard })
+    }
+}
+
+/// The public values of any action, decoded and validated.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum DecodedPublicValues {
+    Deposit(DecodedDeposit),
+    Send(DecodedSend),
+    SendMany(DecodedSendMany),
+    Withdraw(DecodedWithdraw),
+    Rotate(DecodedRotate),
+    Close(DecodedClose),
+    Attest(DecodedAttest),
+    Rollover(DecodedRollover),
+}
+
+impl DecodedPublicValues {
+    /// The ABI encodings of the actions overlap, so the action has to be known up front.
+    pub fn decode(kind: ActionKind, bytes: &[u8]) -> Result<DecodedPublicValues, String> {
+        Ok(match kind {
+            ActionKind::Deposit => DecodedPublicValues::Deposit(DecodedDeposit::decode(bytes)?),
+            ActionKind::Send => DecodedPublicValues::Send(DecodedSend::decode(bytes)?),
+            ActionKind::SendMany => DecodedPublicValues::SendMany(DecodedSendMany::decode(bytes)?),
+            ActionKind::Withdraw => DecodedPublicValues::Withdraw(DecodedWithdraw::decode(bytes)?),
+            ActionKind::Rotate => DecodedPublicValues::Rotate(DecodedRotate::decode(bytes)?),
+            ActionKind::Close => DecodedPublicValues::Close(DecodedClose::decode(bytes)?),
+            ActionKind::Attest => DecodedPublicValues::Attest(DecodedAttest::decode(bytes)?),
+            ActionKind::Rollover => DecodedPublicValues::Rollover(DecodedRollover::decode(bytes)?),
+        })
+    }
+
+    pub fn kind(&self) -> ActionKind {
+        match self {
+            DecodedPublicValues::Deposit(_) => ActionKind::Deposit,
+            DecodedPublicValues::Send(_) => ActionKind::Send,
+            DecodedPublicValues::SendMany(_) => ActionKind::SendMany,
+            DecodedPublicValues::Withdraw(_) => ActionKind::Withdraw,
+            DecodedPublicValues::Rotate(_) => ActionKind::Rotate,
+            DecodedPublicValues::Close(_) => ActionKind::Close,
+            DecodedPublicValues::Attest(_) => ActionKind::Attest,
+            DecodedPublicValues::Rollover(_) => ActionKind::Rollover,
+        }
+    }
+
+    /// The token of the account the action touches, `NATIVE_ASSET` for ETH. A rollover moves
+    /// every account in its shard, so has none.
+    pub fn asset(&self) -> Option<[u8; 20]> {
+        match self {
+            DecodedPublicValues::Deposit(d) => Some(d.asset),
+            DecodedPublicValues::Send(d) => Some(d.asset),
+            DecodedPublicValues::SendMany(d) => Some(d.asset),
+            DecodedPublicValues::Withdraw(d) => Some(d.asset),
+            DecodedPublicValues::Rotate(d) => Some(d.asset),
+            DecodedPublicValues::Close(d) => Some(d.asset),
+            DecodedPublicValues::Attest(d) => Some(d.asset),
+            DecodedPublicValues::Rollover(_) => None,
+        }
+    }
+
+    /// What the action writes, which is nothing for an attestation.
+    pub fn writes(&self) -> Option<&DecodedWrites> {
+        match self {
+            DecodedPublicValues::Deposit(d) => Some(&d.writes),
+            DecodedPublicValues::Send(d) => Some(&d.writes),
+            DecodedPublicValues::SendMany(d) => Some(&d.writes),
+            DecodedPublicValues::Withdraw(d) => Some(&d.writes),
+            DecodedPublicValues::Rotate(d) => Some(&d.writes),
+            DecodedPublicValues::Close(d) => Some(&d.writes),
+            DecodedPublicValues::Attest(_) => None,
+            DecodedPublicValues::Rollover(d) => Some(&d.writes),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("public values are always serializable")
+    }
+}
+
+impl fmt::Display for DecodedPublicValues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "action: {:?}", self.kind())?;
+        if let Some(asset) = self.asset() {
+            write!(f, "\nasset: 0x{}", hex::encode(asset))?;
+        }
+        if let Some(writes) = self.writes() {
+            for w in writes.slots.iter() {
+                write!(f, "\nslot {}/{} t: {} -> {}", w.shard, w.index, scalar_to_hex(&w.old_t), scalar_to_hex(&w.new_t))?;
+                write!(f, "\nslot {}/{} v: {} -> {}", w.shard, w.index, scalar_to_hex(&w.old_v), scalar_to_hex(&w.new_v))?;
+                write!(f, "\nslot {}/{} account: 0x{} -> 0x{}", w.shard, w.index, hex::encode(w.old_account), hex::encode(w.new_account))?;
+            }
+            for w in writes.pending.iter() {
+                write!(f, "\npending {}/{} account: 0x{}", w.shard, w.index, hex::encode(w.account))?;
+                write!(f, "\npending {}/{} t: {} -> {}", w.shard, w.index, scalar_to_hex(&w.old_t), scalar_to_hex(&w.new_t))?;
+                write!(f, "\npending {}/{} v: {} -> {}", w.shard, w.index, scalar_to_hex(&w.old_v), scalar_to_hex(&w.new_v))?;
+            }
+            for d in writes.shards.iter() {
+                write!(f, "\ndelta {}: {}", d.shard, g1_to_hex(&d.delta))?;
+            }
+        }
+        match self {
+            DecodedPublicValues::Deposit(d) => {
+                write!(f, "\namount: {}", d.amount)?;
+                write!(f, "\npkey: {}", scalar_to_hex(&d.pkey))?;
+                write!(f, "\ntop_up: {}", d.top_up)?;
+                write!(f, "\nshard: {}", d.shard)?;
+                write!(f, "\nindex: {}", d.index)?;
+                write!(f, "\nold_free_list_hash: 0x{}", hex::encode(d.old_free_list_hash))?;
+                write!(f, "\nnew_free_list_hash: 0x{}", hex::encode(d.new_free_list_hash))
+            },
+            DecodedPublicValues::Send(d) => {
+                write!(f, "\npkey_sender: {}", scalar_to_hex(&d.pkey_sender))?;
+                write!(f, "\npkey_receiver: {}", scalar_to_hex(&d.pkey_receiver))?;
+                write!(f, "\nfee: {}", d.fee)?;
+                write!(f, "\nrelayer: 0x{}", hex::encode(d.relayer))?;
+                write!(f, "\nnote_ephemeral: {}", scalar_to_hex(&d.note.ephemeral))?;
+                write!(f, "\nnote_ciphertext: 0x{}", hex::encode(&d.note.ciphertext))?;
+                write!(f, "\nnote_tag: 0x{}", hex::encode(d.note.tag))?;
+                if let (Some(auditor), Some(escrow)) = (d.auditor, &d.escrow) {
+                    write!(f, "\nauditor: {}", scalar_to_hex(&auditor))?;
+                    write!(f, "\nauditor_ciphertext: 0x{}", hex::encode(&escrow.ciphertext))?;
+                    write!(f, "\nauditor_tag: 0x{}", hex::encode(escrow.tag))?;
+                }
+                Ok(())
+            },
+            DecodedPublicValues::SendMany(d) => {
+                write!(f, "\npkey_sender: {}", scalar_to_hex(&d.pkey_sender))?;
+                if let Some(auditor) = d.auditor {
+                    write!(f, "\nauditor: {}", scalar_to_hex(&auditor))?;
+                }
+                for (i, r) in d.receivers.iter().enumerate() {
+                    write!(f, "\nreceiver {}:", i)?;
+                    write!(f, "\n  pkey: {}", scalar_to_hex(&r.pkey))?;
+                    write!(f, "\n  note_ephemeral: {}", scalar_to_hex(&r.note.ephemeral))?;
+                    write!(f, "\n  note_ciphertext: 0x{}", hex::encode(&r.note.ciphertext))?;
+                    write!(f, "\n  note_tag: 0x{}", hex::encode(r.note.tag))?;
+                    if let Some(escrow) = &r.escrow {
+                        write!(f, "\n  auditor_ciphertext: 0x{}", hex::encode(&escrow.ciphertext))?;
+                        write!(f, "\n  auditor_tag: 0x{}", hex::encode(escrow.tag))?;
+                    }
+                }
+                Ok(())
+            },
+            DecodedPublicValues::Withdraw(d) => {
+                write!(f, "\namount: {}", d.amount)?;
+                write!(f, "\nrecipient: 0x{}", hex::encode(d.recipient))?;
+                write!(f, "\npkey: {}", scalar_to_hex(&d.pkey))?;
+                write!(f, "\nfee: {}", d.fee)?;
+                write!(f, "\nrelayer: 0x{}", hex::encode(d.relayer))
+            },
+            DecodedPublicValues::Rotate(d) => {
+                write!(f, "\npkey: {}", scalar_to_hex(&d.pkey))?;
+                write!(f, "\nnew_pkey: {}", scalar_to_hex(&d.new_pkey))
+            },
+            DecodedPublicValues::Close(d) => {
+                write!(f, "\npkey: {}", scalar_to_hex(&d.pkey))?;
+                write!(f, "\nshard: {}", d.shard)?;
+                write!(f, "\nindex: {}", d.index)?;
+                write!(f, "\nold_free_list_hash: 0x{}", hex::encode(d.old_free_list_hash))?;
+                write!(f, "\nnew_free_list_hash: 0x{}", hex::encode(d.new_free_list_hash))
+            },
+            DecodedPublicValues::Attest(d) => {
+                write!(f, "\nsupply: {}", d.supply)?;
+                write!(f, "\nphi {}: {}", d.shard, g1_to_hex(&d.phi))?;
+                write!(f, "\nslot {}/{} t: {}", d.shard, d.index, scalar_to_hex(&d.t))?;
+                write!(f, "\naccount: 0x{}", hex::encode(d.account))?;
+                write!(f, "\npkey: {}", scalar_to_hex(&d.pkey))?;
+                write!(f, "\nthreshold: {}", d.threshold)?;
+                write!(f, "\nnonce: 0x{}", hex::encode(d.nonce))
+            },
+            DecodedPublicValues::Rollover(d) => write!(f, "\nshard: {}", d.shard),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Ledger, RECIPIENT, RELAYER};
+
+    /// Runs an action on `ledger`, checks that its decoded writes move the phis as the native run
+    /// did, and returns the decoded values.
+    fn round_trip(ledger: &mut Ledger, kind: ActionKind, action: impl FnOnce(&mut Ledger) -> Vec<u8>) -> DecodedPublicValues {
+        let phi = ledger.phi.clone();
+        let decoded = DecodedPublicValues::decode(kind, &action(ledger)).unwrap();
+        assert_eq!(decoded.kind(), kind);
+        if let Some(writes) = decoded.writes() {
+            assert_eq!(writes.apply(&phi), ledger.phi);
+        }
+        decoded
+    }
+
+    /// A point on the curve but outside the prime-order subgroup.
+    fn low_order_point() -> G1Affine {
+        (1u8..)
+            .find_map(|x| {
+                let mut bytes = [0u8; 48];
+                bytes[0] = 0x80;
+                bytes[47] = x;
+                let point: Option<G1Affine> = G1Affine::from_compressed_unchecked(&bytes).into();
+                point.filter(|p| !bool::from(p.is_torsion_free()))
+            })
+            .unwrap()
+    }
+
+    /// The scalar field modulus, little-endian, which no canonical scalar encodes to.
+    fn modulus() -> [u8; 32] {
+        let limbs = [0xFFFFFFFF00000001u64, 0x53BDA402FFFE5BFE, 0x3339D80809A1D805, 0x73EDA753299D7D48];
+        let mut bytes = [0u8; 32];
+        for (chunk, limb) in bytes.chunks_mut(8).zip(limbs) {
+            chunk.copy_from_slice(&limb.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn a_deposit_round_trips() {
+        let mut ledger = Ledger::new(4);
+        let DecodedPublicValues::Deposit(d) = round_trip(&mut ledger, ActionKind::Deposit, |l| l.deposit(1, 100)) else { panic!() };
+        assert_eq!((d.amount, d.supply_delta, d.pkey, d.top_up, d.shard, d.index), (100, 100, ledger.pkey(1), false, 0, 0));
+        assert_eq!((d.writes.slots[0].new_t, d.writes.slots[0].new_v), (ledger.pp.t[0], ledger.pp.v[0]));
+        assert_eq!(d.new_free_list_hash, ledger.pp.free_list_hash());
+
+        let DecodedPublicValues::Deposit(d) = round_trip(&mut ledger, ActionKind::Deposit, |l| l.deposit(1, 20)) else { panic!() };
+        assert_eq!((d.amount, d.supply_delta, d.top_up), (20, 20, true));
+        assert_eq!((d.writes.pending[0].new_t, d.writes.pending[0].new_v), (ledger.pp.pending_t[0], ledger.pp.pending_v[0]));
+        assert!(d.writes.shards.is_empty());
+    }
+
+    #[test]
+    fn a_send_round_trips() {
+        let mut ledger = Ledger::new(4);
+        ledger.deposit(1, 100);
+        ledger.deposit(2, 0);
+        let DecodedPublicValues::Send(d) = round_trip(&mut ledger, ActionKind::Send, |l| l.send(1, 2, 100, 30, 2)) else { panic!() };
+        assert_eq!((d.pkey_sender, d.pkey_receiver), (ledger.pkey(1), ledger.pkey(2)));
+        assert_eq!((d.fee, d.supply_delta, d.relayer), (2, -2, RELAYER));
+        assert_eq!((d.writes.slots[0].index, d.writes.pending[0].index), (0, 1));
+        assert_eq!(d.writes.pending[0].new_v, ledger.pp.pending_v[1]);
+        assert_eq!(d.note.decrypt(Ledger::skey(2)), Ok((30, b"rent".to_vec())));
+        assert_eq!((d.auditor, d.escrow), (None, None));
+    }
+
+    #[test]
+    fn a_send_to_many_round_trips() {
+        let mut ledger = Ledger::new(4);
+        ledger.deposit(1, 100);
+        ledger.deposit(2, 0);
+        ledger.deposit(3, 0);
+        let DecodedPublicValues::SendMany(d) = round_trip(&mut ledger, ActionKind::SendMany, |l| l.send_many(1, &[(2, 10), (3, 5)], 100)) else { panic!() };
+        assert_eq!(d.pkey_sender, ledger.pkey(1));
+        assert_eq!(d.receivers.iter().map(|r| r.pkey).collect::<Vec<_>>(), vec![ledger.pkey(2), ledger.pkey(3)]);
+        assert_eq!(d.receivers[1].note.decrypt(Ledger::skey(3)), Ok((5, Vec::new())));
+        assert_eq!(d.writes.pending.iter().map(|w| w.index).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn a_withdrawal_round_trips() {
+        let mut ledger = Ledger::new(4);
+        ledger.deposit(1, 100);
+        let DecodedPublicValues::Withdraw(d) = round_trip(&mut ledger, ActionKind::Withdraw, |l| l.withdraw(1, 100, 40, 1)) else { panic!() };
+        assert_eq!((d.amount, d.fee, d.supply_delta), (40, 1, -41));
+        assert_eq!((d.pkey, d.recipient, d.relayer), (ledger.pkey(1), RECIPIENT, RELAYER));
+        assert_eq!(d.writes.slots[0].new_v, ledger.pp.v[0]);
+    }
+
+    #[test]
+    fn a_rotation_and_a_rekey_round_trip() {
+        let mut ledger = Ledger::new(4);
+        ledger.deposit(1, 100);
+        let DecodedPublicValues::Rotate(d) = round_trip(&mut ledger, ActionKind::Rotate, |l| l.rotate(1, None)) else { panic!() };
+        assert_eq!((d.pkey, d.new_pkey), (ledger.pkey(1), ledger.pkey(1)));
+        assert!(d.writes.pending.is_empty());
+
+        let DecodedPublicValues::Rotate(d) = round_trip(&mut ledger, ActionKind::Rotate, |l| l.rotate(1, Some((4, 100)))) else { panic!() };
+        assert_eq!((d.pkey, d.new_pkey), (ledger.pkey(1), ledger.pkey(4)));
+        assert_eq!(d.writes.slots[0].new_account, ledger.pp.account(0));
+        assert_eq!(d.writes.pending.len(), 1);
+    }
+
+    #[test]
+    fn a_close_round_trips() {
+        let mut ledger = Ledger::new(4);
+        ledger.deposit(1, 10);
+        ledger.withdraw(1, 10, 10, 0);
+        let old_free_list_hash = ledger.pp.free_list_hash();
+        let DecodedPublicValues::Close(d) = round_trip(&mut ledger, ActionKind::Close, |l| l.close(1)) else { panic!() };
+        assert_eq!((d.pkey, d.shard, d.index), (ledger.pkey(1), 0, 0));
+        assert_eq!((d.old_free_list_hash, d.new_free_list_hash), (old_free_list_hash, ledger.pp.free_list_hash()));
+        assert_eq!(d.writes.slots[0].new_account, [0u8; 32]);
+    }
+
+    #[test]
+    fn an_attestation_round_trips() {
+        let mut ledger = Ledger::new(4);
+        ledger.deposit(1, 100);
+        let DecodedPublicValues::Attest(d) = round_trip(&mut ledger, ActionKind::Attest, |l| l.attest(1, 100, 60)) else { panic!() };
+        assert_eq!((d.phi, d.t, d.account), (ledger.phi[0], ledger.pp.t[0], ledger.pp.account(0)));
+        assert_eq!((d.pkey, d.supply, d.threshold, d.nonce), (ledger.pkey(1), 100, 60, [0x0c; 32]));
+    }
+
+    #[test]
+    fn a_rollover_round_trips() {
+        let mut ledger = Ledger::new(4);
+        ledger.deposit(1, 100);
+        ledger.deposit(2, 0);
+        ledger.send(1, 2, 100, 30, 0);
+        let DecodedPublicValues::Rollover(d) = round_trip(&mut ledger, ActionKind::Rollover, |l| l.rollover(0)) else { panic!() };
+        assert_eq!(d.shard, 0);
+        assert_eq!(d.writes.slots.iter().map(|w| w.index).collect::<Vec<_>>(), vec![1]);
+        assert_eq!((d.writes.slots[0].new_t, d.writes.slots[0].new_v), (ledger.pp.t[1], ledger.pp.v[1]));
+    }
+
+    #[test]
+    fn a_point_outside_the_subgroup_is_rejected() {
+        let point = low_order_point();
+        assert!(bool::from(point.is_on_curve()));
+        assert_eq!(decode_phi(&point.to_compressed()), Err("phi is not in the prime-order subgroup".to_string()));
+        assert_eq!(decode_evm_g1(&g1_to_evm(&point)), Err("G1 point is not in the prime-order subgroup".to_string()));
+
+        let mut ledger = Ledger::new(4);
+        ledger.deposit(1, 100);
+        let mut values = PublicValuesWithdraw::abi_decode(&ledger.withdraw(1, 100, 40, 0), true).unwrap();
+        values.shards[0].delta = g1_to_evm(&point).into();
+        let rejected = DecodedWithdraw::decode(&PublicValuesWithdraw::abi_encode(&values));
+        assert_eq!(rejected, Err("G1 point is not in the prime-order subgroup".to_string()));
+
+        let mut values = PublicValuesAttest::abi_decode(&ledger.attest(1, 60, 60), true).unwrap();
+        values.phi = g1_to_evm(&point).into();
+        let rejected = DecodedAttest::decode(&PublicValuesAttest::abi_encode(&values));
+        assert_eq!(rejected, Err("G1 point is not in the prime-order subgroup".to_string()));
+    }
+
+    #[test]
+    fn a_non_canonical_scalar_is_rejected() {
+        assert_eq!(decode_scalar(&modulus()), Err("Scalar is not canonical".to_string()));
+        assert!(decode_scalar(&(Scalar::zero() - Scalar::one()).to_bytes()).is_ok());
+
+        let mut ledger = Ledger::new(4);
+        ledger.deposit(1, 100);
+        let bytes = ledger.withdraw(1, 100, 40, 0);
+        let mut values = PublicValuesWithdraw::abi_decode(&bytes, true).unwrap();
+        values.pkey = modulus().into();
+        let rejected = DecodedWithdraw::decode(&PublicValuesWithdraw::abi_encode(&values));
+        assert_eq!(rejected, Err("Scalar is not canonical".to_string()));
+
+        let mut values = PublicValuesWithdraw::abi_decode(&bytes, true).unwrap();
+        values.slots[0].new_v = modulus().into();
+        let rejected = DecodedWithdraw::decode(&PublicValuesWithdraw::abi_encode(&values));
+        assert_eq!(rejected, Err("Scalar is not canonical".to_string()));
+    }
+}
@@ -0,0 +1,320 @@
+//! Rebuilds the ledger from public values alone, so that anyone watching the contract can derive
+//! the ciphertexts behind the current phi without the private inputs of any action.
+
+use crate::{account_key, settle_supply, DecodedPublicValues, DecodedWrites, PublicParams, NATIVE_ASSET};
+use sp1_bls12_381::{G1Affine, Scalar};
+
+fn index_of(pp: &PublicParams, pkey: &Scalar, asset: &[u8; 20]) -> Result<usize, String> {
+    match pp.index_of.get(&account_key(pkey, asset)) {
+        Some(idx) => Ok(*idx),
+        None => Err("Public key not found".to_string()),
+    }
+}
+
+/// The slot at `index` in `shard`, if the ledger has one.
+fn slot_of(pp: &PublicParams, shard: usize, index: usize) -> Result<usize, String> {
+    if shard >= pp.shards() || index >= pp.degree {
+        return Err(format!("Slot {} of shard {} does not exist", index, shard));
+    }
+    Ok(shard * pp.degree + index)
+}
+
+/// Checks that an account's slot lies in the shard its public values name.
+fn in_shard(pp: &PublicParams, slot: usize, shard: usize) -> Result<usize, String> {
+    if pp.locate(slot).0 != shard {
+        return Err(format!("Account is not in shard {}", shard));
+    }
+    Ok(slot)
+}
+
+/// Checks that the action writes the ciphertext of an account's slot.
+fn writes_slot(pp: &PublicParams, writes: &DecodedWrites, slot: usize) -> Result<(), String> {
+    if !writes.slots.iter().any(|w| pp.locate(slot) == (w.shard, w.index)) {
+        return Err("The slot of the account is not written".to_string());
+    }
+    Ok(())
+}
+
+/// Checks that the action writes the pending transfers of an account's slot.
+fn writes_pending(pp: &PublicParams, writes: &DecodedWrites, slot: usize) -> Result<(), String> {
+    if !writes.pending.iter().any(|w| pp.locate(slot) == (w.shard, w.index)) {
+        return Err("The pending transfers of the account are not written".to_string());
+    }
+    Ok(())
+}
+
+/// Checks that a transfer escrows its amount to the ledger's auditor, or to none if it has none.
+fn same_auditor(pp: &PublicParams, auditor: Option<Scalar>) -> Result<(), String> {
+    if auditor != pp.auditor {
+        return Err("Transfer is escrowed to a different auditor than the ledger's".to_string());
+    }
+    Ok(())
+}
+
+/// Checks that every slot the action writes still holds the old value it names, and that the
+/// deltas are what the writes add to the phis of their shards.
+fn check_writes(pp: &PublicParams, writes: &DecodedWrites) -> Result<(), String> {
+    for w in writes.slots.iter() {
+        let slot = slot_of(pp, w.shard, w.index)?;
+        if pp.t[slot] != w.old_t || pp.v[slot] != w.old_v || pp.account(slot) != w.old_account {
+            return Err(format!("Slot {} of shard {} does not match the current state", w.index, w.shard));
+        }
+    }
+    for w in writes.pending.iter() {
+        let slot = slot_of(pp, w.shard, w.index)?;
+        if pp.pending_t[slot] != w.old_t || pp.pending_v[slot] != w.old_v || pp.account(slot) != w.account {
+            return Err(format!("Pending transfers of slot {} of shard {} do not match the current state", w.index, w.shard));
+        }
+    }
+    for d in writes.shards.iter() {
+        let delta = writes
+            .slots
+            .iter()
+            .filter(|w| w.shard == d.shard)
+            .fold(G1Affine::identity(), |sum, w| sum.add_affine(&G1Affine::from(pp.g1_lagrange_basis[w.index] * (w.new_v - w.old_v))));
+        if d.delta != delta {
+            return Err(format!("Delta of shard {} does not match the writes", d.shard));
+        }
+    }
+    Ok(())
+}
+
+/// Applies the public values of one accepted action to `pp`, and returns the new phis, one per
+/// shard.
+///
+/// Fails if a slot the values write does not hold the old value they name, if their deltas are not
+/// what the writes add, or if the writes are not the ones the action makes. A deposit past the
+/// last shard opens a new one.
+pub fn replay(pp: &mut PublicParams, phi: &[G1Affine], values: &DecodedPublicValues) -> Result<Vec<G1Affine>, String> {
+    let mut next_phi = phi.to_vec();
+    if next_phi.len() != pp.shards() {
+        return Err(format!("Expected {} shard phis, got {}", pp.shards(), next_phi.len()));
+    }
+    let writes = match values {
+        DecodedPublicValues::Attest(d) => {
+            // Nothing changes, so this only checks the attestation is against the current state
+            let idx = in_shard(pp, index_of(pp, &d.pkey, &d.asset)?, d.shard)?;
+            if next_phi[d.shard] != d.phi {
+                return Err(format!("phi does not match the current state of shard {}", d.shard));
+            }
+            if pp.locate(idx).1 != d.index || pp.account(idx) != d.account || pp.t[idx] != d.t {
+                return Err("Attestation does not match the account's slot".to_string());
+            }
+            if d.supply != pp.supply(&d.asset) {
+                return Err(format!("Attestation is against a total supply of {}, not {}", d.supply, pp.supply(&d.asset)));
+            }
+            return Ok(next_phi);
+        },
+        _ => values.writes().expect("every action but an attestation writes"),
+    };
+    if let DecodedPublicValues::Deposit(d) = values {
+        if !d.top_up && pp.free.is_empty() && pp.idx == pp.v.len() && d.shard == pp.shards() {
+            pp.open_shard();
+            next_phi.push(G1Affine::identity());
+        }
+    }
+    check_writes(pp, writes)?;
+    match values {
+        DecodedPublicValues::Deposit(d) if d.top_up => {
+            let idx = index_of(pp, &d.pkey, &d.asset)?;
+            if pp.locate(idx) != (d.shard, d.index) {
+                return Err("Top-up does not match the account".to_string());
+            }
+            settle_supply(pp, d.asset, d.supply_delta)?;
+        },
+        DecodedPublicValues::Deposit(d) => {
+            if d.old_free_list_hash != pp.free_list_hash() {
+                return Err("Free list does not match the current state".to_string());
+            }
+            let idx = match pp.free.last() {
+                Some(idx) => *idx,
+                None if pp.idx < pp.v.len() => pp.idx,
+                None => return Err("Deposit failed".to_string()),
+            };
+            if pp.locate(idx) != (d.shard, d.index) || pp.index_of.contains_key(&account_key(&d.pkey, &d.asset)) {
+                return Err("Deposit failed".to_string());
+            }
+            if pp.free.pop().is_none() {
+                pp.idx += 1;
+            }
+            if d.new_free_list_hash != pp.free_list_hash() {
+                return Err("Free list does not match the deposit".to_string());
+            }
+            settle_supply(pp, d.asset, d.supply_delta)?;
+            pp.pkeys[idx] = d.pkey;
+            pp.assets[idx] = d.asset;
+            pp.index_of.insert(account_key(&d.pkey, &d.asset), idx);
+        },
+        DecodedPublicValues::Send(d) => {
+            same_auditor(pp, d.auditor)?;
+            let idx_sender = index_of(pp, &d.pkey_sender, &d.asset)?;
+            let idx_receiver = index_of(pp, &d.pkey_receiver, &d.asset)?;
+            writes_slot(pp, writes, idx_sender)?;
+            writes_pending(pp, writes, idx_receiver)?;
+            settle_supply(pp, d.asset, d.supply_delta)?;
+        },
+        DecodedPublicValues::SendMany(d) => {
+            same_auditor(pp, d.auditor)?;
+            let idx_sender = index_of(pp, &d.pkey_sender, &d.asset)?;
+            writes_slot(pp, writes, idx_sender)?;
+            let mut seen = vec![idx_sender];
+            for receiver in d.receivers.iter() {
+                let idx = index_of(pp, &receiver.pkey, &d.asset)?;
+                if seen.contains(&idx) {
+                    return Err("Receivers must be distinct from each other and the sender".to_string());
+                }
+                seen.push(idx);
+                writes_pending(pp, writes, idx)?;
+            }
+        },
+        DecodedPublicValues::Withdraw(d) => {
+            let idx = index_of(pp, &d.pkey, &d.asset)?;
+            writes_slot(pp, writes, idx)?;
+            // The new ciphertext is re-randomized, so only the proof vouches that it is debited
+            settle_supply(pp, d.asset, d.supply_delta)?;
+        },
+        DecodedPublicValues::Rotate(d) => {
+            let idx = index_of(pp, &d.pkey, &d.asset)?;
+            writes_slot(pp, writes, idx)?;
+            if d.new_pkey != d.pkey {
+                writes_pending(pp, writes, idx)?;
+                if pp.index_of.contains_key(&account_key(&d.new_pkey, &d.asset)) {
+                    return Err("The new public key already has an account".to_string());
+                }
+                pp.pkeys[idx] = d.new_pkey;
+                pp.index_of.remove(&account_key(&d.pkey, &d.asset));
+                pp.index_of.insert(account_key(&d.new_pkey, &d.asset), idx);
+            }
+        },
+        DecodedPublicValues::Close(d) => {
+            let idx = index_of(pp, &d.pkey, &d.asset)?;
+            if pp.locate(idx) != (d.shard, d.index) || d.old_free_list_hash != pp.free_list_hash() {
+                return Err("Close does not match the account".to_string());
+            }
+            pp.pkeys[idx] = Scalar::zero();
+            pp.assets[idx] = NATIVE_ASSET;
+            pp.index_of.remove(&account_key(&d.pkey, &d.asset));
+            pp.free.push(idx);
+            if d.new_free_list_hash != pp.free_list_hash() {
+                return Err("Free list does not match the close".to_string());
+            }
+        },
+        DecodedPublicValues::Rollover(values) => {
+            // A transfer in landing after the rollover was proven makes it incomplete, and the
+            // contract rejects it
+            let (start, end) = (values.shard * pp.degree, (values.shard + 1) * pp.degree);
+            if let Some(slot) = (start..end.min(pp.pending_t.len())).find(|slot| pp.has_pending(*slot) && writes_pending(pp, writes, *slot).is_err()) {
+                return Err(format!("Rollover leaves the pending transfers of slot {} of shard {}", slot - start, values.shard));
+            }
+            for w in writes.slots.iter() {
+                let slot = slot_of(pp, w.shard, w.index)?;
+                if !pp.has_pending(slot) || w.new_t != w.old_t * pp.pending_t[slot] || w.new_v != w.old_v * pp.pending_v[slot] {
+                    return Err(format!("Rollover does not move the pending transfers of slot {} of shard {}", w.index, w.shard));
+                }
+            }
+        },
+        DecodedPublicValues::Attest(_) => unreachable!(),
+    }
+    for w in writes.slots.iter() {
+        let slot = slot_of(pp, w.shard, w.index)?;
+        if pp.account(slot) != w.new_account {
+            return Err(format!("Slot {} of shard {} is written to the wrong account", w.index, w.shard));
+        }
+        pp.t[slot] = w.new_t;
+        pp.v[slot] = w.new_v;
+    }
+    for w in writes.pending.iter() {
+        let slot = slot_of(pp, w.shard, w.index)?;
+        pp.pending_t[slot] = w.new_t;
+        pp.pending_v[slot] = w.new_v;
+    }
+    Ok(writes.apply(&next_phi))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Ledger;
+    use crate::{ActionKind, DecodedSend};
+
+    /// Checks that replaying brought `pp` to the state the native run left `ledger` in.
+    fn assert_same_state(pp: &PublicParams, ledger: &Ledger) {
+        assert_eq!((&pp.t, &pp.v), (&ledger.pp.t, &ledger.pp.v));
+        assert_eq!((&pp.pending_t, &pp.pending_v), (&ledger.pp.pending_t, &ledger.pp.pending_v));
+        assert_eq!((&pp.pkeys, &pp.assets, &pp.index_of), (&ledger.pp.pkeys, &ledger.pp.assets, &ledger.pp.index_of));
+        assert_eq!((pp.idx, &pp.free, &pp.supply), (ledger.pp.idx, &ledger.pp.free, &ledger.pp.supply));
+    }
+
+    /// Runs an action on `ledger` and replays its public values on `pp` and `phi`, which must hold
+    /// the state the action was run against.
+    fn step(ledger: &mut Ledger, pp: &mut PublicParams, phi: &mut Vec<G1Affine>, kind: ActionKind, action: impl FnOnce(&mut Ledger) -> Vec<u8>) {
+        let values = DecodedPublicValues::decode(kind, &action(ledger)).unwrap();
+        *phi = replay(pp, phi, &values).unwrap();
+        assert_eq!(*phi, ledger.phi);
+        assert_same_state(pp, ledger);
+    }
+
+    #[test]
+    fn every_action_replays_to_the_native_result() {
+        let mut ledger = Ledger::new(2);
+        let (mut pp, mut phi) = (ledger.pp.clone(), ledger.phi.clone());
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Deposit, |l| l.deposit(1, 100));
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Deposit, |l| l.deposit(2, 0));
+        // The third account opens a second shard
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Deposit, |l| l.deposit(3, 50));
+        assert_eq!(phi.len(), 2);
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Deposit, |l| l.deposit(3, 5));
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Send, |l| l.send(1, 3, 100, 30, 2));
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::SendMany, |l| l.send_many(1, &[(2, 10), (3, 8)], 68));
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Withdraw, |l| l.withdraw(1, 50, 40, 1));
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Rotate, |l| l.rotate(1, None));
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Rotate, |l| l.rotate(1, Some((4, 9))));
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Rollover, |l| l.rollover(0));
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Rollover, |l| l.rollover(1));
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Attest, |l| l.attest(3, 93, 90));
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Withdraw, |l| l.withdraw(2, 10, 10, 0));
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Close, |l| l.close(2));
+        // The closed slot is reused before the next unused one
+        step(&mut ledger, &mut pp, &mut phi, ActionKind::Deposit, |l| l.deposit(5, 1));
+        assert_eq!(ledger.pp.slot_of(&ledger.pkey(5), &NATIVE_ASSET), Ok(1));
+    }
+
+    #[test]
+    fn values_not_matching_the_current_state_are_rejected() {
+        let mut ledger = Ledger::new(4);
+        ledger.deposit(1, 100);
+        ledger.deposit(2, 0);
+        let (pp, phi) = (ledger.pp.clone(), ledger.phi.clone());
+        let DecodedPublicValues::Send(send) = DecodedPublicValues::decode(ActionKind::Send, &ledger.send(1, 2, 100, 30, 0)).unwrap() else { panic!() };
+        let rejected = |send: DecodedSend| replay(&mut pp.clone(), &phi, &DecodedPublicValues::Send(send));
+
+        let mut values = send.clone();
+        values.writes.slots[0].old_t = Scalar::one();
+        assert_eq!(rejected(values), Err("Slot 0 of shard 0 does not match the current state".to_string()));
+        let mut values = send.clone();
+        values.writes.slots[0].old_v = Scalar::one();
+        assert_eq!(rejected(values), Err("Slot 0 of shard 0 does not match the current state".to_string()));
+        let mut values = send.clone();
+        values.writes.slots[0].old_account = [0x01; 32];
+        assert_eq!(rejected(values), Err("Slot 0 of shard 0 does not match the current state".to_string()));
+        let mut values = send.clone();
+        values.writes.slots[0].new_account = [0x01; 32];
+        assert_eq!(rejected(values), Err("Slot 0 of shard 0 is written to the wrong account".to_string()));
+        let mut values = send.clone();
+        values.writes.pending[0].old_v = Scalar::one();
+        assert_eq!(rejected(values), Err("Pending transfers of slot 1 of shard 0 do not match the current state".to_string()));
+        let mut values = send.clone();
+        values.writes.pending[0].account = [0x01; 32];
+        assert_eq!(rejected(values), Err("Pending transfers of slot 1 of shard 0 do not match the current state".to_string()));
+        let mut values = send.clone();
+        values.writes.shards[0].delta = G1Affine::generator();
+        assert_eq!(rejected(values), Err("Delta of shard 0 does not match the writes".to_string()));
+        assert!(rejected(send.clone()).is_ok());
+
+        // Once applied, the same values no longer match
+        let mut pp = pp.clone();
+        let next_phi = replay(&mut pp, &phi, &DecodedPublicValues::Send(send.clone())).unwrap();
+        let replayed = replay(&mut pp, &next_phi, &DecodedPublicValues::Send(send));
+        assert_eq!(replayed, Err("Slot 0 of shard 0 does not match the current state".to_string()));
+    }
+}
@@ -0,0 +1,72 @@
+//! Generates the Solidity definitions of the public values structs from the `sol!` block, so the
+//! contracts decode exactly what the program commits.
+
+use crate::{
+    PublicValuesAttest, PublicValuesClose, PublicValuesDeposit, PublicValuesRollover, PublicValuesRotate, PublicValuesSend,
+    PublicValuesSendMany, PublicValuesWithdraw,
+};
+use alloy_sol_types::SolStruct;
+
+/// Where the generated definitions are checked in, relative to the repository root.
+pub const SOLIDITY_PATH: &str = "contracts/src/PublicValues.sol";
+
+const HEADER: &str = "// SPDX-License-Identifier: MIT
+// Generated from the `sol!` block in lib/src/lib.rs. Do not edit by hand, run
+// `cargo run -p state-machine-lib --bin sol-gen` instead.
+pragma solidity ^0.8.20;
+";
+
+/// The EIP-712 encode types of every public values struct, which list each struct followed by
+/// the structs it references as `Name(type field,...)`.
+fn encode_types() -> Vec<String> {
+    vec![
+        PublicValuesDeposit::eip712_encode_type().into_owned(),
+        PublicValuesSend::eip712_encode_type().into_owned(),
+        PublicValuesSendMany::eip712_encode_type().into_owned(),
+        PublicValuesWithdraw::eip712_encode_type().into_owned(),
+        PublicValuesRotate::eip712_encode_type().into_owned(),
+        PublicValuesClose::eip712_encode_type().into_owned(),
+        PublicValuesAttest::eip712_encode_type().into_owned(),
+        PublicValuesRollover::eip712_encode_type().into_owned(),
+    ]
+}
+
+/// Renders the contents of `contracts/src/PublicValues.sol`.
+pub fn solidity_structs() -> String {
+    let mut seen: Vec<String> = Vec::new();
+    let mut out = String::from(HEADER);
+    for encode_type in encode_types() {
+        let mut rest = encode_type.as_str();
+        while let Some(open) = rest.find('(') {
+            let close = open + rest[open..].find(')').expect("unbalanced encode type");
+            let name = &rest[..open];
+            let fields = &rest[open + 1..close];
+            rest = &rest[close + 1..];
+            if seen.iter().any(|s| s == name) {
+                continue;
+            }
+            seen.push(name.to_string());
+            out.push_str(&format!("\nstruct {} {{\n", name));
+            for field in fields.split(',').filter(|f| !f.is_empty()) {
+                out.push_str(&format!("    {};\n", field));
+            }
+            out.push_str("}\n");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checked_in_structs_match_the_sol_block() {
+        assert_eq!(
+            solidity_structs(),
+            include_str!("../../contracts/src/PublicValues.sol"),
+            "{} is out of date, run `cargo run -p state-machine-lib --bin sol-gen`",
+            SOLIDITY_PATH
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Spend authorization. The spend key never enters a witness: its holder signs the terms of an
+//! action instead, and the program checks the signature against the spend public key the view key
+//! is hashed from. A prover holding the view key can then prove an action the spend key signed, but
+//! no other.
+//!
+//! Signatures are Schnorr over G1: the spend public key is `G·s`, a signature on `m` is `(R, z)`
+//! with `R = G·k` and `z = k + e·s` for `e = H(R, G·s, m)`. `k` is hashed from `s` and `m`, so signing
+//! needs no randomness.
+//!
+//! The program signs over the account's current ciphertext along with the terms, and every action
+//! that spends writes a fresh ciphertext, so an authorization is good for one action and cannot be
+//! proven again once it lands. A rollover also moves the ciphertext, after which the action has to
+//! be signed again.
+
+use crate::{scalar_to_exponent, Action, ElGamal, PublicParams};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sp1_bls12_381::{G1Affine, Scalar};
+
+fn hash_to_scalar(domain: &[u8], parts: &[&[u8]]) -> Scalar {
+    let mut wide = [0u8; 64];
+    for (i, half) in wide.chunks_mut(32).enumerate() {
+        let mut hasher = Sha256::new().chain_update(domain).chain_update([i as u8]);
+        for part in parts {
+            hasher.update(part);
+        }
+        half.copy_from_slice(&hasher.finalize());
+    }
+    Scalar::from_bytes_wide(&wide)
+}
+
+fn spend_scalar(spend_key: [u64; 4]) -> Scalar {
+    Scalar::from_raw(spend_key)
+}
+
+/// The public key of a spend key, `G·s`.
+pub fn spend_pkey(spend_key: [u64; 4]) -> G1Affine {
+    G1Affine::from(G1Affine::generator() * spend_scalar(spend_key))
+}
+
+/// Derives the view key of an account, the ElGamal secret that decrypts its balance and the notes
+/// sent to it, from its spend key. It is hashed from the spend public key, so the program can tie a
+/// spend signature to the account without seeing the spend key.
+pub fn view_key(spend_key: [u64; 4]) -> [u64; 4] {
+    view_key_of(&spend_pkey(spend_key))
+}
+
+/// The view key belonging to `spend_pkey`. Whoever holds the spend public key can derive it, so the
+/// spend public key is only handed to those trusted with the view key.
+pub fn view_key_of(spend_pkey: &G1Affine) -> [u64; 4] {
+    scalar_to_exponent(&hash_to_scalar(b"zk-state-machine view key", &[&spend_pkey.to_compressed()]))
+}
+
+/// A Schnorr signature by a spend key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signature {
+    pub r: G1Affine,
+    pub z: Scalar,
+}
+
+impl Signature {
+    fn challenge(r: &G1Affine, spend_pkey: &G1Affine, message: &[u8; 32]) -> Scalar {
+        hash_to_scalar(b"zk-state-machine spend challenge", &[&r.to_compressed(), &spend_pkey.to_compressed(), message])
+    }
+
+    pub fn sign(spend_key: [u64; 4], message: &[u8; 32]) -> Signature {
+        let s = spend_scalar(spend_key);
+        let k = hash_to_scalar(b"zk-state-machine spend nonce", &[&s.to_bytes(), message]);
+        let r = G1Affine::from(G1Affine::generator() * k);
+        let e = Signature::challenge(&r, &spend_pkey(spend_key), message);
+        Signature { r, z: k + e * s }
+    }
+
+    pub fn verify(&self, spend_pkey: &G1Affine, message: &[u8; 32]) -> bool {
+        let e = Signature::challenge(&self.r, spend_pkey, message);
+        G1Affine::from(G1Affine::generator() * self.z) == G1Affine::from(self.r + spend_pkey * e)
+    }
+}
+
+/// What the program takes in place of the spend key: the view key it decrypts and debits with, and
+/// the spend key's signature over the action. The default is a placeholder for an action that is
+/// not signed yet, see `Action::sign`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Authorization {
+    pub view_key: [u64; 4],
+    pub spend_pkey: G1Affine,
+    pub signature: Signature,
+}
+
+impl Authorization {
+    /// Signs `action` for the account `spend_key` holds in `asset`, as it stands in `pp`.
+    pub fn sign(spend_key: [u64; 4], pp: &PublicParams, asset: &[u8; 20], action: &Action) -> Result<Authorization, String> {
+        let view_key = view_key(spend_key);
+        let message = spend_message(pp, view_key, asset, action)?;
+        Ok(Authorization { view_key, spend_pkey: spend_pkey(spend_key), signature: Signature::sign(spend_key, &message) })
+    }
+
+    /// Checks that the spend key behind the view key signed `action` against the account's current
+    /// ciphertext, and returns the view key.
+    pub fn verify(&self, pp: &PublicParams, asset: &[u8; 20], action: &Action) -> Result<[u64; 4], String> {
+        if view_key_of(&self.spend_pkey) != self.view_key {
+            return Err("The view key does not belong to the spend key".to_string());
+        }
+        let message = spend_message(pp, self.view_key, asset, action)?;
+        if !self.signature.verify(&self.spend_pkey, &message) {
+            return Err("The spend key did not sign the action".to_string());
+        }
+        Ok(self.view_key)
+    }
+}
+
+impl Action {
+    /// The authorization of an action that needs a spend key, with the asset of its account.
+    pub fn authorization(&self) -> Option<(&Authorization, &[u8; 20])> {
+        match self {
+            Action::Send(s) => Some((&s.auth_sender, &s.asset)),
+            Action::SendMany(s) => Some((&s.auth_sender, &s.asset)),
+            Action::Withdraw(w) => Some((&w.auth, &w.asset)),
+            Action::Rotate(r) => Some((&r.auth, &r.asset)),
+            Action::Close(c) => Some((&c.auth, &c.asset)),
+            Action::Attest(a) => Some((&a.auth, &a.asset)),
+            Action::Deposit(_) | Action::Rollover(_) => None,
+        }
+    }
+
+    /// Signs the action with `spend_key` against the account's ciphertext in `pp`, filling in its
+    /// authorization. Actions that need no spend key are left as they are.
+    pub fn sign(&mut self, spend_key: [u64; 4], pp: &PublicParams) -> Result<(), String> {
+        let Some((_, asset)) = self.authorization() else {
+            return Ok(());
+        };
+        let auth = Authorization::sign(spend_key, pp, &asset.clone(), self)?;
+        match self {
+            Action::Send(s) => s.auth_sender = auth,
+            Action::SendMany(s) => s.auth_sender = auth,
+            Action::Withdraw(w) => w.auth = auth,
+            Action::Rotate(r) => r.auth = auth,
+            Action::Close(c) => c.auth = auth,
+            Action::Attest(a) => a.auth = auth,
+            Action::Deposit(_) | Action::Rollover(_) => {},
+        }
+        Ok(())
+    }
+}
+
+/// What the spend key signs for `action`: the account's current ciphertext and the terms of the
+/// action, leaving out the witness a prover fills in, the balance and the randomness.
+pub fn spend_message(pp: &PublicParams, view_key: [u64; 4], asset: &[u8; 20], action: &Action) -> Result<[u8; 32], String> {
+    let pkey = ElGamal::new(pp.g).from_skey(view_key);
+    let idx = pp.slot_of(&pkey, asset)?;
+    let mut hasher = Sha256::new()
+        .chain_update(b"zk-state-machine spend")
+        .chain_update(pp.t[idx].to_bytes())
+        .chain_update(pp.v[idx].to_bytes())
+        .chain_update(asset);
+    match action {
+        Action::Send(s) => {
+            hasher.update([1u8]);
+            hasher.update(s.pkey_receiver.to_bytes());
+            hasher.update(s.amount.to_le_bytes());
+            hasher.update(s.fee.to_le_bytes());
+            hasher.update(s.relayer);
+            hasher.update((s.memo.len() as u64).to_le_bytes());
+            hasher.update(&s.memo);
+        },
+        Action::SendMany(s) => {
+            hasher.update([2u8]);
+            for receiver in s.receivers.iter() {
+                hasher.update(receiver.pkey.to_bytes());
+                hasher.update(receiver.amount.to_le_bytes());
+                hasher.update((receiver.memo.len() as u64).to_le_bytes());
+                hasher.update(&receiver.memo);
+            }
+        },
+        Action::Withdraw(w) => {
+            hasher.update([3u8]);
+            hasher.update(w.amount.to_le_bytes());
+            hasher.update(w.recipient);
+            hasher.update(w.fee.to_le_bytes());
+            hasher.update(w.relayer);
+        },
+        Action::Rotate(r) => {
+            hasher.update([4u8]);
+            if let Some(rekey) = &r.rekey {
+                hasher.update(rekey.new_pkey.to_bytes());
+            }
+        },
+        Action::Close(_) => hasher.update([5u8]),
+        Action::Attest(a) => {
+            hasher.update([6u8]);
+            hasher.update(a.threshold.to_le_bytes());
+            hasher.update(a.nonce);
+        },
+        Action::Deposit(_) | Action::Rollover(_) => return Err("The action needs no spend key".to_string()),
+    }
+    Ok(hasher.finalize().into())
+}
@@ -0,0 +1,222 @@
+//! A ledger for tests, whose actions run natively and are committed to public values the way
+//! `program/src/main.rs` commits them, without the spend authorization the program checks. Crates
+//! other than this one get it with the `testing` feature.
+
+use crate::public_values::g1_to_evm;
+use crate::writes::supply_delta;
+use crate::*;
+use alloy_sol_types::private::{u256, Address};
+use alloy_sol_types::SolType;
+use sp1_bls12_381::{G1Affine, Scalar};
+
+pub const RELAYER: [u8; 20] = [0x99; 20];
+pub const RECIPIENT: [u8; 20] = [0x42; 20];
+
+pub struct Ledger {
+    pub pp: PublicParams,
+    pub phi: Vec<G1Affine>,
+    random: u64,
+}
+
+impl Ledger {
+    /// An empty ledger of one shard of `degree` slots.
+    pub fn new(degree: usize) -> Ledger {
+        let pp = PublicParams::setup(degree);
+        let phi = vec![KZG::new(pp.g1_lagrange_basis.clone()).commit(vec![Scalar::zero(); degree]).unwrap()];
+        Ledger { pp, phi, random: 0 }
+    }
+
+    /// The view key of the `i`th account.
+    pub fn skey(i: u64) -> [u64; 4] {
+        view_key([i, 7, 0, 0])
+    }
+
+    pub fn pkey(&self, i: u64) -> Scalar {
+        ElGamal::new(self.pp.g).from_skey(Ledger::skey(i))
+    }
+
+    /// Fresh randomness for every ciphertext and note.
+    fn random(&mut self) -> [u64; 4] {
+        self.random += 1;
+        [self.random, 0x5eed, 0, 0]
+    }
+
+    pub fn deposit(&mut self, i: u64, amount: u64) -> Vec<u8> {
+        let (pkey, random) = (self.pkey(i), self.random());
+        let key = account_key(&pkey, &NATIVE_ASSET);
+        let top_up = self.pp.index_of.contains_key(&key);
+        let before = self.pp.clone();
+        self.phi = deposit(&mut self.pp, pkey, NATIVE_ASSET, random, amount, &self.phi).unwrap();
+        let idx = self.pp.index_of[&key];
+        let (shard, index) = self.pp.locate(idx);
+        let writes = if top_up { Writes::new(&before, &self.pp, &[], &[idx]) } else { Writes::new(&before, &self.pp, &[idx], &[]) };
+        PublicValuesDeposit::abi_encode(&PublicValuesDeposit {
+            slots: writes.slots,
+            pending: writes.pending,
+            shards: writes.shards,
+            asset: Address::from(NATIVE_ASSET),
+            amount: u256(amount),
+            supply_delta: supply_delta(&before, &self.pp, &NATIVE_ASSET),
+            pkey: pkey.to_bytes().into(),
+            top_up,
+            shard: shard as u64,
+            index: index as u64,
+            old_free_list_hash: before.free_list_hash().into(),
+            new_free_list_hash: self.pp.free_list_hash().into(),
+        })
+    }
+
+    pub fn send(&mut self, from: u64, to: u64, balance: u64, amount: u64, fee: u64) -> Vec<u8> {
+        let (pkey_sender, pkey_receiver) = (self.pkey(from), self.pkey(to));
+        let (random, debit_random, note_random) = (self.random(), self.random(), self.random());
+        let before = self.pp.clone();
+        self.phi = send(&mut self.pp, Ledger::skey(from), pkey_receiver, NATIVE_ASSET, balance, amount, fee, random, debit_random, &self.phi).unwrap();
+        let idx_sender = self.pp.index_of[&account_key(&pkey_sender, &NATIVE_ASSET)];
+        let idx_receiver = self.pp.index_of[&account_key(&pkey_receiver, &NATIVE_ASSET)];
+        let writes = Writes::new(&before, &self.pp, &[idx_sender], &[idx_receiver]);
+        let note = Note::encrypt(self.pp.g, pkey_receiver, amount, b"rent", note_random).unwrap();
+        PublicValuesSend::abi_encode(&PublicValuesSend {
+            slots: writes.slots,
+            pending: writes.pending,
+            shards: writes.shards,
+            asset: Address::from(NATIVE_ASSET),
+            pkey_sender: pkey_sender.to_bytes().into(),
+            pkey_receiver: pkey_receiver.to_bytes().into(),
+            fee: u256(fee),
+            supply_delta: supply_delta(&before, &self.pp, &NATIVE_ASSET),
+            relayer: Address::from(RELAYER),
+            note_ephemeral: note.ephemeral.to_bytes().into(),
+            note_ciphertext: note.ciphertext.into(),
+            note_tag: note.tag.into(),
+            auditor: Scalar::zero().to_bytes().into(),
+            auditor_ciphertext: Vec::new().into(),
+            auditor_tag: [0u8; 32].into(),
+        })
+    }
+
+    pub fn send_many(&mut self, from: u64, to: &[(u64, u64)], balance: u64) -> Vec<u8> {
+        let pkey_sender = self.pkey(from);
+        let receivers: Vec<(Scalar, u64, [u64; 4])> = to.iter().map(|(i, amount)| (self.pkey(*i), *amount, self.random())).collect();
+        let debit_random = self.random();
+        let before = self.pp.clone();
+        self.phi = send_many(&mut self.pp, Ledger::skey(from), NATIVE_ASSET, &receivers, balance, debit_random, &self.phi).unwrap();
+        let idx_sender = self.pp.index_of[&account_key(&pkey_sender, &NATIVE_ASSET)];
+        let idx_receivers: Vec<usize> = receivers.iter().map(|(pkey, _, _)| self.pp.index_of[&account_key(pkey, &NATIVE_ASSET)]).collect();
+        let writes = Writes::new(&before, &self.pp, &[idx_sender], &idx_receivers);
+        let receivers = receivers
+            .iter()
+            .map(|(pkey, amount, _)| {
+                let note = Note::encrypt(self.pp.g, *pkey, *amount, b"", self.random()).unwrap();
+                SendManyReceiver {
+                    pkey: pkey.to_bytes().into(),
+                    note_ephemeral: note.ephemeral.to_bytes().into(),
+                    note_ciphertext: note.ciphertext.into(),
+                    note_tag: note.tag.into(),
+                    auditor_ciphertext: Vec::new().into(),
+                    auditor_tag: [0u8; 32].into(),
+                }
+            })
+            .collect();
+        PublicValuesSendMany::abi_encode(&PublicValuesSendMany {
+            slots: writes.slots,
+            pending: writes.pending,
+            shards: writes.shards,
+            asset: Address::from(NATIVE_ASSET),
+            pkey_sender: pkey_sender.to_bytes().into(),
+            auditor: Scalar::zero().to_bytes().into(),
+            receivers,
+        })
+    }
+
+    pub fn withdraw(&mut self, i: u64, balance: u64, amount: u64, fee: u64) -> Vec<u8> {
+        let (pkey, random) = (self.pkey(i), self.random());
+        let before = self.pp.clone();
+        self.phi = withdraw(&mut self.pp, Ledger::skey(i), NATIVE_ASSET, balance, amount, fee, random, &self.phi, RECIPIENT).unwrap();
+        let writes = Writes::new(&before, &self.pp, &[self.pp.index_of[&account_key(&pkey, &NATIVE_ASSET)]], &[]);
+        PublicValuesWithdraw::abi_encode(&PublicValuesWithdraw {
+            slots: writes.slots,
+            pending: writes.pending,
+            shards: writes.shards,
+            asset: Address::from(NATIVE_ASSET),
+            amount: u256(amount),
+            recipient: Address::from(RECIPIENT),
+            pkey: pkey.to_bytes().into(),
+            fee: u256(fee),
+            supply_delta: supply_delta(&before, &self.pp, &NATIVE_ASSET),
+            relayer: Address::from(RELAYER),
+        })
+    }
+
+    /// Re-randomizes the account of `i`, or moves it to the key of `to` with `balance`.
+    pub fn rotate(&mut self, i: u64, to: Option<(u64, u64)>) -> Vec<u8> {
+        let (pkey, random) = (self.pkey(i), self.random());
+        let before = self.pp.clone();
+        let (phi, idx) = match to {
+            Some((to, balance)) => {
+                let new_pkey = self.pkey(to);
+                rekey(&mut self.pp, Ledger::skey(i), NATIVE_ASSET, balance, new_pkey, random, &self.phi).unwrap()
+            },
+            None => rotate(&mut self.pp, Ledger::skey(i), NATIVE_ASSET, random, &self.phi).unwrap(),
+        };
+        self.phi = phi;
+        let pending: &[usize] = if to.is_some() { &[idx] } else { &[] };
+        let writes = Writes::new(&before, &self.pp, &[idx], pending);
+        PublicValuesRotate::abi_encode(&PublicValuesRotate {
+            slots: writes.slots,
+            pending: writes.pending,
+            shards: writes.shards,
+            asset: Address::from(NATIVE_ASSET),
+            pkey: pkey.to_bytes().into(),
+            new_pkey: self.pp.pkeys[idx].to_bytes().into(),
+        })
+    }
+
+    pub fn close(&mut self, i: u64) -> Vec<u8> {
+        let before = self.pp.clone();
+        let (phi, idx) = close(&mut self.pp, Ledger::skey(i), NATIVE_ASSET, &self.phi).unwrap();
+        self.phi = phi;
+        let (shard, index) = self.pp.locate(idx);
+        let writes = Writes::new(&before, &self.pp, &[idx], &[idx]);
+        PublicValuesClose::abi_encode(&PublicValuesClose {
+            slots: writes.slots,
+            pending: writes.pending,
+            shards: writes.shards,
+            asset: Address::from(NATIVE_ASSET),
+            pkey: self.pkey(i).to_bytes().into(),
+            shard: shard as u64,
+            index: index as u64,
+            old_free_list_hash: before.free_list_hash().into(),
+            new_free_list_hash: self.pp.free_list_hash().into(),
+        })
+    }
+
+    pub fn attest(&self, i: u64, balance: u64, threshold: u64) -> Vec<u8> {
+        let idx = attest(&self.pp, Ledger::skey(i), NATIVE_ASSET, balance, threshold, &self.phi).unwrap();
+        let (shard, index) = self.pp.locate(idx);
+        PublicValuesAttest::abi_encode(&PublicValuesAttest {
+            phi: g1_to_evm(&self.phi[shard]).into(),
+            shard: shard as u64,
+            index: index as u64,
+            account: self.pp.account(idx).into(),
+            t: self.pp.t[idx].to_bytes().into(),
+            asset: Address::from(NATIVE_ASSET),
+            supply: u256(self.pp.supply(&NATIVE_ASSET)),
+            pkey: self.pp.pkeys[idx].to_bytes().into(),
+            threshold: u256(threshold),
+            nonce: [0x0c; 32].into(),
+        })
+    }
+
+    pub fn rollover(&mut self, shard: usize) -> Vec<u8> {
+        let before = self.pp.clone();
+        self.phi = rollover(&mut self.pp, shard, &self.phi).unwrap();
+        let rolled: Vec<usize> = (shard * self.pp.degree..(shard + 1) * self.pp.degree).filter(|slot| before.has_pending(*slot)).collect();
+        let writes = Writes::new(&before, &self.pp, &rolled, &rolled);
+        PublicValuesRollover::abi_encode(&PublicValuesRollover {
+            slots: writes.slots,
+            pending: writes.pending,
+            shards: writes.shards,
+            shard: shard as u64,
+        })
+    }
+}
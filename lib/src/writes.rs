@@ -0,0 +1,84 @@
+//! The writes an action makes to the ledger, which the program commits in place of whole phis, so
+//! that actions on different slots commute.
+//!
+//! KZG commitments are additive in G1, so writing `v` to a slot moves the phi of its shard by
+//! `(v - old_v)` times the slot's Lagrange basis point whatever the other slots hold. An action
+//! therefore only has to pin the slots it writes, and its delta can be added to whichever phi is
+//! current when it lands.
+
+use crate::public_values::g1_to_evm;
+use crate::{PendingWrite, PublicParams, ShardDelta, SlotWrite};
+use alloy_sol_types::private::I256;
+use sp1_bls12_381::{G1Affine, Scalar};
+
+/// The slots and pending transfers an action writes, and what that adds to the phis of their
+/// shards.
+#[derive(Clone, Default)]
+pub struct Writes {
+    pub slots: Vec<SlotWrite>,
+    pub pending: Vec<PendingWrite>,
+    pub shards: Vec<ShardDelta>,
+}
+
+/// What an action moved the total supply of `asset` by, from `before` to `after`.
+pub fn supply_delta(before: &PublicParams, after: &PublicParams, asset: &[u8; 20]) -> I256 {
+    let delta = after.supply(asset) as i128 - before.supply(asset) as i128;
+    I256::try_from(delta).expect("a u64 difference fits in 256 bits")
+}
+
+/// The value of `slot`, zero for a slot of a shard that was not open yet.
+fn value(values: &[Scalar], slot: usize) -> Scalar {
+    values.get(slot).copied().unwrap_or(Scalar::zero())
+}
+
+impl Writes {
+    /// Lists the ciphertexts of `slots` and the pending transfers of `pending` as written, from
+    /// what they held in `before` to what they hold in `after`, with the accounts the slots belong
+    /// to and the deltas of the shards of `slots`. Pending transfers are not committed by any phi,
+    /// so they add no delta, and are listed under the account they were credited to beforehand. A
+    /// slot is listed even if the action left it as it was, so the action is still only applied
+    /// while it holds the same value.
+    pub fn new(before: &PublicParams, after: &PublicParams, slots: &[usize], pending: &[usize]) -> Writes {
+        let mut writes = Writes::default();
+        let mut deltas: Vec<(usize, G1Affine)> = Vec::new();
+        let mut add = |shard: usize, delta: G1Affine| match deltas.iter_mut().find(|(s, _)| *s == shard) {
+            Some((_, d)) => *d = d.add_affine(&delta),
+            None => deltas.push((shard, delta)),
+        };
+        for slot in slots.iter().copied() {
+            let (shard, index) = after.locate(slot);
+            let (old_t, old_v) = (value(&before.t, slot), value(&before.v, slot));
+            writes.slots.push(SlotWrite {
+                shard: shard as u64,
+                index: index as u64,
+                old_t: old_t.to_bytes().into(),
+                old_v: old_v.to_bytes().into(),
+                new_t: after.t[slot].to_bytes().into(),
+                new_v: after.v[slot].to_bytes().into(),
+                old_account: before.account(slot).into(),
+                new_account: after.account(slot).into(),
+            });
+            add(shard, G1Affine::from(after.g1_lagrange_basis[index] * (after.v[slot] - old_v)));
+        }
+        for slot in pending.iter().copied() {
+            let (shard, index) = after.locate(slot);
+            writes.pending.push(PendingWrite {
+                shard: shard as u64,
+                index: index as u64,
+                account: before.account(slot).into(),
+                old_t: value(&before.pending_t, slot).to_bytes().into(),
+                old_v: value(&before.pending_v, slot).to_bytes().into(),
+                new_t: after.pending_t[slot].to_bytes().into(),
+                new_v: after.pending_v[slot].to_bytes().into(),
+            });
+        }
+        writes.shards = deltas
+            .into_iter()
+            .map(|(shard, delta)| ShardDelta {
+                shard: shard as u64,
+                delta: g1_to_evm(&delta).into(),
+            })
+            .collect();
+        writes
+    }
+}
@@ -9,8 +9,10 @@
 sp1_zkvm::entrypoint!(main);
 
 use alloy_sol_types::SolType;
-use sp1_bls12_381::G1Affine;
-use state_machine_lib::{PublicParams, PublicValuesDeposit, PublicValuesWithdraw, PublicValuesSend, PublicValuesRotate, Action, deposit, send, withdraw, rotate};
+use sp1_bls12_381::{G1Affine, Scalar};
+use state_machine_lib::public_values::g1_to_evm;
+use state_machine_lib::writes::supply_delta;
+use state_machine_lib::{PublicParams, ElGamal, Note, PublicValuesDeposit, PublicValuesWithdraw, PublicValuesSend, PublicValuesSendMany, PublicValuesRotate, PublicValuesClose, PublicValuesAttest, PublicValuesRollover, SendManyReceiver, Writes, Action, account_key, deposit, send, send_many, withdraw, rotate, rekey, close, attest, rollover};
 
 pub fn main() {
     // Read an input to the program.
@@ -18,47 +20,195 @@ pub fn main() {
     // Behind the scenes, this compiles down to a custom system call which handles reading inputs
     // from the prover.
     let action = sp1_zkvm::io::read::<Action>();
-    let phi = sp1_zkvm::io::read::<G1Affine>();
+    let phi = sp1_zkvm::io::read::<Vec<G1Affine>>();
     let mut pp = sp1_zkvm::io::read::<PublicParams>();
 
-    let bytes = match action {
+    let bytes = match &action {
         Action::Deposit(deposit_inputs) => {
-            // Handle deposit
-            let next_phi = deposit(&mut pp, deposit_inputs.pkey, deposit_inputs.random, deposit_inputs.amount, phi).unwrap();
+            // Handle deposit, topping up the account if the key already has one in the asset
+            let key = account_key(&deposit_inputs.pkey, &deposit_inputs.asset);
+            let top_up = pp.index_of.contains_key(&key);
+            let old_free_list_hash = pp.free_list_hash();
+            let before = pp.clone();
+            deposit(&mut pp, deposit_inputs.pkey, deposit_inputs.asset, deposit_inputs.random, deposit_inputs.amount, &phi).unwrap();
+            let idx = pp.index_of[&key];
+            let (shard, index) = pp.locate(idx);
+            // A top-up is credited to the pending transfers, leaving the ciphertext as it is
+            let writes = if top_up { Writes::new(&before, &pp, &[], &[idx]) } else { Writes::new(&before, &pp, &[idx], &[]) };
             PublicValuesDeposit::abi_encode(&PublicValuesDeposit {
-                old_phi: phi.to_compressed().into(),
-                next_phi: next_phi.to_compressed().into(),
+                slots: writes.slots,
+                pending: writes.pending,
+                shards: writes.shards,
+                asset: alloy_sol_types::private::Address::from(deposit_inputs.asset),
                 amount: alloy_sol_types::private::u256(deposit_inputs.amount),
+                supply_delta: supply_delta(&before, &pp, &deposit_inputs.asset),
                 pkey: deposit_inputs.pkey.to_bytes().into(),
-                t: pp.t[pp.idx - 1].to_bytes().into()
+                top_up,
+                shard: shard as u64,
+                index: index as u64,
+                old_free_list_hash: old_free_list_hash.into(),
+                new_free_list_hash: pp.free_list_hash().into()
             })
         },
         Action::Send(send_inputs) => {
-            // Handle send
-            let next_phi = send(&mut pp, send_inputs.skey_sender, send_inputs.pkey_receiver, send_inputs.balance_sender, send_inputs.amount, phi).unwrap();
+            // Handle send, between the two accounts in the same asset. The spend key behind the
+            // view key must have signed it
+            let skey_sender = send_inputs.auth_sender.verify(&pp, &send_inputs.asset, &action).unwrap();
+            assert!(send_inputs.fee == 0 || send_inputs.relayer != [0u8; 20], "A fee is paid to the zero address");
+            let before = pp.clone();
+            send(&mut pp, skey_sender, send_inputs.pkey_receiver, send_inputs.asset, send_inputs.balance_sender, send_inputs.amount, send_inputs.fee, send_inputs.transfer_random, send_inputs.debit_random, &phi).unwrap();
+            let pkey_sender = ElGamal::new(pp.g).from_skey(skey_sender);
+            let idx_sender = pp.index_of[&account_key(&pkey_sender, &send_inputs.asset)];
+            let idx_receiver = pp.index_of[&account_key(&send_inputs.pkey_receiver, &send_inputs.asset)];
+            let writes = Writes::new(&before, &pp, &[idx_sender], &[idx_receiver]);
+            let note = Note::encrypt(pp.g, send_inputs.pkey_receiver, send_inputs.amount, &send_inputs.memo, send_inputs.note_random).unwrap();
+            // The auditor gets the amount alone, under the same ephemeral key
+            let escrow = pp.auditor.map(|auditor| Note::encrypt(pp.g, auditor, send_inputs.amount, b"", send_inputs.note_random).unwrap());
             PublicValuesSend::abi_encode(&PublicValuesSend {
-                old_phi: phi.to_compressed().into(),
-                next_phi: next_phi.to_compressed().into(),
+                slots: writes.slots,
+                pending: writes.pending,
+                shards: writes.shards,
+                asset: alloy_sol_types::private::Address::from(send_inputs.asset),
+                pkey_sender: pkey_sender.to_bytes().into(),
+                pkey_receiver: send_inputs.pkey_receiver.to_bytes().into(),
+                fee: alloy_sol_types::private::u256(send_inputs.fee),
+                supply_delta: supply_delta(&before, &pp, &send_inputs.asset),
+                relayer: alloy_sol_types::private::Address::from(send_inputs.relayer),
+                note_ephemeral: note.ephemeral.to_bytes().into(),
+                note_ciphertext: note.ciphertext.into(),
+                note_tag: note.tag.into(),
+                auditor: pp.auditor.unwrap_or(Scalar::zero()).to_bytes().into(),
+                auditor_ciphertext: escrow.as_ref().map(|e| e.ciphertext.clone()).unwrap_or_default().into(),
+                auditor_tag: escrow.map(|e| e.tag).unwrap_or([0u8; 32]).into(),
+            })
+        },
+        Action::SendMany(send_inputs) => {
+            // Handle send to many, debiting the sender once for all receivers
+            let skey_sender = send_inputs.auth_sender.verify(&pp, &send_inputs.asset, &action).unwrap();
+            let receivers: Vec<_> = send_inputs.receivers.iter().map(|r| (r.pkey, r.amount, r.transfer_random)).collect();
+            let before = pp.clone();
+            send_many(&mut pp, skey_sender, send_inputs.asset, &receivers, send_inputs.balance_sender, send_inputs.debit_random, &phi).unwrap();
+            let pkey_sender = ElGamal::new(pp.g).from_skey(skey_sender);
+            let idx_sender = pp.index_of[&account_key(&pkey_sender, &send_inputs.asset)];
+            let idx_receivers: Vec<usize> = send_inputs.receivers.iter().map(|r| pp.index_of[&account_key(&r.pkey, &send_inputs.asset)]).collect();
+            let writes = Writes::new(&before, &pp, &[idx_sender], &idx_receivers);
+            let receivers = send_inputs.receivers.iter().map(|r| {
+                let note = Note::encrypt(pp.g, r.pkey, r.amount, &r.memo, r.note_random).unwrap();
+                let escrow = pp.auditor.map(|auditor| Note::encrypt(pp.g, auditor, r.amount, b"", r.note_random).unwrap());
+                SendManyReceiver {
+                    pkey: r.pkey.to_bytes().into(),
+                    note_ephemeral: note.ephemeral.to_bytes().into(),
+                    note_ciphertext: note.ciphertext.into(),
+                    note_tag: note.tag.into(),
+                    auditor_ciphertext: escrow.as_ref().map(|e| e.ciphertext.clone()).unwrap_or_default().into(),
+                    auditor_tag: escrow.map(|e| e.tag).unwrap_or([0u8; 32]).into(),
+                }
+            }).collect();
+            PublicValuesSendMany::abi_encode(&PublicValuesSendMany {
+                slots: writes.slots,
+                pending: writes.pending,
+                shards: writes.shards,
+                asset: alloy_sol_types::private::Address::from(send_inputs.asset),
+                pkey_sender: pkey_sender.to_bytes().into(),
+                auditor: pp.auditor.unwrap_or(Scalar::zero()).to_bytes().into(),
+                receivers,
             })
         },
         Action::Withdraw(withdraw_inputs) => {
-            // Handle withdraw
-            let next_phi = withdraw(&mut pp, withdraw_inputs.skey, withdraw_inputs.random, withdraw_inputs.balance, withdraw_inputs.amount, phi, withdraw_inputs.recipient).unwrap();
+            // Handle withdraw, paying the relayer fee out of the same balance
+            let skey = withdraw_inputs.auth.verify(&pp, &withdraw_inputs.asset, &action).unwrap();
+            assert!(withdraw_inputs.fee == 0 || withdraw_inputs.relayer != [0u8; 20], "A fee is paid to the zero address");
+            let before = pp.clone();
+            withdraw(&mut pp, skey, withdraw_inputs.asset, withdraw_inputs.balance, withdraw_inputs.amount, withdraw_inputs.fee, withdraw_inputs.debit_random, &phi, withdraw_inputs.recipient).unwrap();
+            let pkey = ElGamal::new(pp.g).from_skey(skey);
+            let writes = Writes::new(&before, &pp, &[pp.index_of[&account_key(&pkey, &withdraw_inputs.asset)]], &[]);
             PublicValuesWithdraw::abi_encode(&PublicValuesWithdraw {
-                old_phi: phi.to_compressed().into(),
-                next_phi: next_phi.to_compressed().into(),
+                slots: writes.slots,
+                pending: writes.pending,
+                shards: writes.shards,
+                asset: alloy_sol_types::private::Address::from(withdraw_inputs.asset),
                 amount: alloy_sol_types::private::u256(withdraw_inputs.amount),
-                recipient: alloy_sol_types::private::Address::from(withdraw_inputs.recipient)
+                recipient: alloy_sol_types::private::Address::from(withdraw_inputs.recipient),
+                pkey: pkey.to_bytes().into(),
+                fee: alloy_sol_types::private::u256(withdraw_inputs.fee),
+                supply_delta: supply_delta(&before, &pp, &withdraw_inputs.asset),
+                relayer: alloy_sol_types::private::Address::from(withdraw_inputs.relayer)
             })
         },
         Action::Rotate(rotate_inputs)=> {
-            // Handle rotate
-            let (next_phi, idx) = rotate(&mut pp, rotate_inputs.skey, rotate_inputs.new_additive, phi).unwrap();
+            // Handle rotate, re-encrypting under a new key pair when asked to
+            let skey = rotate_inputs.auth.verify(&pp, &rotate_inputs.asset, &action).unwrap();
+            let pkey = ElGamal::new(pp.g).from_skey(skey);
+            let before = pp.clone();
+            let rekeyed = rotate_inputs.rekey.is_some();
+            let (_, idx) = match &rotate_inputs.rekey {
+                Some(r) => rekey(&mut pp, skey, rotate_inputs.asset, r.balance, r.new_pkey, r.new_random, &phi).unwrap(),
+                None => rotate(&mut pp, skey, rotate_inputs.asset, rotate_inputs.new_additive, &phi).unwrap(),
+            };
+            // A rekey lists the empty pending transfers too, so a transfer in to the old key landing
+            // first stops it
+            let pending: &[usize] = if rekeyed { &[idx] } else { &[] };
+            let writes = Writes::new(&before, &pp, &[idx], pending);
             PublicValuesRotate::abi_encode(&PublicValuesRotate {
-                old_phi: phi.to_compressed().into(),
-                next_phi: next_phi.to_compressed().into(),
+                slots: writes.slots,
+                pending: writes.pending,
+                shards: writes.shards,
+                asset: alloy_sol_types::private::Address::from(rotate_inputs.asset),
+                pkey: pkey.to_bytes().into(),
+                new_pkey: pp.pkeys[idx].to_bytes().into()
+            })
+        },
+        Action::Close(close_inputs) => {
+            // Handle close, freeing the slot for a later deposit
+            let skey = close_inputs.auth.verify(&pp, &close_inputs.asset, &action).unwrap();
+            let old_free_list_hash = pp.free_list_hash();
+            let before = pp.clone();
+            let (_, idx) = close(&mut pp, skey, close_inputs.asset, &phi).unwrap();
+            let (shard, index) = pp.locate(idx);
+            // The pending transfers are listed too, so the close is only applied while there are none
+            let writes = Writes::new(&before, &pp, &[idx], &[idx]);
+            PublicValuesClose::abi_encode(&PublicValuesClose {
+                slots: writes.slots,
+                pending: writes.pending,
+                shards: writes.shards,
+                asset: alloy_sol_types::private::Address::from(close_inputs.asset),
+                pkey: ElGamal::new(pp.g).from_skey(skey).to_bytes().into(),
+                shard: shard as u64,
+                index: index as u64,
+                old_free_list_hash: old_free_list_hash.into(),
+                new_free_list_hash: pp.free_list_hash().into()
+            })
+        },
+        Action::Attest(attest_inputs) => {
+            // Handle attest, reading the balance without changing the ledger
+            let skey = attest_inputs.auth.verify(&pp, &attest_inputs.asset, &action).unwrap();
+            let idx = attest(&pp, skey, attest_inputs.asset, attest_inputs.balance, attest_inputs.threshold, &phi).unwrap();
+            let (shard, index) = pp.locate(idx);
+            PublicValuesAttest::abi_encode(&PublicValuesAttest {
+                phi: g1_to_evm(&phi[shard]).into(),
+                shard: shard as u64,
+                index: index as u64,
+                account: pp.account(idx).into(),
+                t: pp.t[idx].to_bytes().into(),
+                asset: alloy_sol_types::private::Address::from(attest_inputs.asset),
+                supply: alloy_sol_types::private::u256(pp.supply(&attest_inputs.asset)),
                 pkey: pp.pkeys[idx].to_bytes().into(),
-                new_t: pp.t[idx].to_bytes().into()
+                threshold: alloy_sol_types::private::u256(attest_inputs.threshold),
+                nonce: attest_inputs.nonce.into()
+            })
+        },
+        Action::Rollover(rollover_inputs) => {
+            // Handle rollover, which any prover can make from the public ledger
+            let shard = rollover_inputs.shard as usize;
+            let before = pp.clone();
+            rollover(&mut pp, shard, &phi).unwrap();
+            let rolled: Vec<usize> = (shard * pp.degree..(shard + 1) * pp.degree).filter(|slot| before.has_pending(*slot)).collect();
+            let writes = Writes::new(&before, &pp, &rolled, &rolled);
+            PublicValuesRollover::abi_encode(&PublicValuesRollover {
+                slots: writes.slots,
+                pending: writes.pending,
+                shards: writes.shards,
+                shard: shard as u64
             })
         },
     };
@@ -0,0 +1,59 @@
+//! Checks balance attestations, proofs that an account holds at least some amount, on behalf of
+//! the counterparty who asked for one. The counterparty picks the nonce and compares the proof
+//! against its own copy of the ledger, so a stale or borrowed attestation does not pass.
+
+use crate::indexer::Ledger;
+use crate::ProofBundle;
+use sp1_bls12_381::Scalar;
+use sp1_sdk::{SP1ProofWithPublicValues, SP1VerifyingKey};
+use state_machine_lib::{replay, ActionKind, DecodedAttest, DecodedPublicValues};
+
+/// What the counterparty asked the account holder to prove.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttestationRequest {
+    pub pkey: Scalar,
+    pub asset: [u8; 20],
+    pub threshold: u64,
+    pub nonce: [u8; 32],
+}
+
+impl AttestationRequest {
+    /// Checks decoded attestation values against the request and the current ledger. A higher
+    /// threshold than asked for is accepted.
+    pub fn check_values(&self, values: &DecodedAttest, ledger: &Ledger) -> Result<(), String> {
+        if values.pkey != self.pkey {
+            return Err("Attestation is for a different public key".to_string());
+        }
+        if values.asset != self.asset {
+            return Err("Attestation is for a different asset".to_string());
+        }
+        if values.nonce != self.nonce {
+            return Err("Attestation answers a different nonce".to_string());
+        }
+        if values.threshold < self.threshold {
+            return Err(format!("Attestation only covers a balance of {}", values.threshold));
+        }
+        // Replaying it changes nothing, but checks the phi, the account and the total supply
+        // against the ledger
+        replay(&mut ledger.pp.clone(), &ledger.phi, &DecodedPublicValues::Attest(values.clone()))
+            .map_err(|e| format!("Attestation is not against the current ledger: {}", e))?;
+        Ok(())
+    }
+
+    /// Checks an attestation bundle against the program key, the request and the ledger, and
+    /// returns the embedded proof, still to be handed to `ProverClient::verify`.
+    pub fn check(
+        &self,
+        bundle: &ProofBundle,
+        vk: &SP1VerifyingKey,
+        ledger: &Ledger,
+    ) -> Result<(SP1ProofWithPublicValues, DecodedAttest), String> {
+        if bundle.action != ActionKind::Attest {
+            return Err(format!("Expected an attestation, got a {:?} proof", bundle.action));
+        }
+        let proof = bundle.check(vk, &ledger.pp)?;
+        let values = DecodedAttest::decode(proof.public_values.as_slice())?;
+        self.check_values(&values, ledger)?;
+        Ok((proof, values))
+    }
+}
@@ -0,0 +1,100 @@
+//! The counterparty's side of a balance attestation. `nonce` picks a fresh nonce to hand to the
+//! account holder, who proves their balance with `wallet attest`; `verify` checks the bundle they
+//! send back against the request and a ledger written by the `indexer` script.
+//!
+//! You can run this script using the following commands:
+//! ```shell
+//! cargo run --release --bin attest -- nonce
+//! cargo run --release --bin attest -- verify --bundle ../proofs/wallet-attest.json --pkey 0x... --threshold 50 --nonce 0x...
+//! ```
+
+use clap::{Parser, Subcommand};
+use sp1_sdk::{include_elf, ProverClient};
+use state_machine_lib::public_values::decode_scalar;
+use state_machine_script::attestation::AttestationRequest;
+use state_machine_script::indexer::{decode_asset, Ledger};
+use state_machine_script::ProofBundle;
+use std::path::PathBuf;
+
+/// The ELF (executable and linkable format) file for the Succinct RISC-V zkVM.
+pub const STATEMACHINE_ELF: &[u8] = include_elf!("state-machine-program");
+
+/// The arguments for the attest command.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct AttestArgs {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a fresh nonce for an attestation request.
+    Nonce,
+    /// Check an attestation bundle.
+    Verify {
+        #[clap(long)]
+        bundle: PathBuf,
+        /// The ledger written by the indexer, which must be current.
+        #[clap(long, default_value = "ledger.json")]
+        ledger: PathBuf,
+        /// The public key of the account holder.
+        #[clap(long)]
+        pkey: String,
+        /// The ERC-20 token of the account, ETH when omitted.
+        #[clap(long, default_value = "")]
+        asset: String,
+        #[clap(long)]
+        threshold: u64,
+        /// The nonce handed to the account holder.
+        #[clap(long)]
+        nonce: String,
+    },
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
+}
+
+fn main() {
+    // Setup the logger.
+    sp1_sdk::utils::setup_logger();
+    dotenv::dotenv().ok();
+
+    // Parse the command line arguments.
+    let args = AttestArgs::parse();
+
+    let (bundle, ledger, pkey, asset, threshold, nonce) = match &args.command {
+        Command::Nonce => {
+            let mut nonce = [0u8; 32];
+            rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut nonce);
+            println!("0x{}", hex::encode(nonce));
+            return;
+        },
+        Command::Verify { bundle, ledger, pkey, asset, threshold, nonce } => (bundle, ledger, pkey, asset, threshold, nonce),
+    };
+
+    let request = AttestationRequest {
+        pkey: hex::decode(pkey.trim_start_matches("0x"))
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| decode_scalar(&bytes))
+            .unwrap_or_else(|e| fail(format!("Invalid public key: {}", e))),
+        asset: decode_asset(asset).unwrap_or_else(|e| fail(e)),
+        threshold: *threshold,
+        nonce: hex::decode(nonce.trim_start_matches("0x"))
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .unwrap_or_else(|| fail("The nonce must be 32 hex bytes".to_string())),
+    };
+    let bundle = ProofBundle::load(bundle).unwrap_or_else(|e| fail(e));
+    let ledger = Ledger::load(ledger).unwrap_or_else(|e| fail(format!("{} (run the indexer first)", e)));
+
+    // Setup the prover client.
+    let client = ProverClient::from_env();
+    let (_, vk) = client.setup(STATEMACHINE_ELF);
+
+    let (proof, values) = request.check(&bundle, &vk, &ledger).unwrap_or_else(|e| fail(e));
+    client.verify(&proof, &vk).unwrap_or_else(|e| fail(format!("Invalid proof: {}", e)));
+    println!("Attestation verified: the account holds at least {} in shard {}", values.threshold, values.shard);
+}
@@ -0,0 +1,119 @@
+//! Prints the amount of every deposit, transfer and withdrawal a `StateMachine` contract accepted.
+//! Deposit and withdrawal amounts are public; transfer amounts are decrypted from the copies
+//! escrowed to the contract's auditor, whose key lives in a wallet keystore.
+//!
+//! You can run this script using the following commands:
+//! ```shell
+//! cargo run --release --bin wallet -- --wallet auditor keygen
+//! cargo run --release --bin auditor -- --wallet auditor --rpc-url http://localhost:8545 --state-machine 0x...
+//! cargo run --release --bin auditor -- --wallet auditor --dump ./transactions.json
+//! ```
+
+use clap::Parser;
+use state_machine_lib::public_values::scalar_to_hex;
+use state_machine_lib::{audit_transfers, DecodedPublicValues};
+use state_machine_script::indexer::{asset_to_hex, decode_call, fetch_transactions, load_dump, IndexedTransaction};
+use state_machine_script::rpc::RpcClient;
+use state_machine_script::wallet::Wallet;
+use std::path::PathBuf;
+
+/// The arguments for the auditor command.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct AuditorArgs {
+    /// The wallet holding the auditor's secret key.
+    #[clap(long, default_value = "auditor")]
+    wallet: PathBuf,
+
+    /// The password of the auditor's keystore, which holds a key that reads every transfer.
+    #[clap(long, env = "WALLET_PASSWORD")]
+    password: String,
+
+    #[clap(long, env = "RPC_URL", conflicts_with = "dump")]
+    rpc_url: Option<String>,
+
+    /// A JSON array of `StateMachine` transactions to read instead of a node.
+    #[clap(long)]
+    dump: Option<PathBuf>,
+
+    /// The address of the `StateMachine` contract, required with `--rpc-url`.
+    #[clap(long)]
+    state_machine: Option<String>,
+
+    /// The block the contract was deployed at.
+    #[clap(long, default_value = "0")]
+    from_block: u64,
+
+    /// Defaults to the latest block.
+    #[clap(long)]
+    to_block: Option<u64>,
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+
+    // Parse the command line arguments.
+    let args = AuditorArgs::parse();
+    let wallet = Wallet::open(&args.wallet, &args.password).unwrap_or_else(|e| fail(e));
+
+    let transactions: Vec<IndexedTransaction> = match (&args.rpc_url, &args.dump) {
+        (Some(url), None) => {
+            let state_machine = args.state_machine.as_deref().unwrap_or_else(|| fail("--state-machine is required with --rpc-url".to_string()));
+            let rpc = RpcClient::new(url);
+            let to_block = match args.to_block {
+                Some(block) => block,
+                None => rpc.block_number().unwrap_or_else(|e| fail(e)),
+            };
+            fetch_transactions(&rpc, state_machine, args.from_block, to_block).unwrap_or_else(|e| fail(e))
+        },
+        (None, Some(path)) => load_dump(path).unwrap_or_else(|e| fail(e)),
+        _ => fail("Pass either --rpc-url or --dump".to_string()),
+    };
+
+    for tx in transactions.iter().filter(|tx| tx.succeeded()) {
+        let label = tx.hash.clone().unwrap_or("transaction".to_string());
+        let values = decode_hex_call(&tx.input).unwrap_or_else(|e| fail(format!("{}: {}", label, e)));
+        match &values {
+            DecodedPublicValues::Deposit(d) => {
+                println!("{} deposit {} {} to {}", label, d.amount, asset_to_hex(&d.asset), scalar_to_hex(&d.pkey));
+            },
+            DecodedPublicValues::Send(_) | DecodedPublicValues::SendMany(_) => {
+                let transfers = audit_transfers(wallet.secrets.skey, [&values]).unwrap_or_else(|e| fail(format!("{}: {}", label, e)));
+                for t in transfers {
+                    println!(
+                        "{} transfer {} {} from {} to {}",
+                        label,
+                        t.amount,
+                        asset_to_hex(&t.asset),
+                        scalar_to_hex(&t.sender),
+                        scalar_to_hex(&t.receiver)
+                    );
+                }
+            },
+            DecodedPublicValues::Withdraw(d) => {
+                println!(
+                    "{} withdraw {} {} from {} to 0x{}",
+                    label,
+                    d.amount,
+                    asset_to_hex(&d.asset),
+                    scalar_to_hex(&d.pkey),
+                    hex::encode(d.recipient)
+                );
+            },
+            DecodedPublicValues::Rotate(_)
+            | DecodedPublicValues::Close(_)
+            | DecodedPublicValues::Attest(_)
+            | DecodedPublicValues::Rollover(_) => {},
+        }
+    }
+}
+
+fn decode_hex_call(input: &str) -> Result<DecodedPublicValues, String> {
+    let input = hex::decode(input.trim_start_matches("0x")).map_err(|e| format!("Invalid hex: {}", e))?;
+    decode_call(&input)
+}
@@ -10,10 +10,11 @@
 //! RUST_LOG=info cargo run --release --bin evm -- --system plonk
 //! ```
 
-use alloy_sol_types::SolType;
 use clap::{Parser, ValueEnum};
-use state_machine_lib::{PublicParams, PublicValuesDeposit, KZG, ElGamal, Action, Deposit, deposit};
-use sp1_bls12_381::Scalar;
+use state_machine_lib::public_values::{g1_to_evm, scalar_to_hex};
+use state_machine_lib::{PublicParams, DecodedDeposit, KZG, ElGamal, Action, Deposit, deposit, ActionKind, NATIVE_ASSET, view_key};
+use state_machine_script::bundle::{bundle_dir, ProofBundle};
+use sp1_bls12_381::{G1Affine, Scalar};
 use serde::{Deserialize, Serialize};
 use sp1_sdk::{
     include_elf, HashableKey, ProverClient, SP1ProofWithPublicValues, SP1Stdin, SP1VerifyingKey,
@@ -59,7 +60,6 @@ struct SP1ProofDepositFixture {
     next_phi: String,
     amount: u64,
     pkey: String,
-    t: String,
     vkey: String,
     public_values: String,
     proof: String,
@@ -82,11 +82,11 @@ fn main() {
     let el_gamal = ElGamal::new(pp.g);
     let kzg = KZG::new(pp.g1_lagrange_basis.clone());
     let v = vec![Scalar::zero(); pp.degree];
-    let mut phi = kzg.commit(v).unwrap();
+    let mut phi = vec![kzg.commit(v).unwrap()];
 
-    let sk_a = [1u64, 2, 3, 4];
+    let sk_a = view_key([1u64, 2, 3, 4]);
     let pk_a = el_gamal.from_skey(sk_a);
-    let sk_b = [5u64, 6, 7, 8];
+    let sk_b = view_key([5u64, 6, 7, 8]);
     let pk_b = el_gamal.from_skey(sk_b);
     println!("User A's public key: {:?}", pk_a);
     println!("User B's public key: {:?}", pk_b);
@@ -98,13 +98,14 @@ fn main() {
     println!("User A deposits: {:?} ETH", m_a);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_a, r_a, m_a, phi).unwrap();
+    phi = deposit(&mut pp, pk_a, NATIVE_ASSET, r_a, m_a, &phi).unwrap();
 
     println!("User B deposits: {:?} ETH", m_b);
     println!("Update state...");
 
     let deposit_inputs = Deposit {
         pkey: pk_b,
+        asset: NATIVE_ASSET,
         random: r_b,
         amount: m_b,
     };
@@ -126,32 +127,31 @@ fn main() {
     }
     .expect("failed to generate proof");
 
-    create_proof_fixture(&proof, &vk, args.system);
+    let bundle = ProofBundle::new(ActionKind::Deposit, &proof, &vk, &pp);
+    let bundle_path = bundle_dir().join(format!("{:?}-deposit-b.json", args.system).to_lowercase());
+    bundle.save(&bundle_path).expect("failed to save proof bundle");
+    println!("Proof bundle: {}", bundle_path.display());
+
+    create_proof_fixture(&proof, &vk, &phi[0], args.system);
 }
 
 /// Create a fixture for the given proof.
 fn create_proof_fixture(
     proof: &SP1ProofWithPublicValues,
     vk: &SP1VerifyingKey,
+    old_phi: &G1Affine,
     system: ProofSystem,
 ) {
     // Deserialize the public values.
     let bytes = proof.public_values.as_slice();
     // Read the output.
-    let decoded = PublicValuesDeposit::abi_decode(bytes, true).unwrap();
-    let PublicValuesDeposit {
-        old_phi, 
-        next_phi, 
-        amount, 
-        pkey, 
-        t } = decoded;
+    let decoded = DecodedDeposit::decode(bytes).unwrap();
     // Create the testing fixture so we can test things end-to-end.
     let fixture = SP1ProofDepositFixture {
-        old_phi: format!("0x{}", hex::encode(old_phi)),
-        next_phi: format!("0x{}", hex::encode(next_phi)),
-        pkey: format!("0x{}", hex::encode(pkey)),
-        t: format!("0x{}", hex::encode(t)),
-        amount: amount.into_limbs()[0],
+        old_phi: format!("0x{}", hex::encode(g1_to_evm(old_phi))),
+        next_phi: format!("0x{}", hex::encode(g1_to_evm(&decoded.writes.apply(&[*old_phi])[0]))),
+        pkey: scalar_to_hex(&decoded.pkey),
+        amount: decoded.amount,
         vkey: vk.bytes32().to_string(),
         public_values: format!("0x{}", hex::encode(bytes)),
         proof: format!("0x{}", hex::encode(proof.bytes())),
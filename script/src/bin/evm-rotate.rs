@@ -10,11 +10,12 @@
 //! RUST_LOG=info cargo run --release --bin evm -- --system plonk
 //! ```
 
-use alloy_sol_types::SolType;
 use hex::decode;
 use clap::{Parser, ValueEnum};
-use state_machine_lib::{deposit, send, withdraw, PublicParams, PublicValuesRotate, KZG, ElGamal, Action, Rotate};
-use sp1_bls12_381::Scalar;
+use state_machine_lib::public_values::{g1_to_evm, scalar_to_hex};
+use state_machine_lib::{deposit, send, withdraw, PublicParams, DecodedRotate, KZG, ElGamal, Action, Rotate, ActionKind, NATIVE_ASSET, view_key, Authorization};
+use state_machine_script::bundle::{bundle_dir, ProofBundle};
+use sp1_bls12_381::{G1Affine, Scalar};
 use serde::{Deserialize, Serialize};
 use sp1_sdk::{
     include_elf, HashableKey, ProverClient, SP1ProofWithPublicValues, SP1Stdin, SP1VerifyingKey,
@@ -58,7 +59,6 @@ struct SP1FibonacciProofFixture {
 struct SP1ProofRotateFixture {
     old_phi: String,
     next_phi: String,
-    new_t: String,
     pkey: String,
     vkey: String,
     public_values: String,
@@ -82,11 +82,12 @@ fn main() {
     let el_gamal = ElGamal::new(pp.g);
     let kzg = KZG::new(pp.g1_lagrange_basis.clone());
     let v = vec![Scalar::zero(); pp.degree];
-    let mut phi = kzg.commit(v).unwrap();
+    let mut phi = vec![kzg.commit(v).unwrap()];
 
-    let sk_a = [1u64, 2, 3, 4];
+    let spend_a = [1u64, 2, 3, 4];
+    let sk_a = view_key(spend_a);
     let pk_a = el_gamal.from_skey(sk_a);
-    let sk_b = [5u64, 6, 7, 8];
+    let sk_b = view_key([5u64, 6, 7, 8]);
     let pk_b = el_gamal.from_skey(sk_b);
     println!("User A's public key: {:?}", pk_a);
     println!("User B's public key: {:?}", pk_b);
@@ -98,20 +99,21 @@ fn main() {
     println!("User A deposits: {:?} ETH", m_a);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_a, r_a, m_a, phi).unwrap();
+    phi = deposit(&mut pp, pk_a, NATIVE_ASSET, r_a, m_a, &phi).unwrap();
 
     println!("User B deposits: {:?} ETH", m_b);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_b, r_b, m_b, phi).unwrap();
+    phi = deposit(&mut pp, pk_b, NATIVE_ASSET, r_b, m_b, &phi).unwrap();
 
     let amount = 30u64;
     println!("User B sends {:?} ETH to User A", amount);
     println!("Update state...");
 
-    phi = send(&mut pp, sk_b, pk_a, m_b, amount, phi).unwrap();
+    phi = send(&mut pp, sk_b, pk_a, NATIVE_ASSET, m_b, amount, 0, [0x4444u64, 0, 0, 0], [0x5555u64, 0, 0, 0], &phi).unwrap();
     m_b -= amount;
-    m_a += amount;
+    // User A's 30 ETH stay pending, and the withdraw only touches the ciphertext of A's slot, so
+    // it is proven against the 100 ETH deposited and lands whether or not the send did
 
     let withdraw_amount = 10u64;
 
@@ -121,7 +123,7 @@ fn main() {
     // private key: 0xc0cf034c2039fbb095aad1cd7dfd8854eddc5fcfed04e009520049107022b22b
     let recipient = "65f697a02d756Cf4BC3465c1cC60dB3a4AF19521"; // TODO: need address
     let recipient: [u8; 20] = decode(recipient).unwrap().try_into().unwrap();
-    phi = withdraw(&mut pp, sk_a, r_a, m_a, withdraw_amount, phi, recipient).unwrap();
+    phi = withdraw(&mut pp, sk_a, NATIVE_ASSET, m_a, withdraw_amount, 0, [0x6666u64, 0, 0, 0], &phi, recipient).unwrap();
     m_a -= withdraw_amount;
 
     let add_additive = [1u64, 0, 0, 0];
@@ -130,11 +132,14 @@ fn main() {
     println!("Update state...");
 
     let rotate_inputs = Rotate {
-        skey: sk_a,
+        auth: Authorization::default(),
+        asset: NATIVE_ASSET,
         new_additive: add_additive,
+        rekey: None,
     };
 
-    let action = Action::Rotate(rotate_inputs);
+    let mut action = Action::Rotate(rotate_inputs);
+    action.sign(spend_a, &pp).unwrap();
 
     // Setup the inputs.
     let mut stdin = SP1Stdin::new();
@@ -157,30 +162,30 @@ fn main() {
     client.verify(&proof, &vk).expect("failed to verify proof");
     println!("Proof verification time: {:?}", start.elapsed());
 
-    create_proof_fixture(&proof, &vk, args.system);
+    let bundle = ProofBundle::new(ActionKind::Rotate, &proof, &vk, &pp);
+    let bundle_path = bundle_dir().join(format!("{:?}-rotate.json", args.system).to_lowercase());
+    bundle.save(&bundle_path).expect("failed to save proof bundle");
+    println!("Proof bundle: {}", bundle_path.display());
+
+    create_proof_fixture(&proof, &vk, &phi[0], args.system);
 }
 
 /// Create a fixture for the given proof.
 fn create_proof_fixture(
     proof: &SP1ProofWithPublicValues,
     vk: &SP1VerifyingKey,
+    old_phi: &G1Affine,
     system: ProofSystem,
 ) {
     // Deserialize the public values.
     let bytes = proof.public_values.as_slice();
     // Read the output.
-    let decoded = PublicValuesRotate::abi_decode(bytes, true).unwrap();
-    let PublicValuesRotate {
-        old_phi, 
-        next_phi,
-        pkey,
-        new_t} = decoded;
+    let decoded = DecodedRotate::decode(bytes).unwrap();
     // Create the testing fixture so we can test things end-to-end.
     let fixture = SP1ProofRotateFixture {
-        old_phi: format!("0x{}", hex::encode(old_phi)),
-        next_phi: format!("0x{}", hex::encode(next_phi)),
-        pkey: format!("0x{}", hex::encode(pkey)),
-        new_t: format!("0x{}", hex::encode(new_t)),
+        old_phi: format!("0x{}", hex::encode(g1_to_evm(old_phi))),
+        next_phi: format!("0x{}", hex::encode(g1_to_evm(&decoded.writes.apply(&[*old_phi])[0]))),
+        pkey: scalar_to_hex(&decoded.pkey),
         vkey: vk.bytes32().to_string(),
         public_values: format!("0x{}", hex::encode(bytes)),
         proof: format!("0x{}", hex::encode(proof.bytes())),
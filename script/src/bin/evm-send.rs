@@ -10,10 +10,11 @@
 //! RUST_LOG=info cargo run --release --bin evm -- --system plonk
 //! ```
 
-use alloy_sol_types::SolType;
 use clap::{Parser, ValueEnum};
-use state_machine_lib::{deposit, PublicParams, PublicValuesSend, KZG, ElGamal, Action, Send};
-use sp1_bls12_381::Scalar;
+use state_machine_lib::public_values::g1_to_evm;
+use state_machine_lib::{deposit, PublicParams, DecodedSend, KZG, ElGamal, Action, Send, ActionKind, NATIVE_ASSET, view_key, Authorization};
+use state_machine_script::bundle::{bundle_dir, ProofBundle};
+use sp1_bls12_381::{G1Affine, Scalar};
 use serde::{Deserialize, Serialize};
 use sp1_sdk::{
     include_elf, HashableKey, ProverClient, SP1ProofWithPublicValues, SP1Stdin, SP1VerifyingKey,
@@ -79,11 +80,12 @@ fn main() {
     let el_gamal = ElGamal::new(pp.g);
     let kzg = KZG::new(pp.g1_lagrange_basis.clone());
     let v = vec![Scalar::zero(); pp.degree];
-    let mut phi = kzg.commit(v).unwrap();
+    let mut phi = vec![kzg.commit(v).unwrap()];
 
-    let sk_a = [1u64, 2, 3, 4];
+    let sk_a = view_key([1u64, 2, 3, 4]);
     let pk_a = el_gamal.from_skey(sk_a);
-    let sk_b = [5u64, 6, 7, 8];
+    let spend_b = [5u64, 6, 7, 8];
+    let sk_b = view_key(spend_b);
     let pk_b = el_gamal.from_skey(sk_b);
     println!("User A's public key: {:?}", pk_a);
     println!("User B's public key: {:?}", pk_b);
@@ -95,12 +97,12 @@ fn main() {
     println!("User A deposits: {:?} ETH", m_a);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_a, r_a, m_a, phi).unwrap();
+    phi = deposit(&mut pp, pk_a, NATIVE_ASSET, r_a, m_a, &phi).unwrap();
 
     println!("User B deposits: {:?} ETH", m_b);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_b, r_b, m_b, phi).unwrap();
+    phi = deposit(&mut pp, pk_b, NATIVE_ASSET, r_b, m_b, &phi).unwrap();
 
     let amount = 30u64;
     println!("User B sends {:?} ETH to User A", amount);
@@ -110,10 +112,18 @@ fn main() {
         balance_sender: m_b,
         amount,
         pkey_receiver: pk_a,
-        skey_sender: sk_b,
+        asset: NATIVE_ASSET,
+        fee: 0,
+        relayer: [0u8; 20],
+        auth_sender: Authorization::default(),
+        note_random: [0x3333u64, 0, 0, 0], // TODO: need random
+        transfer_random: [0x4444u64, 0, 0, 0], // TODO: need random
+        debit_random: [0x5555u64, 0, 0, 0], // TODO: need random
+        memo: b"rent".to_vec(),
     };
 
-    let action = Action::Send(send_inputs);
+    let mut action = Action::Send(send_inputs);
+    action.sign(spend_b, &pp).unwrap();
 
     // Setup the inputs.
     let mut stdin = SP1Stdin::new();
@@ -136,26 +146,29 @@ fn main() {
     client.verify(&proof, &vk).expect("failed to verify proof");
     println!("Proof verification time: {:?}", start.elapsed());
 
-    create_proof_fixture(&proof, &vk, args.system);
+    let bundle = ProofBundle::new(ActionKind::Send, &proof, &vk, &pp);
+    let bundle_path = bundle_dir().join(format!("{:?}-send.json", args.system).to_lowercase());
+    bundle.save(&bundle_path).expect("failed to save proof bundle");
+    println!("Proof bundle: {}", bundle_path.display());
+
+    create_proof_fixture(&proof, &vk, &phi[0], args.system);
 }
 
 /// Create a fixture for the given proof.
 fn create_proof_fixture(
     proof: &SP1ProofWithPublicValues,
     vk: &SP1VerifyingKey,
+    old_phi: &G1Affine,
     system: ProofSystem,
 ) {
     // Deserialize the public values.
     let bytes = proof.public_values.as_slice();
     // Read the output.
-    let decoded = PublicValuesSend::abi_decode(bytes, true).unwrap();
-    let PublicValuesSend {
-        old_phi, 
-        next_phi} = decoded;
+    let decoded = DecodedSend::decode(bytes).unwrap();
     // Create the testing fixture so we can test things end-to-end.
     let fixture = SP1ProofSendFixture {
-        old_phi: format!("0x{}", hex::encode(old_phi)),
-        next_phi: format!("0x{}", hex::encode(next_phi)),
+        old_phi: format!("0x{}", hex::encode(g1_to_evm(old_phi))),
+        next_phi: format!("0x{}", hex::encode(g1_to_evm(&decoded.writes.apply(&[*old_phi])[0]))),
         vkey: vk.bytes32().to_string(),
         public_values: format!("0x{}", hex::encode(bytes)),
         proof: format!("0x{}", hex::encode(proof.bytes())),
@@ -10,11 +10,12 @@
 //! RUST_LOG=info cargo run --release --bin evm -- --system plonk
 //! ```
 
-use alloy_sol_types::SolType;
 use hex::decode;
 use clap::{Parser, ValueEnum};
-use state_machine_lib::{deposit, send, PublicParams, PublicValuesWithdraw, KZG, ElGamal, Action, Withdraw};
-use sp1_bls12_381::Scalar;
+use state_machine_lib::public_values::g1_to_evm;
+use state_machine_lib::{deposit, send, PublicParams, DecodedWithdraw, KZG, ElGamal, Action, Withdraw, ActionKind, NATIVE_ASSET, view_key, Authorization};
+use state_machine_script::bundle::{bundle_dir, ProofBundle};
+use sp1_bls12_381::{G1Affine, Scalar};
 use serde::{Deserialize, Serialize};
 use sp1_sdk::{
     include_elf, HashableKey, ProverClient, SP1ProofWithPublicValues, SP1Stdin, SP1VerifyingKey,
@@ -82,36 +83,38 @@ fn main() {
     let el_gamal = ElGamal::new(pp.g);
     let kzg = KZG::new(pp.g1_lagrange_basis.clone());
     let v = vec![Scalar::zero(); pp.degree];
-    let mut phi = kzg.commit(v).unwrap();
+    let mut phi = vec![kzg.commit(v).unwrap()];
 
-    let sk_a = [1u64, 2, 3, 4];
+    let spend_a = [1u64, 2, 3, 4];
+    let sk_a = view_key(spend_a);
     let pk_a = el_gamal.from_skey(sk_a);
-    let sk_b = [5u64, 6, 7, 8];
+    let sk_b = view_key([5u64, 6, 7, 8]);
     let pk_b = el_gamal.from_skey(sk_b);
     println!("User A's public key: {:?}", pk_a);
     println!("User B's public key: {:?}", pk_b);
     println!("User B's secret key: {:?}", sk_b);
 
-    let (mut m_a, mut m_b) = (100u64, 200u64);    
+    let (m_a, mut m_b) = (100u64, 200u64);    
     let (r_a, r_b) = ([0x1111u64, 0, 0, 0], [0x2222u64, 0, 0, 0]); // TODO: need random
     println!("User A generates random number r = {:?}", r_a);
     println!("User A deposits: {:?} ETH", m_a);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_a, r_a, m_a, phi).unwrap();
+    phi = deposit(&mut pp, pk_a, NATIVE_ASSET, r_a, m_a, &phi).unwrap();
 
     println!("User B deposits: {:?} ETH", m_b);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_b, r_b, m_b, phi).unwrap();
+    phi = deposit(&mut pp, pk_b, NATIVE_ASSET, r_b, m_b, &phi).unwrap();
 
     let amount = 30u64;
     println!("User B sends {:?} ETH to User A", amount);
     println!("Update state...");
 
-    phi = send(&mut pp, sk_b, pk_a, m_b, amount, phi).unwrap();
+    phi = send(&mut pp, sk_b, pk_a, NATIVE_ASSET, m_b, amount, 0, [0x4444u64, 0, 0, 0], [0x5555u64, 0, 0, 0], &phi).unwrap();
     m_b -= amount;
-    m_a += amount;
+    // User A's 30 ETH stay pending, and the withdraw only touches the ciphertext of A's slot, so
+    // it is proven against the 100 ETH deposited and lands whether or not the send did
 
     let withdraw_amount = 10u64;
 
@@ -124,12 +127,16 @@ fn main() {
     let withdraw_inputs = Withdraw {
         balance: m_a,
         amount: withdraw_amount,
-        random: r_a,
-        skey: sk_a,
+        auth: Authorization::default(),
+        asset: NATIVE_ASSET,
         recipient,
+        fee: 0,
+        relayer: [0u8; 20],
+        debit_random: [0x6666u64, 0, 0, 0], // TODO: need random
     };
 
-    let action = Action::Withdraw(withdraw_inputs);
+    let mut action = Action::Withdraw(withdraw_inputs);
+    action.sign(spend_a, &pp).unwrap();
 
     // Setup the inputs.
     let mut stdin = SP1Stdin::new();
@@ -152,30 +159,31 @@ fn main() {
     client.verify(&proof, &vk).expect("failed to verify proof");
     println!("Proof verification time: {:?}", start.elapsed());
 
-    create_proof_fixture(&proof, &vk, args.system);
+    let bundle = ProofBundle::new(ActionKind::Withdraw, &proof, &vk, &pp);
+    let bundle_path = bundle_dir().join(format!("{:?}-withdraw.json", args.system).to_lowercase());
+    bundle.save(&bundle_path).expect("failed to save proof bundle");
+    println!("Proof bundle: {}", bundle_path.display());
+
+    create_proof_fixture(&proof, &vk, &phi[0], args.system);
 }
 
 /// Create a fixture for the given proof.
 fn create_proof_fixture(
     proof: &SP1ProofWithPublicValues,
     vk: &SP1VerifyingKey,
+    old_phi: &G1Affine,
     system: ProofSystem,
 ) {
     // Deserialize the public values.
     let bytes = proof.public_values.as_slice();
     // Read the output.
-    let decoded = PublicValuesWithdraw::abi_decode(bytes, true).unwrap();
-    let PublicValuesWithdraw {
-        old_phi, 
-        next_phi, 
-        amount, 
-        recipient} = decoded;
+    let decoded = DecodedWithdraw::decode(bytes).unwrap();
     // Create the testing fixture so we can test things end-to-end.
     let fixture = SP1ProofWithdrawFixture {
-        old_phi: format!("0x{}", hex::encode(old_phi)),
-        next_phi: format!("0x{}", hex::encode(next_phi)),
-        amount: amount.into_limbs()[0],
-        recipient: format!("0x{}", hex::encode(recipient)),
+        old_phi: format!("0x{}", hex::encode(g1_to_evm(old_phi))),
+        next_phi: format!("0x{}", hex::encode(g1_to_evm(&decoded.writes.apply(&[*old_phi])[0]))),
+        amount: decoded.amount,
+        recipient: format!("0x{}", hex::encode(decoded.recipient)),
         vkey: vk.bytes32().to_string(),
         public_values: format!("0x{}", hex::encode(bytes)),
         proof: format!("0x{}", hex::encode(proof.bytes())),
@@ -0,0 +1,143 @@
+//! Rebuilds the ledger behind a deployed `StateMachine` contract by replaying the transactions it
+//! accepted, and writes the ciphertexts and key registry to a JSON snapshot.
+//!
+//! You can run this script using the following command:
+//! ```shell
+//! cargo run --release --bin indexer -- --rpc-url http://localhost:8545 --state-machine 0x... --out ledger.json
+//! ```
+//! or, from a local dump of the contract's transactions:
+//! ```shell
+//! cargo run --release --bin indexer -- --dump ./transactions.json --out ledger.json
+//! ```
+//! If `--out` already exists, indexing resumes from the block after the one it was last updated at.
+//! With `--rpc-url`, the replayed total supply of every asset is checked against the contract's
+//! count and holdings, and the script exits with an error if they disagree.
+
+use clap::Parser;
+use state_machine_lib::public_values::{decode_scalar, g1_to_hex};
+use alloy_primitives::U256;
+use state_machine_script::indexer::{asset_to_hex, fetch_supply, fetch_transactions, load_dump, IndexedTransaction, Ledger};
+use state_machine_script::rpc::RpcClient;
+use std::path::PathBuf;
+
+/// The arguments for the indexer command.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct IndexerArgs {
+    #[clap(long, env = "RPC_URL", conflicts_with = "dump")]
+    rpc_url: Option<String>,
+
+    /// A JSON array of `StateMachine` transactions to replay instead of reading from a node.
+    #[clap(long)]
+    dump: Option<PathBuf>,
+
+    /// The address of the `StateMachine` contract, required with `--rpc-url`.
+    #[clap(long)]
+    state_machine: Option<String>,
+
+    /// The block the contract was deployed at.
+    #[clap(long, default_value = "0")]
+    from_block: u64,
+
+    /// Defaults to the latest block.
+    #[clap(long)]
+    to_block: Option<u64>,
+
+    /// The degree of the SRS the contract was deployed with.
+    #[clap(long, default_value = "16")]
+    n: u32,
+
+    /// The auditor public key the contract was deployed with, if any.
+    #[clap(long)]
+    auditor: Option<String>,
+
+    #[clap(long, default_value = "ledger.json")]
+    out: PathBuf,
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+
+    // Parse the command line arguments.
+    let args = IndexerArgs::parse();
+    let auditor = args.auditor.as_deref().map(|s| {
+        hex::decode(s.trim_start_matches("0x"))
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| decode_scalar(&bytes))
+            .unwrap_or_else(|e| fail(format!("Invalid auditor key: {}", e)))
+    });
+
+    let mut ledger = if args.out.exists() {
+        let ledger = Ledger::load(&args.out).unwrap_or_else(|e| fail(e));
+        if auditor.is_some() && auditor != ledger.pp.auditor {
+            fail(format!("{} was indexed with a different auditor", args.out.display()));
+        }
+        println!("Resuming from {} at block {:?}", args.out.display(), ledger.last_block);
+        ledger
+    } else {
+        Ledger::new(args.n as usize, auditor)
+    };
+    let from_block = match ledger.last_block {
+        Some(last) => args.from_block.max(last + 1),
+        None => args.from_block,
+    };
+
+    let transactions: Vec<IndexedTransaction> = match (&args.rpc_url, &args.dump) {
+        (Some(url), None) => {
+            let state_machine = args.state_machine.as_deref().unwrap_or_else(|| fail("--state-machine is required with --rpc-url".to_string()));
+            let rpc = RpcClient::new(url);
+            let to_block = match args.to_block {
+                Some(block) => block,
+                None => rpc.block_number().unwrap_or_else(|e| fail(e)),
+            };
+            let transactions = fetch_transactions(&rpc, state_machine, from_block, to_block).unwrap_or_else(|e| fail(e));
+            ledger.last_block = Some(ledger.last_block.map_or(to_block, |last| last.max(to_block)));
+            transactions
+        },
+        (None, Some(path)) => load_dump(path)
+            .unwrap_or_else(|e| fail(e))
+            .into_iter()
+            .filter(|tx| tx.block_number.map_or(ledger.last_block.is_none(), |block| block >= from_block))
+            .collect(),
+        _ => fail("Pass either --rpc-url or --dump".to_string()),
+    };
+
+    let mut applied = 0;
+    for tx in transactions.iter() {
+        if ledger.apply(tx).unwrap_or_else(|e| fail(e)) {
+            applied += 1;
+        }
+    }
+    println!("Replayed {} of {} transactions", applied, transactions.len());
+    println!("Accounts: {}", ledger.pp.index_of.len());
+    for (shard, phi) in ledger.phi.iter().enumerate() {
+        println!("phi of shard {}: {}", shard, g1_to_hex(phi));
+    }
+
+    ledger.save(&args.out).unwrap_or_else(|e| fail(e));
+    println!("Ledger written to {}", args.out.display());
+
+    let mut solvent = true;
+    for (asset, supply) in ledger.pp.supply.iter() {
+        println!("Total supply of {}: {}", asset_to_hex(asset), supply);
+        if let (Some(url), Some(state_machine)) = (&args.rpc_url, &args.state_machine) {
+            let (counted, holds) = fetch_supply(&RpcClient::new(url), state_machine, asset).unwrap_or_else(|e| fail(e));
+            if counted != U256::from(*supply) {
+                println!("  The contract counts {}", counted);
+                solvent = false;
+            }
+            if !holds {
+                println!("  The contract holds less than the total supply");
+                solvent = false;
+            }
+        }
+    }
+    if !solvent {
+        fail("The contract does not back the ledger".to_string());
+    }
+}
@@ -10,11 +10,11 @@
 //! RUST_LOG=info cargo run --release -- --prove
 //! ```
 
-use alloy_sol_types::SolType;
 use clap::Parser;
 use sp1_bls12_381::Scalar;
 use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
-use state_machine_lib::{Action, Deposit, ElGamal, PublicParams, PublicValuesDeposit, KZG};
+use state_machine_lib::{Action, Deposit, ElGamal, PublicParams, DecodedPublicValues, KZG, ActionKind, NATIVE_ASSET, view_key};
+use state_machine_script::bundle::{bundle_dir, ProofBundle};
 
 /// The ELF (executable and linkable format) file for the Succinct RISC-V zkVM.
 pub const STATEMACHINE_ELF: &[u8] = include_elf!("state-machine-program");
@@ -53,11 +53,11 @@ fn main() {
     let el_gamal = ElGamal::new(pp.g);
     let kzg = KZG::new(pp.g1_lagrange_basis.clone());
     let v = vec![Scalar::zero(); pp.degree];
-    let phi = kzg.commit(v).unwrap();
+    let phi = vec![kzg.commit(v).unwrap()];
 
-    let sk_a = [1u64, 2, 3, 4];
+    let sk_a = view_key([1u64, 2, 3, 4]);
     let pk_a = el_gamal.from_skey(sk_a);
-    let sk_b = [5u64, 6, 7, 8];
+    let sk_b = view_key([5u64, 6, 7, 8]);
     let pk_b = el_gamal.from_skey(sk_b);
     println!("User A's public key: {:?}", pk_a);
     println!("User B's public key: {:?}", pk_b);
@@ -71,6 +71,7 @@ fn main() {
 
     let deposit_inputs = Deposit {
         pkey: pk_a,
+        asset: NATIVE_ASSET,
         random: r_a,
         amount: m_a,
     };
@@ -91,16 +92,8 @@ fn main() {
         println!("Program executed successfully.");
 
         // Read the output.
-        let decoded = PublicValuesDeposit::abi_decode(output.as_slice(), true).unwrap();
-        let PublicValuesDeposit {
-            old_phi,
-            next_phi,
-            amount,
-            pkey,
-            t,
-        } = decoded;
-        println!("old_phi: {:?}", old_phi);
-        println!("next_phi: {:?}", next_phi);
+        let decoded = DecodedPublicValues::decode(ActionKind::Deposit, output.as_slice()).unwrap();
+        println!("{}", decoded);
 
         // Record the number of cycles executed.
         println!("Number of cycles: {}", report.total_instruction_count());
@@ -119,5 +112,10 @@ fn main() {
         // Verify the proof.
         client.verify(&proof, &vk).expect("failed to verify proof");
         println!("Successfully verified proof!");
+
+        let bundle = ProofBundle::new(ActionKind::Deposit, &proof, &vk, &pp);
+        let bundle_path = bundle_dir().join("core-deposit-a.json");
+        bundle.save(&bundle_path).expect("failed to save proof bundle");
+        println!("Proof bundle: {}", bundle_path.display());
     }
 }
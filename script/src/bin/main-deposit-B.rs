@@ -10,13 +10,13 @@
 //! RUST_LOG=info cargo run --release -- --prove
 //! ```
 
-use alloy_sol_types::SolType;
 use clap::Parser;
 use sp1_bls12_381::Scalar;
 use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
 use state_machine_lib::{
-    deposit, Action, Deposit, ElGamal, PublicParams, PublicValuesDeposit, KZG,
+    deposit, Action, ActionKind, Deposit, ElGamal, PublicParams, DecodedPublicValues, KZG, NATIVE_ASSET, view_key,
 };
+use state_machine_script::bundle::{bundle_dir, ProofBundle};
 
 /// The ELF (executable and linkable format) file for the Succinct RISC-V zkVM.
 pub const STATEMACHINE_ELF: &[u8] = include_elf!("state-machine-program");
@@ -52,11 +52,11 @@ fn main() {
     let el_gamal = ElGamal::new(pp.g);
     let kzg = KZG::new(pp.g1_lagrange_basis.clone());
     let v = vec![Scalar::zero(); pp.degree];
-    let mut phi = kzg.commit(v).unwrap();
+    let mut phi = vec![kzg.commit(v).unwrap()];
 
-    let sk_a = [1u64, 2, 3, 4];
+    let sk_a = view_key([1u64, 2, 3, 4]);
     let pk_a = el_gamal.from_skey(sk_a);
-    let sk_b = [5u64, 6, 7, 8];
+    let sk_b = view_key([5u64, 6, 7, 8]);
     let pk_b = el_gamal.from_skey(sk_b);
     println!("User A's public key: {:?}", pk_a);
     println!("User B's public key: {:?}", pk_b);
@@ -68,13 +68,14 @@ fn main() {
     println!("User A deposits: {:?} ETH", m_a);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_a, r_a, m_a, phi).unwrap();
+    phi = deposit(&mut pp, pk_a, NATIVE_ASSET, r_a, m_a, &phi).unwrap();
 
     println!("User B deposits: {:?} ETH", m_b);
     println!("Update state...");
 
     let deposit_inputs = Deposit {
         pkey: pk_b,
+        asset: NATIVE_ASSET,
         random: r_b,
         amount: m_b,
     };
@@ -93,16 +94,8 @@ fn main() {
         println!("Program executed successfully.");
 
         // Read the output.
-        let decoded = PublicValuesDeposit::abi_decode(output.as_slice(), true).unwrap();
-        let PublicValuesDeposit {
-            old_phi,
-            next_phi,
-            amount,
-            pkey,
-            t,
-        } = decoded;
-        println!("old_phi: {:?}", old_phi);
-        println!("next_phi: {:?}", next_phi);
+        let decoded = DecodedPublicValues::decode(ActionKind::Deposit, output.as_slice()).unwrap();
+        println!("{}", decoded);
 
         // Record the number of cycles executed.
         println!("Number of cycles: {}", report.total_instruction_count());
@@ -121,5 +114,10 @@ fn main() {
         // Verify the proof.
         client.verify(&proof, &vk).expect("failed to verify proof");
         println!("Successfully verified proof!");
+
+        let bundle = ProofBundle::new(ActionKind::Deposit, &proof, &vk, &pp);
+        let bundle_path = bundle_dir().join("core-deposit-b.json");
+        bundle.save(&bundle_path).expect("failed to save proof bundle");
+        println!("Proof bundle: {}", bundle_path.display());
     }
 }
@@ -10,10 +10,10 @@
 //! RUST_LOG=info cargo run --release -- --prove
 //! ```
 
-use alloy_sol_types::SolType;
 use hex::{encode, decode};
 use clap::Parser;
-use state_machine_lib::{deposit, send, withdraw, Action, ElGamal, PublicParams, PublicValuesRotate, Rotate, KZG};
+use state_machine_lib::{deposit, send, withdraw, Action, ElGamal, PublicParams, DecodedPublicValues, Rotate, KZG, ActionKind, NATIVE_ASSET, view_key, Authorization};
+use state_machine_script::bundle::{bundle_dir, ProofBundle};
 use sp1_bls12_381::Scalar;
 use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
 
@@ -54,11 +54,12 @@ fn main() {
     let el_gamal = ElGamal::new(pp.g);
     let kzg = KZG::new(pp.g1_lagrange_basis.clone());
     let v = vec![Scalar::zero(); pp.degree];
-    let mut phi = kzg.commit(v).unwrap();
+    let mut phi = vec![kzg.commit(v).unwrap()];
 
-    let sk_a = [1u64, 2, 3, 4];
+    let spend_a = [1u64, 2, 3, 4];
+    let sk_a = view_key(spend_a);
     let pk_a = el_gamal.from_skey(sk_a);
-    let sk_b = [5u64, 6, 7, 8];
+    let sk_b = view_key([5u64, 6, 7, 8]);
     let pk_b = el_gamal.from_skey(sk_b);
     println!("User A's public key: {:?}", pk_a);
     println!("User B's public key: {:?}", pk_b);
@@ -70,23 +71,24 @@ fn main() {
     println!("User A deposits: {:?} ETH", m_a);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_a, r_a, m_a, phi).unwrap();
-    println!("phi: {:?}", encode(phi.to_compressed()));
+    phi = deposit(&mut pp, pk_a, NATIVE_ASSET, r_a, m_a, &phi).unwrap();
+    println!("phi: {:?}", encode(phi[0].to_compressed()));
 
     println!("User B deposits: {:?} ETH", m_b);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_b, r_b, m_b, phi).unwrap();
-    println!("phi: {:?}", encode(phi.to_compressed()));
+    phi = deposit(&mut pp, pk_b, NATIVE_ASSET, r_b, m_b, &phi).unwrap();
+    println!("phi: {:?}", encode(phi[0].to_compressed()));
 
     let amount = 30u64;
     println!("User B sends {:?} ETH to User A", amount);
     println!("Update state...");
 
-    phi = send(&mut pp, sk_b, pk_a, m_b, amount, phi).unwrap();
-    println!("phi: {:?}", encode(phi.to_compressed()));
+    phi = send(&mut pp, sk_b, pk_a, NATIVE_ASSET, m_b, amount, 0, [0x4444u64, 0, 0, 0], [0x5555u64, 0, 0, 0], &phi).unwrap();
+    println!("phi: {:?}", encode(phi[0].to_compressed()));
     m_b -= amount;
-    m_a += amount;
+    // User A's 30 ETH stay pending, and the withdraw only touches the ciphertext of A's slot, so
+    // it is proven against the 100 ETH deposited and lands whether or not the send did
 
     let withdraw_amount = 10u64;
 
@@ -96,8 +98,8 @@ fn main() {
     // private key: 0xc0cf034c2039fbb095aad1cd7dfd8854eddc5fcfed04e009520049107022b22b
     let recipient = "65f697a02d756Cf4BC3465c1cC60dB3a4AF19521"; // TODO: need address
     let recipient: [u8; 20] = decode(recipient).unwrap().try_into().unwrap();
-    phi = withdraw(&mut pp, sk_a, r_a, m_a, withdraw_amount, phi, recipient).unwrap();
-    println!("phi: {:?}", encode(phi.to_compressed()));
+    phi = withdraw(&mut pp, sk_a, NATIVE_ASSET, m_a, withdraw_amount, 0, [0x6666u64, 0, 0, 0], &phi, recipient).unwrap();
+    println!("phi: {:?}", encode(phi[0].to_compressed()));
     m_a -= withdraw_amount;
 
     let add_additive = [1u64, 0, 0, 0];
@@ -106,11 +108,14 @@ fn main() {
     println!("Update state...");
 
     let rotate_inputs = Rotate {
-        skey: sk_a,
+        auth: Authorization::default(),
+        asset: NATIVE_ASSET,
         new_additive: add_additive,
+        rekey: None,
     };
 
-    let action = Action::Rotate(rotate_inputs);
+    let mut action = Action::Rotate(rotate_inputs);
+    action.sign(spend_a, &pp).unwrap();
 
     // Setup the inputs.
     let mut stdin = SP1Stdin::new();
@@ -126,14 +131,8 @@ fn main() {
         println!("Program executed successfully.");
 
         // Read the output.
-        let decoded = PublicValuesRotate::abi_decode(output.as_slice(), true).unwrap();
-        let PublicValuesRotate {
-            old_phi, 
-            next_phi,
-            pkey,
-            new_t} = decoded;
-        println!("old_phi: {:?}", old_phi);
-        println!("next_phi: {:?}", next_phi);
+        let decoded = DecodedPublicValues::decode(ActionKind::Rotate, output.as_slice()).unwrap();
+        println!("{}", decoded);
 
         // Record the number of cycles executed.
         println!("Number of cycles: {}", report.total_instruction_count());
@@ -152,5 +151,10 @@ fn main() {
         // Verify the proof.
         client.verify(&proof, &vk).expect("failed to verify proof");
         println!("Successfully verified proof!");
+
+        let bundle = ProofBundle::new(ActionKind::Rotate, &proof, &vk, &pp);
+        let bundle_path = bundle_dir().join("core-rotate.json");
+        bundle.save(&bundle_path).expect("failed to save proof bundle");
+        println!("Proof bundle: {}", bundle_path.display());
     }
 }
@@ -10,9 +10,9 @@
 //! RUST_LOG=info cargo run --release -- --prove
 //! ```
 
-use alloy_sol_types::SolType;
 use clap::Parser;
-use state_machine_lib::{ElGamal, PublicParams, PublicValuesSend, KZG, Action, Send, deposit};
+use state_machine_lib::{ElGamal, PublicParams, DecodedPublicValues, KZG, Action, Send, deposit, ActionKind, NATIVE_ASSET, view_key, Authorization};
+use state_machine_script::bundle::{bundle_dir, ProofBundle};
 use sp1_bls12_381::Scalar;
 use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
 
@@ -53,11 +53,12 @@ fn main() {
     let el_gamal = ElGamal::new(pp.g);
     let kzg = KZG::new(pp.g1_lagrange_basis.clone());
     let v = vec![Scalar::zero(); pp.degree];
-    let mut phi = kzg.commit(v).unwrap();
+    let mut phi = vec![kzg.commit(v).unwrap()];
 
-    let sk_a = [1u64, 2, 3, 4];
+    let sk_a = view_key([1u64, 2, 3, 4]);
     let pk_a = el_gamal.from_skey(sk_a);
-    let sk_b = [5u64, 6, 7, 8];
+    let spend_b = [5u64, 6, 7, 8];
+    let sk_b = view_key(spend_b);
     let pk_b = el_gamal.from_skey(sk_b);
     println!("User A's public key: {:?}", pk_a);
     println!("User B's public key: {:?}", pk_b);
@@ -69,12 +70,12 @@ fn main() {
     println!("User A deposits: {:?} ETH", m_a);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_a, r_a, m_a, phi).unwrap();
+    phi = deposit(&mut pp, pk_a, NATIVE_ASSET, r_a, m_a, &phi).unwrap();
 
     println!("User B deposits: {:?} ETH", m_b);
     println!("Update state...");
 
-    phi = deposit(&mut pp, pk_b, r_b, m_b, phi).unwrap();
+    phi = deposit(&mut pp, pk_b, NATIVE_ASSET, r_b, m_b, &phi).unwrap();
 
     let amount = 30u64;
     println!("User B sends {:?} ETH to User A", amount);
@@ -84,10 +85,18 @@ fn main() {
         balance_sender: m_b,
         amount,
         pkey_receiver: pk_a,
-        skey_sender: sk_b,
+        asset: NATIVE_ASSET,
+        fee: 0,
+        relayer: [0u8; 20],
+        auth_sender: Authorization::default(),
+        note_random: [0x3333u64, 0, 0, 0], // TODO: need random
+        transfer_random: [0x4444u64, 0, 0, 0], // TODO: need random
+        debit_random: [0x5555u64, 0, 0, 0], // TODO: need random
+        memo: b"rent".to_vec(),
     };
 
-    let action = Action::Send(send_inputs);
+    let mut action = Action::Send(send_inputs);
+    action.sign(spend_b, &pp).unwrap();
 
     // Setup the inputs.
     let mut stdin = SP1Stdin::new();
@@ -103,13 +112,8 @@ fn main() {
         println!("Program executed successfully.");
 
         // Read the output.
-        let decoded = PublicValuesSend::abi_decode(output.as_slice(), true).unwrap();
-        let PublicValuesSend {
-            old_phi, 
-            next_phi, 
-            } = decoded;
-        println!("old_phi: {:?}", old_phi);
-        println!("next_phi: {:?}", next_phi);
+        let decoded = DecodedPublicValues::decode(ActionKind::Send, output.as_slice()).unwrap();
+        println!("{}", decoded);
 
         // Record the number of cycles executed.
         println!("Number of cycles: {}", report.total_instruction_count());
@@ -128,5 +132,10 @@ fn main() {
         // Verify the proof.
         client.verify(&proof, &vk).expect("failed to verify proof");
         println!("Successfully verified proof!");
+
+        let bundle = ProofBundle::new(ActionKind::Send, &proof, &vk, &pp);
+        let bundle_path = bundle_dir().join("core-send.json");
+        bundle.save(&bundle_path).expect("failed to save proof bundle");
+        println!("Proof bundle: {}", bundle_path.display());
     }
 }
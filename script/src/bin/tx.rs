@@ -0,0 +1,131 @@
+//! Builds the `StateMachine` transaction that submits a proof bundle, optionally signs it with a
+//! key from an Ethereum keystore and sends it to a node.
+//!
+//! You can run this script using the following command:
+//! ```shell
+//! cargo run --release --bin tx -- --bundle ../proofs/groth16-send.json --state-machine 0x...
+//! ```
+//! or, to sign and submit it:
+//! ```shell
+//! cargo run --release --bin tx -- --bundle ../proofs/groth16-send.json --state-machine 0x... \
+//!     --keystore ./key.json --rpc-url http://localhost:8545 --submit
+//! ```
+
+use alloy_consensus::SignableTransaction;
+use alloy_primitives::Address;
+use clap::Parser;
+use state_machine_script::rpc::RpcClient;
+use state_machine_script::tx::{build_call, build_transaction, load_signing_key, sign_transaction, signer_address, TxParams};
+use state_machine_script::ProofBundle;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The arguments for the tx command.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct TxArgs {
+    #[clap(long)]
+    bundle: PathBuf,
+
+    /// The address of the `StateMachine` contract.
+    #[clap(long)]
+    state_machine: String,
+
+    /// A node to read the chain id, nonce and fees from, and to submit to.
+    #[clap(long, env = "RPC_URL")]
+    rpc_url: Option<String>,
+
+    #[clap(long)]
+    chain_id: Option<u64>,
+
+    #[clap(long)]
+    nonce: Option<u64>,
+
+    #[clap(long, default_value = "1000000")]
+    gas_limit: u128,
+
+    #[clap(long)]
+    max_fee_per_gas: Option<u128>,
+
+    #[clap(long)]
+    max_priority_fee_per_gas: Option<u128>,
+
+    /// An Ethereum v3 keystore with the key to sign the transaction with.
+    #[clap(long)]
+    keystore: Option<PathBuf>,
+
+    #[clap(long, env = "KEYSTORE_PASSWORD", default_value = "")]
+    password: String,
+
+    /// Send the signed transaction to `--rpc-url`.
+    #[clap(long)]
+    submit: bool,
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+
+    // Parse the command line arguments.
+    let args = TxArgs::parse();
+
+    let bundle = ProofBundle::load(&args.bundle).unwrap_or_else(|e| fail(e));
+    let state_machine = Address::from_str(&args.state_machine).unwrap_or_else(|e| fail(format!("Invalid address: {}", e)));
+    let call = build_call(&bundle).unwrap_or_else(|e| fail(e));
+    println!("Action: {:?}", bundle.action);
+    println!("Calldata: 0x{}", hex::encode(&call.data));
+    println!("Value: {}", call.value);
+
+    let key = args.keystore.as_ref().map(|path| load_signing_key(path, &args.password).unwrap_or_else(|e| fail(e)));
+    let rpc = args.rpc_url.as_deref().map(RpcClient::new);
+
+    // Values given on the command line take precedence over the ones read from the node.
+    let from_node = |name: &str, value: Option<Result<u128, String>>| -> u128 {
+        match value {
+            Some(Ok(value)) => value,
+            Some(Err(e)) => fail(e),
+            None => fail(format!("--{} is required without --rpc-url", name)),
+        }
+    };
+    let chain_id = args.chain_id.unwrap_or_else(|| {
+        from_node("chain-id", rpc.as_ref().map(|rpc| rpc.chain_id().map(u128::from))) as u64
+    });
+    let nonce = args.nonce.unwrap_or_else(|| {
+        let signer = key.as_ref().map(|key| signer_address(key).to_string());
+        let nonce = match (rpc.as_ref(), signer) {
+            (Some(rpc), Some(signer)) => Some(rpc.nonce(&signer).map(u128::from)),
+            _ => None,
+        };
+        from_node("nonce", nonce) as u64
+    });
+    let max_priority_fee_per_gas = args.max_priority_fee_per_gas.unwrap_or_else(|| {
+        from_node("max-priority-fee-per-gas", rpc.as_ref().map(|rpc| rpc.max_priority_fee_per_gas()))
+    });
+    let max_fee_per_gas = args.max_fee_per_gas.unwrap_or_else(|| {
+        let gas_price = rpc.as_ref().map(|rpc| rpc.gas_price().map(|price| 2 * price + max_priority_fee_per_gas));
+        from_node("max-fee-per-gas", gas_price)
+    });
+
+    let params = TxParams { chain_id, nonce, gas_limit: args.gas_limit, max_fee_per_gas, max_priority_fee_per_gas };
+    let tx = build_transaction(&call, state_machine, &params);
+    let mut unsigned = Vec::new();
+    tx.encode_for_signing(&mut unsigned);
+    println!("Unsigned transaction: 0x{}", hex::encode(&unsigned));
+
+    let Some(key) = key else {
+        return;
+    };
+    println!("Signer: {}", signer_address(&key));
+    let raw = sign_transaction(tx, &key).unwrap_or_else(|e| fail(e));
+    println!("Signed transaction: 0x{}", hex::encode(&raw));
+
+    if args.submit {
+        let rpc = rpc.unwrap_or_else(|| fail("--submit requires --rpc-url".to_string()));
+        let hash = rpc.send_raw_transaction(&raw).unwrap_or_else(|e| fail(e));
+        println!("Transaction hash: {}", hash);
+    }
+}
@@ -0,0 +1,71 @@
+//! Verifies a proof bundle written by one of the proving scripts against the state machine
+//! program, and prints the public values it commits to.
+//!
+//! You can run this script using the following command:
+//! ```shell
+//! RUST_LOG=info cargo run --release --bin verify -- --bundle ../proofs/groth16-send.json
+//! ```
+
+use clap::Parser;
+use sp1_sdk::{include_elf, ProverClient};
+use state_machine_lib::{DecodedPublicValues, PublicParams};
+use state_machine_script::ProofBundle;
+use std::path::PathBuf;
+
+/// The ELF (executable and linkable format) file for the Succinct RISC-V zkVM.
+pub const STATEMACHINE_ELF: &[u8] = include_elf!("state-machine-program");
+
+/// The arguments for the verify command.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct VerifyArgs {
+    #[clap(long)]
+    bundle: PathBuf,
+
+    /// The degree of the SRS the proof is expected to be generated against.
+    #[clap(long, default_value = "16")]
+    n: u32,
+
+    /// Print the decoded public values as JSON.
+    #[clap(long)]
+    json: bool,
+}
+
+fn main() {
+    // Setup the logger.
+    sp1_sdk::utils::setup_logger();
+    dotenv::dotenv().ok();
+
+    // Parse the command line arguments.
+    let args = VerifyArgs::parse();
+
+    let bundle = ProofBundle::load(&args.bundle).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    println!("Action: {:?}", bundle.action);
+    println!("Proof System: {:?}", bundle.system);
+
+    // Setup the prover client.
+    let client = ProverClient::from_env();
+    let (_, vk) = client.setup(STATEMACHINE_ELF);
+    let pp = PublicParams::setup(args.n as usize);
+
+    let proof = bundle.check(&vk, &pp).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    let start = std::time::Instant::now();
+    client.verify(&proof, &vk).expect("failed to verify proof");
+    println!("Proof verification time: {:?}", start.elapsed());
+    println!("Successfully verified proof!");
+
+    let decoded = DecodedPublicValues::decode(bundle.action, proof.public_values.as_slice())
+        .expect("failed to decode public values");
+    if args.json {
+        println!("{}", decoded.to_json());
+    } else {
+        println!("{}", decoded);
+    }
+}
@@ -0,0 +1,602 @@
+//! A command-line wallet for a single state machine account. Keys live in an encrypted keystore,
+//! balances are read from a ledger written by the `indexer` script, and every action is proven
+//! locally and saved as a proof bundle, ready to be submitted with the `tx` script.
+//!
+//! You can run this script using the following commands:
+//! ```shell
+//! cargo run --release --bin wallet -- keygen --words 24
+//! cargo run --release --bin wallet -- --wallet usdc keygen --account 1 --asset 0x<token address>
+//! cargo run --release --bin wallet -- deposit --amount 100
+//! cargo run --release --bin wallet -- balance
+//! cargo run --release --bin wallet -- send --to 0x... --amount 30
+//! cargo run --release --bin wallet -- send-many --to 0x...:30 --to 0x...:20
+//! cargo run --release --bin wallet -- withdraw --amount 10 --recipient 0x...
+//! cargo run --release --bin wallet -- rotate
+//! cargo run --release --bin wallet -- rotate --new-key
+//! cargo run --release --bin wallet -- rollover
+//! cargo run --release --bin wallet -- close
+//! cargo run --release --bin wallet -- attest --threshold 50 --nonce 0x...
+//! ```
+//! A wallet lost along with its keystore comes back with `restore --mnemonic "<words>"`.
+//! Keys can be moved between machines with `export <file>` and `import --keystore <file>`, and raw
+//! secrets printed by the other scripts can be brought in with `import --spend-key 0x... --random 0x...`.
+//! `export --view-only <file>` leaves the spend key out, for a service that only reads the balance.
+//! With `--rpc-url http://localhost:8545 --state-machine 0x...`, the wallet follows the chain until the
+//! proof lands, and proves the action again if what was mined meanwhile made the proof stale. Add
+//! `--signer <keystore>` to have it send the proof itself.
+
+use alloy_primitives::Address;
+use clap::{Parser, Subcommand};
+use sp1_sdk::include_elf;
+use sp1_bls12_381::Scalar;
+use state_machine_lib::public_values::{decode_scalar, scalar_to_hex};
+use state_machine_lib::{
+    account_key, scalar_to_exponent, view_key, Action, ActionKind, Attest, Authorization, Close, Deposit, ElGamal, PublicParams, Receiver, Rekey, Rollover, Rotate, Send,
+    SendMany, Withdraw,
+};
+use state_machine_script::bundle::bundle_dir;
+use state_machine_script::indexer::{asset_to_hex, decode_asset, Ledger};
+use state_machine_script::keystore::{Keystore, KeystoreSecrets, PendingRekey};
+use state_machine_script::prover::{rebuild_witness, Prover};
+use state_machine_script::rpc::RpcClient;
+use state_machine_script::seed::generate_mnemonic;
+use state_machine_script::wallet::{random_exponent, Wallet};
+use state_machine_script::tx::{load_signing_key, send};
+use state_machine_script::{ProofBundle, ProofSystem};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The ELF (executable and linkable format) file for the Succinct RISC-V zkVM.
+pub const STATEMACHINE_ELF: &[u8] = include_elf!("state-machine-program");
+
+/// The arguments for the wallet command.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct WalletArgs {
+    #[clap(long, default_value = "wallet")]
+    wallet: PathBuf,
+
+    #[clap(long, env = "WALLET_PASSWORD", default_value = "")]
+    password: String,
+
+    /// The ledger written by the indexer.
+    #[clap(long, default_value = "ledger.json")]
+    ledger: PathBuf,
+
+    /// The largest balance to search for when the last known one no longer decrypts.
+    #[clap(long, default_value = "1000000")]
+    max_balance: u64,
+
+    /// The proof system to prove actions with.
+    #[clap(long, value_enum, default_value = "groth16")]
+    system: ProofSystem,
+
+    /// Where to write the proof bundle, defaults to `proofs/wallet-<action>.json`.
+    #[clap(long)]
+    out: Option<PathBuf>,
+
+    /// A node to follow once the proof is generated. If actions mined meanwhile changed the slots
+    /// the proof reads, the ledger is caught up and the action proven again.
+    #[clap(long, requires = "state_machine")]
+    rpc_url: Option<String>,
+
+    /// The address of the `StateMachine` contract, required with `--rpc-url`.
+    #[clap(long)]
+    state_machine: Option<String>,
+
+    /// How many times to prove again before giving up.
+    #[clap(long, default_value = "3")]
+    max_retries: u32,
+
+    /// How many seconds to wait between checks of the chain until the proof lands.
+    #[clap(long, default_value = "12")]
+    poll_interval: u64,
+
+    /// An Ethereum v3 keystore to sign and send the proof with, once it applies. Without it the
+    /// bundle is written for `tx` to submit, and written again each time it is proven again.
+    #[clap(long, requires = "rpc_url")]
+    signer: Option<PathBuf>,
+
+    #[clap(long, env = "SIGNER_PASSWORD", default_value = "")]
+    signer_password: String,
+
+    #[clap(long, default_value = "1000000")]
+    gas_limit: u128,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create a wallet from a fresh mnemonic.
+    Keygen {
+        /// 12 or 24.
+        #[clap(long, default_value = "12")]
+        words: usize,
+        /// Which account of the mnemonic to use.
+        #[clap(long, default_value = "0")]
+        account: u32,
+        /// The ERC-20 token the account holds, ETH when omitted.
+        #[clap(long, default_value = "")]
+        asset: String,
+        /// The degree of the SRS the contract was deployed with.
+        #[clap(long, default_value = "16")]
+        n: u32,
+    },
+    /// Recreate a wallet from its mnemonic, and sync it if the ledger is available.
+    Restore {
+        #[clap(long, env = "WALLET_MNEMONIC")]
+        mnemonic: String,
+        #[clap(long, default_value = "0")]
+        account: u32,
+        #[clap(long, default_value = "")]
+        asset: String,
+        /// The degree of the SRS the contract was deployed with.
+        #[clap(long, default_value = "16")]
+        n: u32,
+    },
+    /// Create a wallet from an exported keystore, or from a raw spend or view key.
+    Import {
+        #[clap(long, required_unless_present_any = ["spend_key", "view_key"])]
+        keystore: Option<PathBuf>,
+        #[clap(long, conflicts_with_all = ["keystore", "view_key"])]
+        spend_key: Option<String>,
+        /// Import a view-only wallet, which can read the balance and notes but not spend.
+        #[clap(long, conflicts_with = "keystore")]
+        view_key: Option<String>,
+        /// The randomness of the deposit opening the account, fresh when omitted.
+        #[clap(long, conflicts_with = "keystore")]
+        random: Option<String>,
+        #[clap(long, default_value = "")]
+        asset: String,
+        /// The degree of the SRS the contract was deployed with.
+        #[clap(long, default_value = "16")]
+        n: u32,
+    },
+    /// Write the wallet's keystore to a file.
+    Export {
+        path: PathBuf,
+        /// Seal the exported keystore under a different password.
+        #[clap(long, env = "EXPORT_PASSWORD")]
+        export_password: Option<String>,
+        /// Leave out the spend key, and the mnemonic it comes from.
+        #[clap(long)]
+        view_only: bool,
+    },
+    /// Sync with the ledger and print the balance.
+    Balance,
+    /// List the transfers received, with their memos.
+    Notes,
+    /// Open the account with a first deposit, or top it up.
+    Deposit {
+        #[clap(long)]
+        amount: u64,
+    },
+    Send {
+        /// The receiver's public key.
+        #[clap(long)]
+        to: String,
+        #[clap(long)]
+        amount: u64,
+        /// A message for the receiver, encrypted along with the amount.
+        #[clap(long, default_value = "")]
+        memo: String,
+        /// Paid to the relayer on top of the amount.
+        #[clap(long, default_value_t = 0)]
+        fee: u64,
+        /// The address the fee is paid to.
+        #[clap(long)]
+        relayer: Option<String>,
+    },
+    /// Pay several receivers with one proof.
+    SendMany {
+        /// A receiver and the amount it gets, as `<public key>:<amount>`. Repeat for each receiver.
+        #[clap(long = "to", required = true)]
+        to: Vec<String>,
+        /// A message for every receiver, encrypted along with each amount.
+        #[clap(long, default_value = "")]
+        memo: String,
+    },
+    Withdraw {
+        #[clap(long)]
+        amount: u64,
+        #[clap(long)]
+        recipient: String,
+        /// Paid to the relayer on top of the amount, so the recipient needs no ETH for gas.
+        #[clap(long, default_value_t = 0)]
+        fee: u64,
+        /// The address the fee is paid to.
+        #[clap(long)]
+        relayer: Option<String>,
+    },
+    /// Re-randomize the account's ciphertext.
+    Rotate {
+        /// Move the account to a fresh key pair instead, revoking the current secret key.
+        #[clap(long)]
+        new_key: bool,
+    },
+    /// Move the transfers received into the spendable balance. Each shard can be rolled over once
+    /// per epoch, by anyone, so no key is needed.
+    Rollover {
+        /// Defaults to the account's shard.
+        #[clap(long)]
+        shard: Option<u64>,
+    },
+    /// Close the account, which must be empty, freeing its slot.
+    Close,
+    /// Prove to a counterparty that the balance is at least `threshold`, without moving it.
+    Attest {
+        #[clap(long)]
+        threshold: u64,
+        /// The nonce the counterparty asked for, from `attest nonce`.
+        #[clap(long)]
+        nonce: String,
+    },
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
+}
+
+/// Reads an exponent written as a little-endian hex scalar.
+fn parse_exponent(s: &str) -> [u64; 4] {
+    hex::decode(s.trim_start_matches("0x"))
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| decode_scalar(&bytes))
+        .map(|scalar| scalar_to_exponent(&scalar))
+        .unwrap_or_else(|e| fail(format!("Invalid exponent {}: {}", s, e)))
+}
+
+/// Reads a public key written as a little-endian hex scalar.
+fn parse_pkey(s: &str) -> Scalar {
+    hex::decode(s.trim_start_matches("0x"))
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| decode_scalar(&bytes))
+        .unwrap_or_else(|e| fail(format!("Invalid receiver key: {}", e)))
+}
+
+fn parse_asset(s: &str) -> [u8; 20] {
+    decode_asset(s).unwrap_or_else(|e| fail(e))
+}
+
+fn parse_address(s: &str, what: &str) -> [u8; 20] {
+    hex::decode(s.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .unwrap_or_else(|| fail(format!("Invalid {} address", what)))
+}
+
+/// The relayer fee and the address it is paid to, none when there is no fee.
+fn parse_fee(fee: u64, relayer: &Option<String>) -> (u64, [u8; 20]) {
+    let relayer = relayer.as_deref().map(|r| parse_address(r, "relayer")).unwrap_or([0u8; 20]);
+    if fee > 0 && relayer == [0u8; 20] {
+        fail("The fee needs a relayer address".to_string());
+    }
+    (fee, relayer)
+}
+
+/// The amount plus the relayer fee, which must both fit in the balance.
+fn debit(balance: u64, amount: u64, fee: u64) -> u64 {
+    match amount.checked_add(fee) {
+        Some(debit) if debit <= balance => debit,
+        _ => fail(format!("Insufficient balance: {}", balance)),
+    }
+}
+
+fn load_ledger(path: &Path) -> Ledger {
+    Ledger::load(path).unwrap_or_else(|e| fail(format!("{} (run the indexer first)", e)))
+}
+
+fn main() {
+    // Setup the logger.
+    sp1_sdk::utils::setup_logger();
+    dotenv::dotenv().ok();
+
+    // Parse the command line arguments.
+    let args = WalletArgs::parse();
+
+    if let Command::Keygen { words, account, asset, n } = &args.command {
+        let pp = PublicParams::setup(*n as usize);
+        let mnemonic = generate_mnemonic(*words).unwrap_or_else(|e| fail(e));
+        let asset = parse_asset(asset);
+        let wallet = Wallet::from_mnemonic(&args.wallet, &args.password, &mnemonic.to_string(), *account, asset, &pp)
+            .unwrap_or_else(|e| fail(e));
+        println!("Wallet created at {}", args.wallet.display());
+        println!("Public key: {}", wallet.state.pkey);
+        println!("Mnemonic: {}", mnemonic);
+        println!("Write the mnemonic down, it is the only way to restore the wallet.");
+        return;
+    }
+
+    if let Command::Restore { mnemonic, account, asset, n } = &args.command {
+        let pp = PublicParams::setup(*n as usize);
+        let asset = parse_asset(asset);
+        let mut wallet = Wallet::from_mnemonic(&args.wallet, &args.password, mnemonic, *account, asset, &pp)
+            .unwrap_or_else(|e| fail(e));
+        println!("Wallet restored to {}", args.wallet.display());
+        println!("Public key: {}", wallet.state.pkey);
+        if args.ledger.exists() {
+            let ledger = load_ledger(&args.ledger);
+            match wallet.sync(&ledger, args.max_balance) {
+                Ok(balance) => {
+                    wallet.save().unwrap_or_else(|e| fail(e));
+                    let (shard, index) = ledger.pp.locate(wallet.state.index.unwrap());
+                    println!("Account: shard {}, index {}", shard, index);
+                    println!("Balance: {}", balance);
+                },
+                Err(e) => println!("Not synced: {}", e),
+            }
+        }
+        return;
+    }
+
+    if let Command::Import { keystore, spend_key, view_key: view, random, asset, n } = &args.command {
+        let pp = PublicParams::setup(*n as usize);
+        let secrets = match (keystore, spend_key, view, random) {
+            (Some(path), _, _, _) => Keystore::load(path)
+                .and_then(|keystore| keystore.decrypt(&args.password))
+                .unwrap_or_else(|e| fail(e)),
+            (None, spend_key, view, random) => KeystoreSecrets {
+                skey: match (spend_key, view) {
+                    (Some(spend_key), _) => view_key(parse_exponent(spend_key)),
+                    (None, Some(view)) => parse_exponent(view),
+                    (None, None) => unreachable!(),
+                },
+                spend_key: spend_key.as_deref().map(parse_exponent),
+                random: random.as_deref().map(parse_exponent).unwrap_or_else(random_exponent),
+                seed: None,
+                pending_rekey: None,
+            },
+        };
+        let wallet =
+            Wallet::import(&args.wallet, &args.password, secrets, parse_asset(asset), &pp).unwrap_or_else(|e| fail(e));
+        println!("Wallet imported to {}", args.wallet.display());
+        println!("Public key: {}", wallet.state.pkey);
+        return;
+    }
+
+    let mut wallet = Wallet::open(&args.wallet, &args.password).unwrap_or_else(|e| fail(e));
+    if let Command::Export { path, export_password, view_only } = &args.command {
+        let password = export_password.as_deref().unwrap_or(&args.password);
+        let keystore = match view_only {
+            true => Keystore::encrypt(&wallet.view_only(), &wallet.state.pkey, password),
+            false => wallet.keystore(password),
+        };
+        keystore.and_then(|keystore| keystore.save(path)).unwrap_or_else(|e| fail(e));
+        println!("Keystore exported to {}", path.display());
+        return;
+    }
+    let mut ledger = load_ledger(&args.ledger);
+    let pkey = wallet.pkey(&ledger.pp);
+    let synced = wallet.state.clone();
+
+    let (kind, mut action) = match &args.command {
+        Command::Keygen { .. } | Command::Restore { .. } | Command::Import { .. } | Command::Export { .. } => {
+            unreachable!()
+        },
+        Command::Balance => {
+            let balance = wallet.sync(&ledger, args.max_balance).unwrap_or_else(|e| fail(e));
+            wallet.save().unwrap_or_else(|e| fail(e));
+            println!("Public key: {}", wallet.state.pkey);
+            println!("Asset: {}", asset_to_hex(&wallet.asset));
+            let (shard, index) = ledger.pp.locate(wallet.state.index.unwrap());
+            println!("Account: shard {}, index {}", shard, index);
+            println!("Balance: {}", balance);
+            if wallet.state.pending > 0 {
+                println!("Pending: {} (spendable once shard {} is rolled over)", wallet.state.pending, shard);
+            }
+            return;
+        },
+        Command::Notes => {
+            for note in wallet.received_notes(&ledger, 0) {
+                println!("{} from {}: {}", note.amount, scalar_to_hex(&note.sender), String::from_utf8_lossy(&note.memo));
+            }
+            return;
+        },
+        Command::Deposit { amount } => {
+            let random = if ledger.pp.index_of.contains_key(&account_key(&pkey, &wallet.asset)) {
+                // A top-up is pending until the next rollover, like a transfer
+                wallet.sync(&ledger, args.max_balance).unwrap_or_else(|e| fail(e));
+                wallet.state.pending += amount;
+                random_exponent()
+            } else {
+                // A new account, or one reopened after a close, is encrypted under the deposit randomness
+                wallet.state.balance = *amount;
+                wallet.state.pending = 0;
+                wallet.secrets.random
+            };
+            let deposit_inputs = Deposit { amount: *amount, pkey, asset: wallet.asset, random };
+            (ActionKind::Deposit, Action::Deposit(deposit_inputs))
+        },
+        Command::Send { to, amount, memo, fee, relayer } => {
+            let balance = wallet.sync(&ledger, args.max_balance).unwrap_or_else(|e| fail(e));
+            let (fee, relayer) = parse_fee(*fee, relayer);
+            let debit = debit(balance, *amount, fee);
+            let pkey_receiver = parse_pkey(to);
+            if !ledger.pp.index_of.contains_key(&account_key(&pkey_receiver, &wallet.asset)) {
+                fail("The receiver has no account in this asset on the ledger".to_string());
+            }
+            let send_inputs = Send {
+                balance_sender: balance,
+                amount: *amount,
+                auth_sender: Authorization::default(),
+                pkey_receiver,
+                asset: wallet.asset,
+                fee,
+                relayer,
+                note_random: random_exponent(),
+                transfer_random: random_exponent(),
+                debit_random: random_exponent(),
+                memo: memo.as_bytes().to_vec(),
+            };
+            wallet.state.balance = balance - debit;
+            (ActionKind::Send, Action::Send(send_inputs))
+        },
+        Command::SendMany { to, memo } => {
+            let balance = wallet.sync(&ledger, args.max_balance).unwrap_or_else(|e| fail(e));
+            let receivers: Vec<Receiver> = to
+                .iter()
+                .map(|to| {
+                    let (pkey, amount) = to
+                        .rsplit_once(':')
+                        .unwrap_or_else(|| fail(format!("Expected <public key>:<amount>, got {}", to)));
+                    let pkey = parse_pkey(pkey);
+                    if !ledger.pp.index_of.contains_key(&account_key(&pkey, &wallet.asset)) {
+                        fail(format!("The receiver {} has no account in this asset on the ledger", scalar_to_hex(&pkey)));
+                    }
+                    let amount = amount.parse().unwrap_or_else(|e| fail(format!("Invalid amount {}: {}", amount, e)));
+                    Receiver {
+                        pkey,
+                        amount,
+                        note_random: random_exponent(),
+                        transfer_random: random_exponent(),
+                        memo: memo.as_bytes().to_vec(),
+                    }
+                })
+                .collect();
+            let total = receivers
+                .iter()
+                .try_fold(0u64, |total, r| total.checked_add(r.amount))
+                .unwrap_or_else(|| fail("Total amount overflows".to_string()));
+            if total > balance {
+                fail(format!("Insufficient balance: {}", balance));
+            }
+            let send_inputs = SendMany {
+                balance_sender: balance,
+                auth_sender: Authorization::default(),
+                asset: wallet.asset,
+                receivers,
+                debit_random: random_exponent(),
+            };
+            wallet.state.balance = balance - total;
+            (ActionKind::SendMany, Action::SendMany(send_inputs))
+        },
+        Command::Withdraw { amount, recipient, fee, relayer } => {
+            let balance = wallet.sync(&ledger, args.max_balance).unwrap_or_else(|e| fail(e));
+            let (fee, relayer) = parse_fee(*fee, relayer);
+            let debit = debit(balance, *amount, fee);
+            let recipient = parse_address(recipient, "recipient");
+            let withdraw_inputs = Withdraw {
+                balance,
+                amount: *amount,
+                auth: Authorization::default(),
+                asset: wallet.asset,
+                recipient,
+                fee,
+                relayer,
+                debit_random: random_exponent(),
+            };
+            wallet.state.balance = balance - debit;
+            (ActionKind::Withdraw, Action::Withdraw(withdraw_inputs))
+        },
+        Command::Rotate { new_key: true } => {
+            let balance = wallet.sync(&ledger, args.max_balance).unwrap_or_else(|e| fail(e));
+            if wallet.secrets.pending_rekey.is_some() {
+                fail("A previous rotation has not been indexed yet".to_string());
+            }
+            if wallet.state.pending != 0 {
+                fail(format!("Roll over the pending {} first", wallet.state.pending));
+            }
+            let rekey = PendingRekey { spend_key: random_exponent(), random: random_exponent() };
+            let new_pkey = ElGamal::new(ledger.pp.g).from_skey(view_key(rekey.spend_key));
+            println!("New public key: {}", scalar_to_hex(&new_pkey));
+            let rekey_inputs = Rekey { balance, new_pkey, new_random: rekey.random };
+            wallet.secrets.pending_rekey = Some(rekey);
+            let rotate_inputs =
+                Rotate { auth: Authorization::default(), asset: wallet.asset, new_additive: [0u64; 4], rekey: Some(rekey_inputs) };
+            (ActionKind::Rotate, Action::Rotate(rotate_inputs))
+        },
+        Command::Rotate { new_key: false } => {
+            wallet.sync(&ledger, args.max_balance).unwrap_or_else(|e| fail(e));
+            if wallet.secrets.pending_rekey.is_some() {
+                fail("A previous rotation has not been indexed yet".to_string());
+            }
+            let rotate_inputs =
+                Rotate { auth: Authorization::default(), asset: wallet.asset, new_additive: random_exponent(), rekey: None };
+            (ActionKind::Rotate, Action::Rotate(rotate_inputs))
+        },
+        Command::Close => {
+            let balance = wallet.sync(&ledger, args.max_balance).unwrap_or_else(|e| fail(e));
+            if balance != 0 {
+                fail(format!("Withdraw or send the remaining balance of {} first", balance));
+            }
+            if wallet.state.pending != 0 {
+                fail(format!("Roll over the pending {} and spend it first", wallet.state.pending));
+            }
+            wallet.state.index = None;
+            (ActionKind::Close, Action::Close(Close { auth: Authorization::default(), asset: wallet.asset }))
+        },
+        Command::Attest { threshold, nonce } => {
+            let balance = wallet.sync(&ledger, args.max_balance).unwrap_or_else(|e| fail(e));
+            if balance < *threshold {
+                fail(format!("Insufficient balance: {}", balance));
+            }
+            let attest_inputs = Attest {
+                balance,
+                auth: Authorization::default(),
+                asset: wallet.asset,
+                threshold: *threshold,
+                nonce: hex::decode(nonce.trim_start_matches("0x"))
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .unwrap_or_else(|| fail("The nonce must be 32 hex bytes".to_string())),
+            };
+            (ActionKind::Attest, Action::Attest(attest_inputs))
+        },
+        Command::Rollover { shard } => {
+            let shard = match shard {
+                Some(shard) => *shard,
+                None => {
+                    wallet.sync(&ledger, args.max_balance).unwrap_or_else(|e| fail(e));
+                    ledger.pp.locate(wallet.state.index.unwrap()).0 as u64
+                },
+            };
+            let slots = shard as usize * ledger.pp.degree..(shard as usize + 1) * ledger.pp.degree;
+            if shard as usize >= ledger.pp.shards() || !slots.into_iter().any(|slot| ledger.pp.has_pending(slot)) {
+                fail(format!("Nothing is pending in shard {}", shard));
+            }
+            (ActionKind::Rollover, Action::Rollover(Rollover { shard }))
+        },
+    };
+
+    if action.authorization().is_some() {
+        // The spend key signs the action here, and only the signature goes into the witness
+        let spend_key = wallet.spend_key().unwrap_or_else(|e| fail(e));
+        action.sign(spend_key, &ledger.pp).unwrap_or_else(|e| fail(e));
+    }
+    println!("Public key: {}", scalar_to_hex(&pkey));
+    let prover = Prover::new(STATEMACHINE_ELF, args.system, args.max_retries, Duration::from_secs(args.poll_interval));
+    let mut bundle = prover.prove(&action, &ledger.phi, &ledger.pp).unwrap_or_else(|e| fail(e));
+    println!("Successfully generated proof!");
+    let path = match &args.out {
+        Some(path) => path.clone(),
+        None => bundle_dir().join(format!("wallet-{}.json", serde_json::to_value(kind).unwrap().as_str().unwrap())),
+    };
+    if let (Some(url), Some(state_machine)) = (&args.rpc_url, &args.state_machine) {
+        let rpc = RpcClient::new(url);
+        let signer = args.signer.as_ref().map(|path| load_signing_key(path, &args.signer_password).unwrap_or_else(|e| fail(e)));
+        let address = Address::from_str(state_machine).unwrap_or_else(|e| fail(format!("Invalid address: {}", e)));
+        let rebuild = |action: &mut Action, ledger: &Ledger| {
+            wallet.state = synced.clone();
+            rebuild_witness(&mut wallet, action, ledger, args.max_balance)
+        };
+        let submit = |bundle: &ProofBundle| {
+            bundle.save(&path)?;
+            println!("Proof bundle: {}", path.display());
+            if let Some(key) = &signer {
+                println!("Transaction hash: {}", send(&rpc, bundle, address, key, args.gas_limit)?);
+            }
+            Ok(())
+        };
+        bundle = prover
+            .keep_current(bundle, &mut action, &mut ledger, &rpc, state_machine, rebuild, submit)
+            .unwrap_or_else(|e| fail(e));
+        ledger.save(&args.ledger).unwrap_or_else(|e| fail(e));
+    }
+    bundle.save(&path).unwrap_or_else(|e| fail(e));
+    println!("Proof bundle: {}", path.display());
+    wallet.save().unwrap_or_else(|e| fail(e));
+}
@@ -0,0 +1,135 @@
+//! A self-contained proof artifact that can be stored, shipped and verified later, away from the
+//! process that generated it.
+
+use serde::{Deserialize, Serialize};
+use sp1_sdk::{HashableKey, SP1Proof, SP1ProofWithPublicValues, SP1VerifyingKey};
+use state_machine_lib::{ActionKind, PublicParams};
+use std::path::{Path, PathBuf};
+
+/// The proof system a bundle was generated with.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ProofSystem {
+    Core,
+    Compressed,
+    Plonk,
+    Groth16,
+}
+
+impl ProofSystem {
+    fn of(proof: &SP1ProofWithPublicValues) -> ProofSystem {
+        match proof.proof {
+            SP1Proof::Core(_) => ProofSystem::Core,
+            SP1Proof::Compressed(_) => ProofSystem::Compressed,
+            SP1Proof::Plonk(_) => ProofSystem::Plonk,
+            SP1Proof::Groth16(_) => ProofSystem::Groth16,
+        }
+    }
+
+    /// Whether proofs of this system can be checked by the on-chain SP1 verifier.
+    pub fn is_onchain(&self) -> bool {
+        matches!(self, ProofSystem::Plonk | ProofSystem::Groth16)
+    }
+}
+
+/// A saved proof together with everything needed to check it against a program and an SRS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofBundle {
+    pub version: u32,
+    pub action: ActionKind,
+    pub system: ProofSystem,
+    /// The program verification key hash, as returned by `vk.bytes32()`.
+    pub vkey: String,
+    pub srs_fingerprint: String,
+    pub public_values: String,
+    /// The proof bytes accepted by the on-chain verifier, empty for core and compressed proofs.
+    pub proof: String,
+    /// The full SP1 proof, bincode-encoded, for verification with the SDK.
+    pub sp1_proof: String,
+}
+
+impl ProofBundle {
+    pub const VERSION: u32 = 1;
+
+    pub fn new(
+        action: ActionKind,
+        proof: &SP1ProofWithPublicValues,
+        vk: &SP1VerifyingKey,
+        pp: &PublicParams,
+    ) -> ProofBundle {
+        let system = ProofSystem::of(proof);
+        let onchain_proof = if system.is_onchain() { proof.bytes() } else { Vec::new() };
+        let sp1_proof = bincode::serialize(proof).expect("failed to serialize proof");
+        ProofBundle {
+            version: Self::VERSION,
+            action,
+            system,
+            vkey: vk.bytes32().to_string(),
+            srs_fingerprint: format!("0x{}", hex::encode(pp.srs_fingerprint())),
+            public_values: format!("0x{}", hex::encode(proof.public_values.as_slice())),
+            proof: format!("0x{}", hex::encode(onchain_proof)),
+            sp1_proof: format!("0x{}", hex::encode(sp1_proof)),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<ProofBundle, String> {
+        let json = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Malformed proof bundle: {}", e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path.as_ref(), json)
+            .map_err(|e| format!("Failed to write {}: {}", path.as_ref().display(), e))
+    }
+
+    pub fn public_values_bytes(&self) -> Result<Vec<u8>, String> {
+        decode_hex(&self.public_values)
+    }
+
+    pub fn proof_bytes(&self) -> Result<Vec<u8>, String> {
+        decode_hex(&self.proof)
+    }
+
+    /// Checks the bundle metadata against the program key and SRS, and returns the embedded SP1
+    /// proof ready to be handed to `ProverClient::verify`.
+    pub fn check(
+        &self,
+        vk: &SP1VerifyingKey,
+        pp: &PublicParams,
+    ) -> Result<SP1ProofWithPublicValues, String> {
+        if self.version != Self::VERSION {
+            return Err(format!("Unsupported bundle version {}", self.version));
+        }
+        if self.vkey != vk.bytes32() {
+            return Err(format!("Verification key mismatch: bundle has {}, program has {}", self.vkey, vk.bytes32()));
+        }
+        let fingerprint = format!("0x{}", hex::encode(pp.srs_fingerprint()));
+        if self.srs_fingerprint != fingerprint {
+            return Err(format!("SRS mismatch: bundle has {}, expected {}", self.srs_fingerprint, fingerprint));
+        }
+        let proof: SP1ProofWithPublicValues = bincode::deserialize(&decode_hex(&self.sp1_proof)?)
+            .map_err(|e| format!("Malformed SP1 proof: {}", e))?;
+        if ProofSystem::of(&proof) != self.system {
+            return Err("Proof system does not match the embedded proof".to_string());
+        }
+        if proof.public_values.as_slice() != self.public_values_bytes()?.as_slice() {
+            return Err("Public values do not match the embedded proof".to_string());
+        }
+        Ok(proof)
+    }
+}
+
+/// The directory bundles are written to by the scripts.
+pub fn bundle_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../proofs")
+}
+
+pub(crate) fn decode_hex(s: &str) -> Result<Vec<u8>, String> {
+    hex::decode(s.trim_start_matches("0x")).map_err(|e| format!("Invalid hex: {}", e))
+}
@@ -0,0 +1,58 @@
+//! Rust bindings for the state machine contracts and access to their compiled artifacts.
+//!
+//! The artifacts are produced by `forge build` in the `contracts` directory.
+
+use alloy_sol_types::sol;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+sol! {
+    contract StateMachineVerifier {
+        constructor(address _verifier, bytes32 _stateMachineProgramVKey);
+    }
+
+    contract StateMachine {
+        constructor(address _verifier, bytes _phi, bytes32 _auditor, uint256 _epochLength);
+
+        function getCurrentState(uint64 shard) public view returns (bytes memory);
+        function epoch() public view returns (uint256);
+        function ciphertexts(uint64 shard, uint64 index) public view returns (bytes32 t, bytes32 v);
+        function pendingTransfers(uint64 shard, uint64 index) public view returns (bytes32 t, bytes32 v);
+        function accounts(uint64 shard, uint64 index) public view returns (bytes32);
+        function pendingSlots(uint64 shard) public view returns (uint64);
+        function freeListHash() public view returns (bytes32);
+        function totalSupply(address asset) public view returns (uint256);
+        function solvent(address asset) public view returns (bool);
+        function checkAttestation(bytes calldata _publicValues, bytes calldata _proofBytes) public view;
+        function deposit(bytes calldata _publicValues, bytes calldata _proofBytes) public payable;
+        function send(bytes calldata _publicValues, bytes calldata _proofBytes) public;
+        function sendMany(bytes calldata _publicValues, bytes calldata _proofBytes) public;
+        function withdraw(bytes calldata _publicValues, bytes calldata _proofBytes) public;
+        function rotate(bytes calldata _publicValues, bytes calldata _proofBytes) public;
+        function close(bytes calldata _publicValues, bytes calldata _proofBytes) public;
+        function rollover(bytes calldata _publicValues, bytes calldata _proofBytes) public;
+    }
+}
+
+#[derive(Deserialize)]
+struct Artifact {
+    bytecode: ArtifactBytecode,
+}
+
+#[derive(Deserialize)]
+struct ArtifactBytecode {
+    object: String,
+}
+
+/// Loads the creation bytecode of `contract` from `contracts/out/<file>/<contract>.json`.
+pub fn load_bytecode(file: &str, contract: &str) -> Result<Vec<u8>, String> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../contracts/out")
+        .join(file)
+        .join(format!("{}.json", contract));
+    let json = std::fs::read_to_string(&path).map_err(|e| {
+        format!("Failed to read {} ({}), run `forge build` in contracts first", path.display(), e)
+    })?;
+    let artifact: Artifact = serde_json::from_str(&json).map_err(|e| format!("Malformed artifact: {}", e))?;
+    hex::decode(artifact.bytecode.object.trim_start_matches("0x")).map_err(|e| format!("Invalid bytecode: {}", e))
+}
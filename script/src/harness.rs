@@ -0,0 +1,298 @@
+//! An in-memory EVM with the state machine contracts deployed, for end-to-end tests of the
+//! contracts against proofs produced by the scripts, without a node.
+//!
+//! The SP1 verifier is `SP1MockVerifier`, which accepts empty proof bytes, so the harness is meant
+//! to be driven with proofs generated by the mock prover (`SP1_PROVER=mock`).
+
+use crate::contracts::{load_bytecode, StateMachine, StateMachineVerifier};
+use alloy_consensus::TxEnvelope;
+use alloy_eips::eip2718::Decodable2718;
+use alloy_sol_types::{SolCall, SolConstructor};
+use revm::db::{CacheDB, EmptyDB};
+use revm::primitives::{
+    Address, Bytes, ExecutionResult, Output, SpecId, TxKind, B256, U256,
+};
+use revm::{Database, Evm};
+use sp1_bls12_381::{G1Affine, Scalar};
+use state_machine_lib::public_values::g1_to_evm;
+
+pub struct EvmHarness {
+    evm: Evm<'static, (), CacheDB<EmptyDB>>,
+    pub deployer: Address,
+    pub sp1_verifier: Address,
+    pub verifier: Address,
+    pub state_machine: Address,
+}
+
+impl EvmHarness {
+    /// Deploys `SP1MockVerifier`, `StateMachineVerifier` for the program `vkey`, and
+    /// `StateMachine` starting at `phi`, escrowing transfer amounts to `auditor` if given and
+    /// rolling shards over once per `epoch_length` blocks. The EVM runs Prague, since the contract
+    /// adds phis with the EIP-2537 precompiles.
+    pub fn new(vkey: B256, phi: &G1Affine, auditor: Option<Scalar>, epoch_length: u64) -> Result<EvmHarness, String> {
+        let evm = Evm::builder().with_db(CacheDB::new(EmptyDB::default())).with_spec_id(SpecId::PRAGUE).build();
+        let mut harness = EvmHarness {
+            evm,
+            deployer: Address::repeat_byte(0xde),
+            sp1_verifier: Address::ZERO,
+            verifier: Address::ZERO,
+            state_machine: Address::ZERO,
+        };
+        harness.fund(harness.deployer, U256::from(u64::MAX));
+
+        let code = load_bytecode("SP1MockVerifier.sol", "SP1MockVerifier")?;
+        harness.sp1_verifier = harness.deploy(harness.deployer, code)?;
+
+        let mut code = load_bytecode("StateMachineVerifier.sol", "StateMachineVerifier")?;
+        code.extend(
+            StateMachineVerifier::constructorCall {
+                _verifier: harness.sp1_verifier,
+                _stateMachineProgramVKey: vkey,
+            }
+            .abi_encode(),
+        );
+        harness.verifier = harness.deploy(harness.deployer, code)?;
+
+        let mut code = load_bytecode("StateMachine.sol", "StateMachine")?;
+        code.extend(
+            StateMachine::constructorCall {
+                _verifier: harness.verifier,
+                _phi: g1_to_evm(phi).into(),
+                _auditor: B256::from(auditor.unwrap_or(Scalar::zero()).to_bytes()),
+                _epochLength: U256::from(epoch_length),
+            }
+            .abi_encode(),
+        );
+        harness.state_machine = harness.deploy(harness.deployer, code)?;
+        Ok(harness)
+    }
+
+    /// Sets the balance of `account`.
+    pub fn fund(&mut self, account: Address, balance: U256) {
+        let db = self.evm.db_mut();
+        let mut info = db.basic(account).ok().flatten().unwrap_or_default();
+        info.balance = balance;
+        db.insert_account_info(account, info);
+    }
+
+    pub fn balance(&mut self, account: Address) -> U256 {
+        self.evm.db_mut().basic(account).ok().flatten().map(|info| info.balance).unwrap_or_default()
+    }
+
+    pub fn deploy(&mut self, from: Address, code: Vec<u8>) -> Result<Address, String> {
+        match self.transact(from, TxKind::Create, code, U256::ZERO, true)? {
+            Output::Create(_, Some(address)) => Ok(address),
+            _ => Err("Deployment did not create a contract".to_string()),
+        }
+    }
+
+    /// Executes a call and commits its state changes.
+    pub fn call(&mut self, from: Address, to: Address, data: Vec<u8>, value: U256) -> Result<Bytes, String> {
+        match self.transact(from, TxKind::Call(to), data, value, true)? {
+            Output::Call(output) => Ok(output),
+            Output::Create(output, _) => Ok(output),
+        }
+    }
+
+    /// Executes a call without committing it.
+    pub fn view(&mut self, to: Address, data: Vec<u8>) -> Result<Bytes, String> {
+        match self.transact(self.deployer, TxKind::Call(to), data, U256::ZERO, false)? {
+            Output::Call(output) => Ok(output),
+            Output::Create(output, _) => Ok(output),
+        }
+    }
+
+    fn transact(&mut self, from: Address, to: TxKind, data: Vec<u8>, value: U256, commit: bool) -> Result<Output, String> {
+        let tx = self.evm.tx_mut();
+        tx.caller = from;
+        tx.transact_to = to;
+        tx.data = data.into();
+        tx.value = value;
+        tx.gas_limit = 30_000_000;
+        tx.gas_price = U256::ZERO;
+        tx.gas_priority_fee = None;
+        tx.nonce = None;
+        tx.chain_id = None;
+        self.execute(commit)
+    }
+
+    fn execute(&mut self, commit: bool) -> Result<Output, String> {
+        let result = if commit {
+            self.evm.transact_commit().map_err(|e| format!("{:?}", e))?
+        } else {
+            self.evm.transact().map_err(|e| format!("{:?}", e))?.result
+        };
+        match result {
+            ExecutionResult::Success { output, .. } => Ok(output),
+            ExecutionResult::Revert { output, .. } => Err(format!("Reverted: 0x{}", hex::encode(output))),
+            ExecutionResult::Halt { reason, .. } => Err(format!("Halted: {:?}", reason)),
+        }
+    }
+
+    /// Moves the block number forward, e.g. into the next epoch.
+    pub fn advance_blocks(&mut self, blocks: u64) {
+        let block = self.evm.block_mut();
+        block.number += U256::from(blocks);
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.evm.cfg().chain_id
+    }
+
+    pub fn nonce(&mut self, account: Address) -> u64 {
+        self.evm.db_mut().basic(account).ok().flatten().map(|info| info.nonce).unwrap_or_default()
+    }
+
+    /// Decodes, checks and executes a signed EIP-2718 transaction the way a node would on
+    /// `eth_sendRawTransaction`, so the transaction builder can be tested without one.
+    pub fn submit_raw_transaction(&mut self, raw: &[u8]) -> Result<Bytes, String> {
+        let envelope = TxEnvelope::decode_2718(&mut &raw[..]).map_err(|e| format!("Invalid transaction: {}", e))?;
+        let signed = match envelope {
+            TxEnvelope::Eip1559(signed) => signed,
+            _ => return Err("Only EIP-1559 transactions are supported".to_string()),
+        };
+        let from = signed.recover_signer().map_err(|e| format!("Invalid signature: {}", e))?;
+        let tx = signed.tx();
+        let env = self.evm.tx_mut();
+        env.caller = from;
+        env.transact_to = tx.to;
+        env.data = tx.input.clone();
+        env.value = tx.value;
+        env.gas_limit = tx.gas_limit as u64;
+        env.gas_price = U256::from(tx.max_fee_per_gas);
+        env.gas_priority_fee = Some(U256::from(tx.max_priority_fee_per_gas));
+        env.nonce = Some(tx.nonce);
+        env.chain_id = Some(tx.chain_id);
+        match self.execute(true)? {
+            Output::Call(output) => Ok(output),
+            Output::Create(output, _) => Ok(output),
+        }
+    }
+
+    pub fn deposit(&mut self, from: Address, public_values: &[u8], proof: &[u8], amount: u64) -> Result<(), String> {
+        let data = StateMachine::depositCall {
+            _publicValues: public_values.to_vec().into(),
+            _proofBytes: proof.to_vec().into(),
+        }
+        .abi_encode();
+        self.call(from, self.state_machine, data, U256::from(amount)).map(|_| ())
+    }
+
+    pub fn send(&mut self, from: Address, public_values: &[u8], proof: &[u8]) -> Result<(), String> {
+        let data = StateMachine::sendCall {
+            _publicValues: public_values.to_vec().into(),
+            _proofBytes: proof.to_vec().into(),
+        }
+        .abi_encode();
+        self.call(from, self.state_machine, data, U256::ZERO).map(|_| ())
+    }
+
+    pub fn send_many(&mut self, from: Address, public_values: &[u8], proof: &[u8]) -> Result<(), String> {
+        let data = StateMachine::sendManyCall {
+            _publicValues: public_values.to_vec().into(),
+            _proofBytes: proof.to_vec().into(),
+        }
+        .abi_encode();
+        self.call(from, self.state_machine, data, U256::ZERO).map(|_| ())
+    }
+
+    pub fn withdraw(&mut self, from: Address, public_values: &[u8], proof: &[u8]) -> Result<(), String> {
+        let data = StateMachine::withdrawCall {
+            _publicValues: public_values.to_vec().into(),
+            _proofBytes: proof.to_vec().into(),
+        }
+        .abi_encode();
+        self.call(from, self.state_machine, data, U256::ZERO).map(|_| ())
+    }
+
+    pub fn rotate(&mut self, from: Address, public_values: &[u8], proof: &[u8]) -> Result<(), String> {
+        let data = StateMachine::rotateCall {
+            _publicValues: public_values.to_vec().into(),
+            _proofBytes: proof.to_vec().into(),
+        }
+        .abi_encode();
+        self.call(from, self.state_machine, data, U256::ZERO).map(|_| ())
+    }
+
+    pub fn close(&mut self, from: Address, public_values: &[u8], proof: &[u8]) -> Result<(), String> {
+        let data = StateMachine::closeCall {
+            _publicValues: public_values.to_vec().into(),
+            _proofBytes: proof.to_vec().into(),
+        }
+        .abi_encode();
+        self.call(from, self.state_machine, data, U256::ZERO).map(|_| ())
+    }
+
+    pub fn rollover(&mut self, from: Address, public_values: &[u8], proof: &[u8]) -> Result<(), String> {
+        let data = StateMachine::rolloverCall {
+            _publicValues: public_values.to_vec().into(),
+            _proofBytes: proof.to_vec().into(),
+        }
+        .abi_encode();
+        self.call(from, self.state_machine, data, U256::ZERO).map(|_| ())
+    }
+
+    /// The phi currently stored by `StateMachine` for `shard`, in the EIP-2537 encoding.
+    pub fn phi(&mut self, shard: u64) -> Result<Vec<u8>, String> {
+        let output = self.view(self.state_machine, StateMachine::getCurrentStateCall { shard }.abi_encode())?;
+        let decoded = StateMachine::getCurrentStateCall::abi_decode_returns(&output, true).map_err(|e| e.to_string())?;
+        Ok(decoded._0.to_vec())
+    }
+
+    /// The ciphertext `(t, v)` stored by `StateMachine` for the slot at `index` in `shard`.
+    pub fn ciphertext(&mut self, shard: u64, index: u64) -> Result<(B256, B256), String> {
+        let output = self.view(self.state_machine, StateMachine::ciphertextsCall { shard, index }.abi_encode())?;
+        let decoded = StateMachine::ciphertextsCall::abi_decode_returns(&output, true).map_err(|e| e.to_string())?;
+        Ok((decoded.t, decoded.v))
+    }
+
+    /// The registry key of the account `StateMachine` stores for the slot at `index` in `shard`.
+    pub fn account(&mut self, shard: u64, index: u64) -> Result<B256, String> {
+        let output = self.view(self.state_machine, StateMachine::accountsCall { shard, index }.abi_encode())?;
+        let decoded = StateMachine::accountsCall::abi_decode_returns(&output, true).map_err(|e| e.to_string())?;
+        Ok(decoded._0)
+    }
+
+    /// The pending transfers `(t, v)` stored by `StateMachine` for the slot at `index` in `shard`.
+    pub fn pending_transfers(&mut self, shard: u64, index: u64) -> Result<(B256, B256), String> {
+        let output = self.view(self.state_machine, StateMachine::pendingTransfersCall { shard, index }.abi_encode())?;
+        let decoded = StateMachine::pendingTransfersCall::abi_decode_returns(&output, true).map_err(|e| e.to_string())?;
+        Ok((decoded.t, decoded.v))
+    }
+
+    /// The number of slots of `shard` with pending transfers, as counted by `StateMachine`.
+    pub fn pending_slots(&mut self, shard: u64) -> Result<u64, String> {
+        let output = self.view(self.state_machine, StateMachine::pendingSlotsCall { shard }.abi_encode())?;
+        let decoded = StateMachine::pendingSlotsCall::abi_decode_returns(&output, true).map_err(|e| e.to_string())?;
+        Ok(decoded._0)
+    }
+
+    /// The free list hash stored by `StateMachine`.
+    pub fn free_list_hash(&mut self) -> Result<B256, String> {
+        let output = self.view(self.state_machine, StateMachine::freeListHashCall {}.abi_encode())?;
+        let decoded = StateMachine::freeListHashCall::abi_decode_returns(&output, true).map_err(|e| e.to_string())?;
+        Ok(decoded._0)
+    }
+
+    /// The total supply of `asset` counted by `StateMachine`.
+    pub fn total_supply(&mut self, asset: [u8; 20]) -> Result<U256, String> {
+        let output = self.view(self.state_machine, StateMachine::totalSupplyCall { asset: asset.into() }.abi_encode())?;
+        let decoded = StateMachine::totalSupplyCall::abi_decode_returns(&output, true).map_err(|e| e.to_string())?;
+        Ok(decoded._0)
+    }
+
+    /// Whether `StateMachine` holds at least the total supply of `asset`.
+    pub fn solvent(&mut self, asset: [u8; 20]) -> Result<bool, String> {
+        let output = self.view(self.state_machine, StateMachine::solventCall { asset: asset.into() }.abi_encode())?;
+        let decoded = StateMachine::solventCall::abi_decode_returns(&output, true).map_err(|e| e.to_string())?;
+        Ok(decoded._0)
+    }
+
+    /// Checks an attestation against the state `StateMachine` holds, failing with the revert
+    /// reason if it does not match.
+    pub fn check_attestation(&mut self, public_values: &[u8], proof: &[u8]) -> Result<(), String> {
+        let call = StateMachine::checkAttestationCall { _publicValues: public_values.to_vec().into(), _proofBytes: proof.to_vec().into() };
+        self.view(self.state_machine, call.abi_encode())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,386 @@
+//! Rebuilds the ledger behind the on-chain `phi` from the `StateMachine` transactions that were
+//! accepted, so any party can recover the ciphertexts and key registry without the scripts that
+//! produced them.
+//!
+//! Transactions come either from a JSON-RPC endpoint or from a local dump, a JSON array of
+//! objects with an `input` field and optional `hash`, `blockNumber` and `status` fields.
+
+use crate::bundle::decode_hex;
+use crate::contracts::StateMachine;
+use crate::rpc::RpcClient;
+use alloy_primitives::U256;
+use alloy_sol_types::SolCall;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sp1_bls12_381::{G1Affine, Scalar};
+use state_machine_lib::public_values::{decode_phi, decode_scalar, g1_to_hex, scalar_to_hex};
+use state_machine_lib::{account_key, replay, ActionKind, DecodedPublicValues, Note, PublicParams, KZG, NATIVE_ASSET};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A call to the `StateMachine` contract, as read from the chain or from a dump.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedTransaction {
+    #[serde(default)]
+    pub hash: Option<String>,
+    #[serde(default)]
+    pub block_number: Option<u64>,
+    pub input: String,
+    /// Whether the transaction succeeded. Missing means it did.
+    #[serde(default)]
+    pub status: Option<bool>,
+}
+
+impl IndexedTransaction {
+    pub fn succeeded(&self) -> bool {
+        self.status.unwrap_or(true)
+    }
+}
+
+/// Decodes the public values out of the calldata of a `StateMachine` call.
+pub fn decode_call(input: &[u8]) -> Result<DecodedPublicValues, String> {
+    if input.len() < 4 {
+        return Err("Calldata is shorter than a selector".to_string());
+    }
+    let selector: [u8; 4] = input[..4].try_into().unwrap();
+    let (kind, public_values) = match selector {
+        StateMachine::depositCall::SELECTOR => {
+            let call = StateMachine::depositCall::abi_decode(input, true).map_err(|e| e.to_string())?;
+            (ActionKind::Deposit, call._publicValues)
+        },
+        StateMachine::sendCall::SELECTOR => {
+            let call = StateMachine::sendCall::abi_decode(input, true).map_err(|e| e.to_string())?;
+            (ActionKind::Send, call._publicValues)
+        },
+        StateMachine::sendManyCall::SELECTOR => {
+            let call = StateMachine::sendManyCall::abi_decode(input, true).map_err(|e| e.to_string())?;
+            (ActionKind::SendMany, call._publicValues)
+        },
+        StateMachine::withdrawCall::SELECTOR => {
+            let call = StateMachine::withdrawCall::abi_decode(input, true).map_err(|e| e.to_string())?;
+            (ActionKind::Withdraw, call._publicValues)
+        },
+        StateMachine::rotateCall::SELECTOR => {
+            let call = StateMachine::rotateCall::abi_decode(input, true).map_err(|e| e.to_string())?;
+            (ActionKind::Rotate, call._publicValues)
+        },
+        StateMachine::closeCall::SELECTOR => {
+            let call = StateMachine::closeCall::abi_decode(input, true).map_err(|e| e.to_string())?;
+            (ActionKind::Close, call._publicValues)
+        },
+        StateMachine::rolloverCall::SELECTOR => {
+            let call = StateMachine::rolloverCall::abi_decode(input, true).map_err(|e| e.to_string())?;
+            (ActionKind::Rollover, call._publicValues)
+        },
+        _ => return Err(format!("Unknown selector 0x{}", hex::encode(selector))),
+    };
+    DecodedPublicValues::decode(kind, &public_values)
+}
+
+/// Reads a hex token address, where an empty string, as written before assets were tracked, is
+/// ETH.
+pub fn decode_asset(s: &str) -> Result<[u8; 20], String> {
+    if s.is_empty() {
+        return Ok(NATIVE_ASSET);
+    }
+    decode_hex(s)?.try_into().map_err(|_| format!("Invalid asset {}", s))
+}
+
+pub fn asset_to_hex(asset: &[u8; 20]) -> String {
+    format!("0x{}", hex::encode(asset))
+}
+
+/// A transfer note committed by a send, in the order the sends were applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerNote {
+    pub pkey_sender: Scalar,
+    pub pkey_receiver: Scalar,
+    pub asset: [u8; 20],
+    pub note: Note,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteSnapshot {
+    pub pkey_sender: String,
+    pub pkey_receiver: String,
+    #[serde(default)]
+    pub asset: String,
+    pub ephemeral: String,
+    pub ciphertext: String,
+    pub tag: String,
+}
+
+impl LedgerNote {
+    fn snapshot(&self) -> NoteSnapshot {
+        NoteSnapshot {
+            pkey_sender: scalar_to_hex(&self.pkey_sender),
+            pkey_receiver: scalar_to_hex(&self.pkey_receiver),
+            asset: asset_to_hex(&self.asset),
+            ephemeral: scalar_to_hex(&self.note.ephemeral),
+            ciphertext: format!("0x{}", hex::encode(&self.note.ciphertext)),
+            tag: format!("0x{}", hex::encode(self.note.tag)),
+        }
+    }
+
+    fn from_snapshot(snapshot: &NoteSnapshot) -> Result<LedgerNote, String> {
+        Ok(LedgerNote {
+            pkey_sender: decode_scalar(&decode_hex(&snapshot.pkey_sender)?)?,
+            pkey_receiver: decode_scalar(&decode_hex(&snapshot.pkey_receiver)?)?,
+            asset: decode_asset(&snapshot.asset)?,
+            note: Note {
+                ephemeral: decode_scalar(&decode_hex(&snapshot.ephemeral)?)?,
+                ciphertext: decode_hex(&snapshot.ciphertext)?,
+                tag: decode_hex(&snapshot.tag)?.try_into().map_err(|_| "Invalid note tag".to_string())?,
+            },
+        })
+    }
+}
+
+/// The ledger state as written to disk, with every point and scalar hex-encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerSnapshot {
+    pub degree: usize,
+    pub srs_fingerprint: String,
+    /// One phi per shard.
+    pub phis: Vec<String>,
+    pub idx: usize,
+    pub v: Vec<String>,
+    pub t: Vec<String>,
+    /// The transfers of every slot not rolled over yet, as ElGamal pairs. Empty in ledgers written
+    /// before transfers were held as pending, which have none.
+    #[serde(default)]
+    pub pending_t: Vec<String>,
+    #[serde(default)]
+    pub pending_v: Vec<String>,
+    pub pkeys: Vec<String>,
+    /// The token of every slot. Empty in ledgers written before assets were tracked, which only
+    /// hold ETH.
+    #[serde(default)]
+    pub assets: Vec<String>,
+    #[serde(default)]
+    pub free: Vec<usize>,
+    #[serde(default)]
+    pub notes: Vec<NoteSnapshot>,
+    /// The public key transfer amounts are escrowed to, if the contract has an auditor.
+    #[serde(default)]
+    pub auditor: Option<String>,
+    /// The total supply of every asset deposited so far, by token address.
+    #[serde(default)]
+    pub supply: BTreeMap<String, u64>,
+    pub last_block: Option<u64>,
+}
+
+/// The public parameters and current commitments of a replayed ledger.
+pub struct Ledger {
+    pub pp: PublicParams,
+    /// The phi of every shard.
+    pub phi: Vec<G1Affine>,
+    pub notes: Vec<LedgerNote>,
+    /// The last block whose transactions have been applied.
+    pub last_block: Option<u64>,
+}
+
+impl Ledger {
+    /// An empty ledger, matching a freshly deployed contract with the given auditor.
+    pub fn new(degree: usize, auditor: Option<Scalar>) -> Ledger {
+        let mut pp = PublicParams::setup(degree);
+        pp.auditor = auditor;
+        let kzg = KZG::new(pp.g1_lagrange_basis.clone());
+        let phi = vec![kzg.commit(pp.v.clone()).unwrap()];
+        Ledger { pp, phi, notes: Vec::new(), last_block: None }
+    }
+
+    /// Applies a transaction, returning whether it changed the ledger. Reverted transactions are
+    /// skipped; a successful one that does not replay means the ledger is out of sync.
+    pub fn apply(&mut self, tx: &IndexedTransaction) -> Result<bool, String> {
+        if let Some(block) = tx.block_number {
+            self.last_block = Some(self.last_block.map_or(block, |last| last.max(block)));
+        }
+        if !tx.succeeded() {
+            return Ok(false);
+        }
+        let label = tx.hash.clone().unwrap_or("transaction".to_string());
+        let values = decode_call(&decode_hex(&tx.input)?).map_err(|e| format!("{}: {}", label, e))?;
+        self.phi = replay(&mut self.pp, &self.phi, &values).map_err(|e| format!("{}: {}", label, e))?;
+        match values {
+            DecodedPublicValues::Send(send) => self.notes.push(LedgerNote {
+                pkey_sender: send.pkey_sender,
+                pkey_receiver: send.pkey_receiver,
+                asset: send.asset,
+                note: send.note,
+            }),
+            DecodedPublicValues::SendMany(send) => {
+                self.notes.extend(send.receivers.into_iter().map(|receiver| LedgerNote {
+                    pkey_sender: send.pkey_sender,
+                    pkey_receiver: receiver.pkey,
+                    asset: send.asset,
+                    note: receiver.note,
+                }));
+            },
+            _ => {},
+        }
+        Ok(true)
+    }
+
+    /// Applies the calls to `state_machine` mined since the last block applied, and returns them.
+    pub fn follow(&mut self, rpc: &RpcClient, state_machine: &str) -> Result<Vec<IndexedTransaction>, String> {
+        let to_block = rpc.block_number()?;
+        let from_block = self.last_block.map_or(0, |last| last + 1);
+        if from_block > to_block {
+            return Ok(Vec::new());
+        }
+        let transactions = fetch_transactions(rpc, state_machine, from_block, to_block)?;
+        for tx in transactions.iter() {
+            self.apply(tx)?;
+        }
+        self.last_block = Some(to_block);
+        Ok(transactions)
+    }
+
+    pub fn snapshot(&self) -> LedgerSnapshot {
+        LedgerSnapshot {
+            degree: self.pp.degree,
+            srs_fingerprint: format!("0x{}", hex::encode(self.pp.srs_fingerprint())),
+            phis: self.phi.iter().map(g1_to_hex).collect(),
+            idx: self.pp.idx,
+            v: self.pp.v.iter().map(scalar_to_hex).collect(),
+            t: self.pp.t.iter().map(scalar_to_hex).collect(),
+            pending_t: self.pp.pending_t.iter().map(scalar_to_hex).collect(),
+            pending_v: self.pp.pending_v.iter().map(scalar_to_hex).collect(),
+            pkeys: self.pp.pkeys.iter().map(scalar_to_hex).collect(),
+            assets: self.pp.assets.iter().map(asset_to_hex).collect(),
+            free: self.pp.free.clone(),
+            notes: self.notes.iter().map(LedgerNote::snapshot).collect(),
+            auditor: self.pp.auditor.as_ref().map(scalar_to_hex),
+            supply: self.pp.supply.iter().map(|(asset, supply)| (asset_to_hex(asset), *supply)).collect(),
+            last_block: self.last_block,
+        }
+    }
+
+    pub fn from_snapshot(snapshot: &LedgerSnapshot) -> Result<Ledger, String> {
+        let mut pp = PublicParams::setup(snapshot.degree);
+        let fingerprint = format!("0x{}", hex::encode(pp.srs_fingerprint()));
+        if snapshot.srs_fingerprint != fingerprint {
+            return Err(format!("SRS mismatch: snapshot has {}, expected {}", snapshot.srs_fingerprint, fingerprint));
+        }
+        let slots = snapshot.phis.len() * pp.degree;
+        if snapshot.phis.is_empty() || snapshot.v.len() != slots || snapshot.t.len() != slots || snapshot.pkeys.len() != slots {
+            return Err("Snapshot vectors do not match its shards".to_string());
+        }
+        if snapshot.idx > slots || snapshot.free.iter().any(|idx| *idx >= snapshot.idx) {
+            return Err("Snapshot free list does not match its index".to_string());
+        }
+        pp.idx = snapshot.idx;
+        pp.v = snapshot.v.iter().map(|s| decode_scalar(&decode_hex(s)?)).collect::<Result<_, _>>()?;
+        pp.t = snapshot.t.iter().map(|s| decode_scalar(&decode_hex(s)?)).collect::<Result<_, _>>()?;
+        pp.pkeys = snapshot.pkeys.iter().map(|s| decode_scalar(&decode_hex(s)?)).collect::<Result<_, _>>()?;
+        if !snapshot.pending_t.is_empty() {
+            if snapshot.pending_t.len() != slots || snapshot.pending_v.len() != slots {
+                return Err("Snapshot vectors do not match its shards".to_string());
+            }
+            pp.pending_t = snapshot.pending_t.iter().map(|s| decode_scalar(&decode_hex(s)?)).collect::<Result<_, _>>()?;
+            pp.pending_v = snapshot.pending_v.iter().map(|s| decode_scalar(&decode_hex(s)?)).collect::<Result<_, _>>()?;
+        } else {
+            pp.pending_t = vec![Scalar::zero(); slots];
+            pp.pending_v = vec![Scalar::zero(); slots];
+        }
+        if !snapshot.assets.is_empty() {
+            if snapshot.assets.len() != slots {
+                return Err("Snapshot vectors do not match its shards".to_string());
+            }
+            pp.assets = snapshot.assets.iter().map(|s| decode_asset(s)).collect::<Result<_, _>>()?;
+        } else {
+            pp.assets = vec![NATIVE_ASSET; slots];
+        }
+        pp.free = snapshot.free.clone();
+        pp.auditor = snapshot.auditor.as_ref().map(|s| decode_scalar(&decode_hex(s)?)).transpose()?;
+        pp.supply = snapshot.supply.iter().map(|(asset, supply)| Ok((decode_asset(asset)?, *supply))).collect::<Result<_, String>>()?;
+        for (idx, pkey) in pp.pkeys.iter().enumerate() {
+            if *pkey != Scalar::zero() {
+                pp.index_of.insert(account_key(pkey, &pp.assets[idx]), idx);
+            }
+        }
+        let phi: Vec<G1Affine> = snapshot.phis.iter().map(|s| decode_phi(&decode_hex(s)?)).collect::<Result<_, _>>()?;
+        let kzg = KZG::new(pp.g1_lagrange_basis.clone());
+        for (shard, v) in pp.v.chunks(pp.degree).enumerate() {
+            if kzg.commit(v.to_vec()).unwrap() != phi[shard] {
+                return Err(format!("Snapshot ciphertexts do not commit to the phi of shard {}", shard));
+            }
+        }
+        let notes = snapshot.notes.iter().map(LedgerNote::from_snapshot).collect::<Result<_, _>>()?;
+        Ok(Ledger { pp, phi, notes, last_block: snapshot.last_block })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Ledger, String> {
+        let json = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+        let snapshot: LedgerSnapshot = serde_json::from_str(&json).map_err(|e| format!("Malformed ledger: {}", e))?;
+        Ledger::from_snapshot(&snapshot)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(&self.snapshot()).map_err(|e| e.to_string())?;
+        std::fs::write(path.as_ref(), json)
+            .map_err(|e| format!("Failed to write {}: {}", path.as_ref().display(), e))
+    }
+}
+
+/// The total supply of `asset` counted by the contract at `state_machine`, and whether it holds at
+/// least that much.
+pub fn fetch_supply(rpc: &RpcClient, state_machine: &str, asset: &[u8; 20]) -> Result<(U256, bool), String> {
+    let output = rpc.eth_call(state_machine, &StateMachine::totalSupplyCall { asset: (*asset).into() }.abi_encode())?;
+    let supply = StateMachine::totalSupplyCall::abi_decode_returns(&output, true).map_err(|e| e.to_string())?._0;
+    let output = rpc.eth_call(state_machine, &StateMachine::solventCall { asset: (*asset).into() }.abi_encode())?;
+    let solvent = StateMachine::solventCall::abi_decode_returns(&output, true).map_err(|e| e.to_string())?._0;
+    Ok((supply, solvent))
+}
+
+/// Reads a local transaction dump.
+pub fn load_dump(path: impl AsRef<Path>) -> Result<Vec<IndexedTransaction>, String> {
+    let json = std::fs::read_to_string(path.as_ref())
+        .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Malformed transaction dump: {}", e))
+}
+
+fn parse_quantity(value: &Value) -> Result<u64, String> {
+    let hex = value.as_str().ok_or("Expected a hex quantity".to_string())?;
+    u64::from_str_radix(hex.trim_start_matches("0x"), 16).map_err(|e| format!("Invalid quantity {}: {}", hex, e))
+}
+
+/// Collects the calls to `state_machine` in blocks `from..=to`, in chain order, with their
+/// receipt status.
+pub fn fetch_transactions(
+    rpc: &RpcClient,
+    state_machine: &str,
+    from: u64,
+    to: u64,
+) -> Result<Vec<IndexedTransaction>, String> {
+    let state_machine = state_machine.to_lowercase();
+    let mut transactions = Vec::new();
+    for number in from..=to {
+        let block = rpc.block_with_transactions(number)?;
+        let Some(txs) = block["transactions"].as_array() else {
+            return Err(format!("Block {} has no transaction list", number));
+        };
+        for tx in txs {
+            if tx["to"].as_str().map(|to| to.to_lowercase()) != Some(state_machine.clone()) {
+                continue;
+            }
+            let hash = tx["hash"].as_str().ok_or("Transaction without hash".to_string())?.to_string();
+            let receipt = rpc.transaction_receipt(&hash)?;
+            transactions.push(IndexedTransaction {
+                input: tx["input"].as_str().ok_or(format!("{} has no input", hash))?.to_string(),
+                status: Some(parse_quantity(&receipt["status"])? == 1),
+                block_number: Some(number),
+                hash: Some(hash),
+            });
+        }
+    }
+    Ok(transactions)
+}
@@ -0,0 +1,239 @@
+//! A password-protected keystore for ElGamal secrets, laid out like Ethereum's v3 keystore: the
+//! key is stretched with scrypt and the secrets are sealed with ChaCha20-Poly1305. The public key
+//! and every KDF parameter are authenticated along with the ciphertext, so editing any field of
+//! the file makes decryption fail just like a wrong password does.
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const KDF: &str = "scrypt";
+const CIPHER: &str = "chacha20poly1305";
+const DEFAULT_LOG_N: u8 = 15;
+const DEFAULT_R: u32 = 8;
+const DEFAULT_P: u32 = 1;
+
+/// The secrets a keystore protects.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeystoreSecrets {
+    /// The view key, which decrypts the balance and the notes received.
+    pub skey: [u64; 4],
+    /// The spend key the view key is derived from. Missing from view-only keystores, which can
+    /// read the account but not prove anything that moves its funds.
+    #[serde(default)]
+    pub spend_key: Option<[u64; 4]>,
+    /// The randomness `r` of the deposit opening the account.
+    pub random: [u64; 4],
+    /// Where the secrets were derived from, if they came from a mnemonic.
+    #[serde(default)]
+    pub seed: Option<SeedAccount>,
+    /// The new key pair of a rekey that has been proven but not yet seen on the ledger.
+    #[serde(default)]
+    pub pending_rekey: Option<PendingRekey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingRekey {
+    pub spend_key: [u64; 4],
+    /// The randomness the balance is re-encrypted under.
+    pub random: [u64; 4],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeedAccount {
+    pub mnemonic: String,
+    pub account: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScryptParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+    pub salt: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeystoreCrypto {
+    pub kdf: String,
+    pub kdfparams: ScryptParams,
+    pub cipher: String,
+    pub nonce: String,
+    /// The sealed secrets, with the Poly1305 tag appended.
+    pub ciphertext: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Keystore {
+    pub version: u32,
+    /// The ElGamal public key of the account, in the clear so a keystore can be identified without
+    /// its password.
+    pub pkey: String,
+    pub crypto: KeystoreCrypto,
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, String> {
+    hex::decode(s.trim_start_matches("0x")).map_err(|e| format!("Invalid hex: {}", e))
+}
+
+fn derive_key(password: &str, params: &ScryptParams) -> Result<Key, String> {
+    let scrypt_params = scrypt::Params::new(params.log_n, params.r, params.p, 32)
+        .map_err(|e| format!("Invalid scrypt parameters: {}", e))?;
+    let mut key = Key::default();
+    scrypt::scrypt(password.as_bytes(), &decode_hex(&params.salt)?, &scrypt_params, &mut key)
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+impl Keystore {
+    pub const VERSION: u32 = 1;
+
+    pub fn encrypt(secrets: &KeystoreSecrets, pkey: &str, password: &str) -> Result<Keystore, String> {
+        Self::encrypt_with_log_n(secrets, pkey, password, DEFAULT_LOG_N)
+    }
+
+    fn encrypt_with_log_n(secrets: &KeystoreSecrets, pkey: &str, password: &str, log_n: u8) -> Result<Keystore, String> {
+        let mut salt = [0u8; 32];
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let mut keystore = Keystore {
+            version: Self::VERSION,
+            pkey: pkey.to_string(),
+            crypto: KeystoreCrypto {
+                kdf: KDF.to_string(),
+                kdfparams: ScryptParams {
+                    log_n,
+                    r: DEFAULT_R,
+                    p: DEFAULT_P,
+                    salt: format!("0x{}", hex::encode(salt)),
+                },
+                cipher: CIPHER.to_string(),
+                nonce: format!("0x{}", hex::encode(nonce)),
+                ciphertext: String::new(),
+            },
+        };
+        let key = derive_key(password, &keystore.crypto.kdfparams)?;
+        let plaintext = serde_json::to_vec(secrets).map_err(|e| e.to_string())?;
+        let ciphertext = ChaCha20Poly1305::new(&key)
+            .encrypt(&Nonce::from(nonce), Payload { msg: &plaintext, aad: &keystore.associated_data() })
+            .map_err(|_| "Encryption failed".to_string())?;
+        keystore.crypto.ciphertext = format!("0x{}", hex::encode(ciphertext));
+        Ok(keystore)
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<KeystoreSecrets, String> {
+        if self.version != Self::VERSION {
+            return Err(format!("Unsupported keystore version {}", self.version));
+        }
+        if self.crypto.kdf != KDF || self.crypto.cipher != CIPHER {
+            return Err(format!("Unsupported keystore scheme {}/{}", self.crypto.kdf, self.crypto.cipher));
+        }
+        let nonce: [u8; 12] = decode_hex(&self.crypto.nonce)?
+            .try_into()
+            .map_err(|_| "Invalid keystore nonce".to_string())?;
+        let key = derive_key(password, &self.crypto.kdfparams)?;
+        let plaintext = ChaCha20Poly1305::new(&key)
+            .decrypt(
+                &Nonce::from(nonce),
+                Payload { msg: &decode_hex(&self.crypto.ciphertext)?, aad: &self.associated_data() },
+            )
+            .map_err(|_| "Wrong password or corrupted keystore".to_string())?;
+        serde_json::from_slice(&plaintext).map_err(|e| format!("Malformed keystore secrets: {}", e))
+    }
+
+    /// Everything but the ciphertext, bound to it by the AEAD tag.
+    fn associated_data(&self) -> Vec<u8> {
+        let crypto = &self.crypto;
+        serde_json::to_vec(&(self.version, &self.pkey, &crypto.kdf, &crypto.kdfparams, &crypto.cipher, &crypto.nonce))
+            .expect("keystore headers are always serializable")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Keystore, String> {
+        let json = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Malformed keystore: {}", e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path.as_ref(), json)
+            .map_err(|e| format!("Failed to write {}: {}", path.as_ref().display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps scrypt cheap, the parameters are authenticated all the same.
+    const TEST_LOG_N: u8 = 4;
+    const PASSWORD: &str = "correct horse";
+
+    fn secrets() -> KeystoreSecrets {
+        KeystoreSecrets {
+            skey: [5u64, 6, 7, 8],
+            spend_key: Some([1u64, 2, 3, 4]),
+            random: [0x2222u64, 0, 0, 0],
+            seed: None,
+            pending_rekey: Some(PendingRekey { spend_key: [9u64, 10, 11, 12], random: [0x3333u64, 0, 0, 0] }),
+        }
+    }
+
+    fn keystore() -> Keystore {
+        Keystore::encrypt_with_log_n(&secrets(), "0x02", PASSWORD, TEST_LOG_N).unwrap()
+    }
+
+    fn flip_last_hex_digit(s: &mut String) {
+        let last = s.pop().unwrap();
+        s.push(if last == '0' { '1' } else { '0' });
+    }
+
+    #[test]
+    fn round_trips() {
+        let keystore = keystore();
+        assert_eq!(keystore.decrypt(PASSWORD).unwrap(), secrets());
+        // The same secrets never produce the same file.
+        assert_ne!(keystore.crypto.ciphertext, self::keystore().crypto.ciphertext);
+
+        let path = std::env::temp_dir().join(format!("keystore-test-{}.json", std::process::id()));
+        keystore.save(&path).unwrap();
+        let loaded = Keystore::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().decrypt(PASSWORD).unwrap(), secrets());
+    }
+
+    #[test]
+    fn rejects_a_wrong_password() {
+        let keystore = keystore();
+        assert!(keystore.decrypt("wrong horse").is_err());
+        assert!(keystore.decrypt("").is_err());
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let edits: [fn(&mut Keystore); 6] = [
+            |k| flip_last_hex_digit(&mut k.crypto.ciphertext),
+            |k| flip_last_hex_digit(&mut k.crypto.nonce),
+            |k| flip_last_hex_digit(&mut k.crypto.kdfparams.salt),
+            |k| k.pkey = "0x03".to_string(),
+            |k| k.crypto.kdfparams.log_n += 1,
+            |k| k.version += 1,
+        ];
+        let keystore = keystore();
+        for edit in edits {
+            let mut tampered = keystore.clone();
+            edit(&mut tampered);
+            assert!(tampered.decrypt(PASSWORD).is_err());
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! Host-side helpers shared by the state machine scripts.
+
+pub mod attestation;
+pub mod bundle;
+pub mod contracts;
+pub mod harness;
+pub mod indexer;
+pub mod keystore;
+pub mod prover;
+pub mod rpc;
+pub mod seed;
+pub mod sequencer;
+pub mod tx;
+pub mod wallet;
+
+pub use bundle::{ProofBundle, ProofSystem};
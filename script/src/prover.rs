@@ -0,0 +1,234 @@
+//! Proves actions on the host and keeps the proofs current until they land.
+//!
+//! The contract applies an action only while the slots it writes still hold the values it was
+//! proven against, so a proof is wasted if another action touching the same slots lands while it
+//! is being generated, waits to be sent or waits to be mined. The prover follows the chain until
+//! the proof lands, replays it on top of what was mined, and when it no longer applies rebuilds
+//! the witness over the caught-up ledger and proves and submits again, a bounded number of times.
+
+use crate::bundle::decode_hex;
+use crate::indexer::{decode_call, IndexedTransaction, Ledger};
+use crate::rpc::RpcClient;
+use crate::tx::build_call;
+use crate::wallet::Wallet;
+use crate::{ProofBundle, ProofSystem};
+use sp1_bls12_381::G1Affine;
+use sp1_sdk::{EnvProver, ProverClient, SP1ProvingKey, SP1Stdin, SP1VerifyingKey};
+use state_machine_lib::{account_key, replay, Action, DecodedPublicValues, DecodedWrites, PublicParams};
+use std::time::Duration;
+
+/// Whether a proof still applies on top of a ledger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Freshness {
+    Current,
+    /// The proof's writes have been mined, however they were submitted.
+    Landed,
+    /// Another action changed what the proof reads, for the reason given.
+    Stale(String),
+}
+
+/// Whether `theirs` makes the first write of `ours`. Every write leaves a freshly randomized
+/// ciphertext, so only the same proof can.
+fn same_write(ours: &DecodedWrites, theirs: &DecodedWrites) -> bool {
+    match (ours.slots.first(), ours.pending.first()) {
+        (Some(w), _) => theirs.slots.iter().any(|t| (t.shard, t.index, t.new_t, t.new_v) == (w.shard, w.index, w.new_t, w.new_v)),
+        (None, Some(w)) => theirs.pending.iter().any(|t| (t.shard, t.index, t.new_t, t.new_v) == (w.shard, w.index, w.new_t, w.new_v)),
+        (None, None) => false,
+    }
+}
+
+/// Whether `pp` holds what `writes` writes to the slots, or to the pending transfers when it
+/// writes no slot, e.g. for a top-up.
+fn holds(pp: &PublicParams, writes: &DecodedWrites) -> bool {
+    let slot = |shard: usize, index: usize| Some(shard * pp.degree + index).filter(|slot| *slot < pp.v.len());
+    if !writes.slots.is_empty() {
+        return writes.slots.iter().all(|w| slot(w.shard, w.index).is_some_and(|s| (pp.t[s], pp.v[s]) == (w.new_t, w.new_v)));
+    }
+    !writes.pending.is_empty()
+        && writes.pending.iter().all(|w| slot(w.shard, w.index).is_some_and(|s| (pp.pending_t[s], pp.pending_v[s]) == (w.new_t, w.new_v)))
+}
+
+/// Checks `bundle` against `ledger`, which has `applied` as its latest transactions. The proof has
+/// landed once a mined transaction carries its writes, whoever sent it and however it was encoded,
+/// or once the ledger holds what it writes.
+pub fn freshness(bundle: &ProofBundle, ledger: &Ledger, applied: &[IndexedTransaction]) -> Result<Freshness, String> {
+    let values = DecodedPublicValues::decode(bundle.action, &bundle.public_values_bytes()?)?;
+    if let Some(ours) = values.writes() {
+        let landed = applied
+            .iter()
+            .filter(|tx| tx.succeeded())
+            .filter_map(|tx| decode_call(&decode_hex(&tx.input).ok()?).ok())
+            .any(|theirs| theirs.writes().is_some_and(|theirs| same_write(ours, theirs)));
+        if landed || holds(&ledger.pp, ours) {
+            return Ok(Freshness::Landed);
+        }
+    }
+    Ok(match replay(&mut ledger.pp.clone(), &ledger.phi, &values) {
+        Ok(_) => Freshness::Current,
+        Err(e) => Freshness::Stale(e),
+    })
+}
+
+/// Refreshes the parts of `action`'s witness read from the ledger, and the wallet state the
+/// action leaves behind, keeping everything the holder chose. The balance is decrypted again, so
+/// transfers rolled in since the first proof can be spent, and the action is signed again.
+pub fn rebuild_witness(wallet: &mut Wallet, action: &mut Action, ledger: &Ledger, max_balance: u64) -> Result<(), String> {
+    let opened = ledger.pp.index_of.contains_key(&account_key(&wallet.pkey(&ledger.pp), &wallet.asset));
+    let insufficient = |balance: u64| format!("Insufficient balance: {}", balance);
+    match action {
+        Action::Deposit(deposit) => {
+            if opened {
+                wallet.sync(ledger, max_balance)?;
+                wallet.state.pending += deposit.amount;
+            } else {
+                wallet.state.balance = deposit.amount;
+                wallet.state.pending = 0;
+            }
+        },
+        Action::Send(send) => {
+            let balance = wallet.sync(ledger, max_balance)?;
+            let debit = send.amount.checked_add(send.fee).filter(|debit| *debit <= balance).ok_or(insufficient(balance))?;
+            send.balance_sender = balance;
+            wallet.state.balance = balance - debit;
+        },
+        Action::SendMany(send) => {
+            let balance = wallet.sync(ledger, max_balance)?;
+            let total = send
+                .receivers
+                .iter()
+                .try_fold(0u64, |total, r| total.checked_add(r.amount))
+                .filter(|total| *total <= balance)
+                .ok_or(insufficient(balance))?;
+            send.balance_sender = balance;
+            wallet.state.balance = balance - total;
+        },
+        Action::Withdraw(withdraw) => {
+            let balance = wallet.sync(ledger, max_balance)?;
+            let debit =
+                withdraw.amount.checked_add(withdraw.fee).filter(|debit| *debit <= balance).ok_or(insufficient(balance))?;
+            withdraw.balance = balance;
+            wallet.state.balance = balance - debit;
+        },
+        Action::Rotate(rotate) => {
+            let balance = wallet.sync(ledger, max_balance)?;
+            if let Some(rekey) = rotate.rekey.as_mut() {
+                if wallet.state.pending != 0 {
+                    return Err("Roll over the pending transfers first".to_string());
+                }
+                rekey.balance = balance;
+            }
+        },
+        Action::Close(_) => {
+            let balance = wallet.sync(ledger, max_balance)?;
+            if balance != 0 || wallet.state.pending != 0 {
+                return Err("The account is no longer empty".to_string());
+            }
+            wallet.state.index = None;
+        },
+        Action::Attest(attest) => {
+            let balance = wallet.sync(ledger, max_balance)?;
+            if balance < attest.threshold {
+                return Err(insufficient(balance));
+            }
+            attest.balance = balance;
+        },
+        Action::Rollover(rollover) => {
+            let shard = rollover.shard as usize;
+            let mut slots = shard * ledger.pp.degree..(shard + 1) * ledger.pp.degree;
+            if shard >= ledger.pp.shards() || !slots.any(|slot| ledger.pp.has_pending(slot)) {
+                return Err(format!("Nothing is pending in shard {} anymore", shard));
+            }
+        },
+    }
+    // The signature covers the account's ciphertext, which a rollover may have moved
+    if action.authorization().is_some() {
+        action.sign(wallet.spend_key()?, &ledger.pp)?;
+    }
+    Ok(())
+}
+
+/// Proves actions with one proof system, and proves them again when the ledger moves on.
+pub struct Prover {
+    client: EnvProver,
+    pk: SP1ProvingKey,
+    vk: SP1VerifyingKey,
+    system: ProofSystem,
+    /// How many times an action is proven again before giving up.
+    max_retries: u32,
+    /// How long to wait between checks of the chain once a proof is submitted.
+    poll_interval: Duration,
+}
+
+impl Prover {
+    pub fn new(elf: &[u8], system: ProofSystem, max_retries: u32, poll_interval: Duration) -> Prover {
+        let client = ProverClient::from_env();
+        let (pk, vk) = client.setup(elf);
+        Prover { client, pk, vk, system, max_retries, poll_interval }
+    }
+
+    /// Proves `action` against the ledger with phis `phi` and public parameters `pp`.
+    pub fn prove(&self, action: &Action, phi: &[G1Affine], pp: &PublicParams) -> Result<ProofBundle, String> {
+        let mut stdin = SP1Stdin::new();
+        stdin.write(action);
+        stdin.write(&phi.to_vec());
+        stdin.write(pp);
+
+        let request = self.client.prove(&self.pk, &stdin);
+        let request = match self.system {
+            ProofSystem::Core => request.core(),
+            ProofSystem::Compressed => request.compressed(),
+            ProofSystem::Plonk => request.plonk(),
+            ProofSystem::Groth16 => request.groth16(),
+        };
+        let proof = request.run().map_err(|e| format!("Failed to generate proof: {}", e))?;
+        self.client.verify(&proof, &self.vk).map_err(|e| format!("Failed to verify proof: {}", e))?;
+        Ok(ProofBundle::new(action.kind(), &proof, &self.vk, pp))
+    }
+
+    /// Hands `bundle` to `submit` and follows `state_machine` until it lands, returning the bundle
+    /// that did. Whenever the proof no longer applies on top of everything mined, `rebuild`
+    /// refreshes the witness of `action` over the caught-up `ledger`, and the action is proven and
+    /// submitted again. Bundles that cannot land on-chain, attestations and proofs the contract does
+    /// not verify, are returned once submitted.
+    #[allow(clippy::too_many_arguments)]
+    pub fn keep_current(
+        &self,
+        mut bundle: ProofBundle,
+        action: &mut Action,
+        ledger: &mut Ledger,
+        rpc: &RpcClient,
+        state_machine: &str,
+        mut rebuild: impl FnMut(&mut Action, &Ledger) -> Result<(), String>,
+        mut submit: impl FnMut(&ProofBundle) -> Result<(), String>,
+    ) -> Result<ProofBundle, String> {
+        let mut retries = 0;
+        let mut submitted = false;
+        loop {
+            let applied = ledger.follow(rpc, state_machine)?;
+            let reason = match freshness(&bundle, ledger, &applied)? {
+                Freshness::Landed => return Ok(bundle),
+                Freshness::Current if submitted => {
+                    std::thread::sleep(self.poll_interval);
+                    continue;
+                },
+                Freshness::Current => {
+                    submit(&bundle)?;
+                    if build_call(&bundle).is_err() {
+                        return Ok(bundle);
+                    }
+                    submitted = true;
+                    continue;
+                },
+                Freshness::Stale(reason) => reason,
+            };
+            if retries == self.max_retries {
+                return Err(format!("The proof is still stale after {} retries: {}", retries, reason));
+            }
+            retries += 1;
+            tracing::warn!("The proof is stale ({}), proving again ({}/{})", reason, retries, self.max_retries);
+            rebuild(action, ledger)?;
+            bundle = self.prove(action, &ledger.phi, &ledger.pp)?;
+            submitted = false;
+        }
+    }
+}
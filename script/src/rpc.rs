@@ -0,0 +1,82 @@
+//! A minimal Ethereum JSON-RPC client, enough to submit transactions and read chain state.
+
+use serde_json::{json, Value};
+
+pub struct RpcClient {
+    url: String,
+}
+
+impl RpcClient {
+    pub fn new(url: &str) -> RpcClient {
+        RpcClient { url: url.to_string() }
+    }
+
+    pub fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response: Value = ureq::post(&self.url)
+            .send_json(request)
+            .map_err(|e| format!("{} failed: {}", method, e))?
+            .into_json()
+            .map_err(|e| format!("{} returned invalid JSON: {}", method, e))?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("{} failed: {}", method, error));
+        }
+        Ok(response["result"].clone())
+    }
+
+    fn call_u128(&self, method: &str, params: Value) -> Result<u128, String> {
+        let result = self.call(method, params)?;
+        let hex = result.as_str().ok_or(format!("{} returned a non-string result", method))?;
+        u128::from_str_radix(hex.trim_start_matches("0x"), 16).map_err(|e| format!("{} returned {}: {}", method, hex, e))
+    }
+
+    pub fn chain_id(&self) -> Result<u64, String> {
+        self.call_u128("eth_chainId", json!([])).map(|id| id as u64)
+    }
+
+    pub fn block_number(&self) -> Result<u64, String> {
+        self.call_u128("eth_blockNumber", json!([])).map(|n| n as u64)
+    }
+
+    pub fn nonce(&self, address: &str) -> Result<u64, String> {
+        self.call_u128("eth_getTransactionCount", json!([address, "pending"])).map(|n| n as u64)
+    }
+
+    pub fn gas_price(&self) -> Result<u128, String> {
+        self.call_u128("eth_gasPrice", json!([]))
+    }
+
+    pub fn max_priority_fee_per_gas(&self) -> Result<u128, String> {
+        self.call_u128("eth_maxPriorityFeePerGas", json!([]))
+    }
+
+    /// Fetches a block with its full transaction objects.
+    pub fn block_with_transactions(&self, number: u64) -> Result<Value, String> {
+        let block = self.call("eth_getBlockByNumber", json!([format!("0x{:x}", number), true]))?;
+        if block.is_null() {
+            return Err(format!("Block {} not found", number));
+        }
+        Ok(block)
+    }
+
+    /// Runs a read-only call against the latest block and returns its output.
+    pub fn eth_call(&self, to: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+        let result = self.call("eth_call", json!([{ "to": to, "data": format!("0x{}", hex::encode(data)) }, "latest"]))?;
+        let output = result.as_str().ok_or("eth_call returned a non-string result".to_string())?;
+        hex::decode(output.trim_start_matches("0x")).map_err(|e| format!("eth_call returned invalid hex: {}", e))
+    }
+
+    pub fn transaction_receipt(&self, hash: &str) -> Result<Value, String> {
+        let receipt = self.call("eth_getTransactionReceipt", json!([hash]))?;
+        if receipt.is_null() {
+            return Err(format!("Receipt for {} not found", hash));
+        }
+        Ok(receipt)
+    }
+
+    /// Submits a signed, EIP-2718 encoded transaction and returns its hash.
+    pub fn send_raw_transaction(&self, raw: &[u8]) -> Result<String, String> {
+        let result = self.call("eth_sendRawTransaction", json!([format!("0x{}", hex::encode(raw))]))?;
+        result.as_str().map(|s| s.to_string()).ok_or("eth_sendRawTransaction returned no hash".to_string())
+    }
+}
@@ -0,0 +1,125 @@
+//! Deterministic ElGamal secrets from a BIP-39 mnemonic, so a wallet can be restored from its
+//! words and the indexer's ledger alone.
+//!
+//! The 64-byte BIP-39 seed is stretched into a master node with HMAC-SHA512, and every secret is
+//! read off a labelled path below it, one HMAC-SHA512 step per label:
+//!
+//! - `account/<a>/spend`: the spend key of account `a`, from which its view key is derived
+//! - `account/<a>/deposit/<i>`: the randomness of its `i`-th deposit
+//!
+//! Each node is reduced to an exponent modulo the group order through a wide scalar reduction.
+
+use crate::keystore::{KeystoreSecrets, SeedAccount};
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha512;
+use sp1_bls12_381::Scalar;
+use state_machine_lib::{scalar_to_exponent, view_key};
+
+const MASTER_KEY: &[u8] = b"zk-state-machine seed";
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for chunk in data {
+        mac.update(chunk);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// Generates a fresh mnemonic of 12 or 24 words.
+pub fn generate_mnemonic(words: usize) -> Result<Mnemonic, String> {
+    let mut entropy = match words {
+        12 => vec![0u8; 16],
+        24 => vec![0u8; 32],
+        _ => return Err(format!("Mnemonics have 12 or 24 words, not {}", words)),
+    };
+    rand::thread_rng().fill_bytes(&mut entropy);
+    Mnemonic::from_entropy(&entropy).map_err(|e| e.to_string())
+}
+
+pub struct Seed {
+    phrase: String,
+    master: [u8; 64],
+}
+
+impl Seed {
+    pub fn from_mnemonic(phrase: &str) -> Result<Seed, String> {
+        let mnemonic = Mnemonic::parse(phrase).map_err(|e| format!("Invalid mnemonic: {}", e))?;
+        Ok(Seed { phrase: mnemonic.to_string(), master: hmac_sha512(MASTER_KEY, &[&mnemonic.to_seed("")]) })
+    }
+
+    fn derive(&self, path: &[String]) -> [u64; 4] {
+        let node = path.iter().fold(self.master, |node, label| hmac_sha512(&node[32..], &[&node[..32], label.as_bytes()]));
+        scalar_to_exponent(&Scalar::from_bytes_wide(&node))
+    }
+
+    pub fn spend_key(&self, account: u32) -> [u64; 4] {
+        self.derive(&["account".to_string(), account.to_string(), "spend".to_string()])
+    }
+
+    pub fn deposit_randomness(&self, account: u32, deposit: u32) -> [u64; 4] {
+        self.derive(&["account".to_string(), account.to_string(), "deposit".to_string(), deposit.to_string()])
+    }
+
+    /// The secrets of `account` before any rekey.
+    pub fn secrets(&self, account: u32) -> KeystoreSecrets {
+        let spend_key = self.spend_key(account);
+        KeystoreSecrets {
+            skey: view_key(spend_key),
+            spend_key: Some(spend_key),
+            random: self.deposit_randomness(account, 0),
+            seed: Some(SeedAccount { mnemonic: self.phrase.clone(), account }),
+            pending_rekey: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use state_machine_lib::{account_key, ElGamal, PublicParams, NATIVE_ASSET};
+
+    const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    /// Changing these breaks every wallet restored from its words.
+    #[test]
+    fn derives_fixed_keys_from_a_mnemonic() {
+        let seed = Seed::from_mnemonic(MNEMONIC).unwrap();
+        assert_eq!(seed.spend_key(0), [0x7c53477059c93601, 0xff207636f5459fb2, 0xb58a22707bb5e897, 0x62ca377c037a3969]);
+        assert_eq!(seed.spend_key(1), [0x322cccfb545105e4, 0x786f3447040dae18, 0xe422c02743764ebe, 0x2492891862600ee2]);
+        assert_eq!(seed.deposit_randomness(0, 0), [0x527d477596d9a9dd, 0x8301a29c4187128a, 0x325e6a3a33076d56, 0x48205be94382dd3c]);
+        assert_eq!(seed.deposit_randomness(0, 1), [0x43ffda7e5881f806, 0x0f5851bb9ef48541, 0xeda8c4e9023e4a4c, 0x1768e2cd18bcc559]);
+
+        let secrets = seed.secrets(0);
+        assert_eq!(secrets.skey, [0x6f0cb29cd620dc22, 0x2282c8c50dbab18b, 0x4c8603172341513e, 0x6f658fc0f37918b5]);
+        assert_eq!((secrets.spend_key, secrets.random), (Some(seed.spend_key(0)), seed.deposit_randomness(0, 0)));
+        assert_eq!(secrets.seed, Some(SeedAccount { mnemonic: MNEMONIC.to_string(), account: 0 }));
+    }
+
+    #[test]
+    fn accounts_and_assets_get_their_own_keys() {
+        let seed = Seed::from_mnemonic(MNEMONIC).unwrap();
+        let spend_keys: Vec<_> = (0..4).map(|account| seed.spend_key(account)).collect();
+        let view_keys: Vec<_> = spend_keys.iter().map(|spend_key| view_key(*spend_key)).collect();
+        for i in 0..4 {
+            for j in i + 1..4 {
+                assert_ne!(spend_keys[i], spend_keys[j]);
+                assert_ne!(view_keys[i], view_keys[j]);
+            }
+        }
+        assert_ne!(seed.deposit_randomness(0, 0), seed.deposit_randomness(1, 0));
+
+        // An account's key is shared across assets, each of which is a separate registry entry
+        let pkey = ElGamal::new(PublicParams::setup(1).g).from_skey(view_keys[0]);
+        assert_ne!(account_key(&pkey, &NATIVE_ASSET), account_key(&pkey, &[0x70; 20]));
+    }
+
+    #[test]
+    fn rejects_a_mnemonic_with_a_bad_checksum() {
+        let phrase = MNEMONIC.replace("about", "abandon");
+        assert!(Seed::from_mnemonic(&phrase).is_err());
+        let other = Seed::from_mnemonic("legal winner thank year wave sausage worth useful legal winner thank yellow").unwrap();
+        assert_ne!(other.spend_key(0), Seed::from_mnemonic(MNEMONIC).unwrap().spend_key(0));
+    }
+}
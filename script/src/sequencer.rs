@@ -0,0 +1,552 @@
+//! A local sequencer for a relayer that proves actions on behalf of many users.
+//!
+//! Clients send intents, each a signed action with its witness and a nonce, to the relayer. The
+//! sequencer checks that every intent names accounts of the ledger it builds on and holds it in a
+//! mempool. A batch takes intents by the fee they pay the relayer, first come first served among
+//! equal fees, and skips any that conflict with an intent already in the batch, leaving it for the
+//! next batch. The actions of a batch therefore commute: each is proven against the same
+//! ledger and they land in any order. The ledger then moves on to the batch applied, so the next
+//! batch builds on it.
+//!
+//! An intent never holds a spend key. The action carries the spend key's signature over its terms
+//! and the account's current ciphertext, which the program checks, so the relayer learns the view
+//! key of the accounts it proves for but cannot spend from them. The intent itself is signed by the
+//! same spend key over its hash, binding the nonce to the account: the nonce, which must grow with
+//! every intent for the same account, keeps one seen in transit from being queued again. Deposits
+//! and rollovers need no key, so they carry no signature and touch no account's nonce.
+
+use crate::indexer::Ledger;
+use crate::prover::Prover;
+use crate::ProofBundle;
+use alloy_primitives::{keccak256, B256};
+use serde::{Deserialize, Serialize};
+use sp1_bls12_381::G1Affine;
+use state_machine_lib::spend::view_key_of;
+use state_machine_lib::{
+    account_key, attest, close, deposit, rekey, rollover, rotate, send, send_many, withdraw, Action, Authorization, ElGamal,
+    PublicParams, Signature,
+};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+
+/// A signed action and its witness, sent by a client to the relayer.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Intent {
+    pub action: Action,
+    /// Greater than the nonce of every earlier intent for the same account.
+    pub nonce: u64,
+    /// The spend key's signature over the hash, none for an action that needs no spend key.
+    pub signature: Option<Signature>,
+}
+
+impl Intent {
+    /// Wraps `action`, already signed with `Action::sign`, and signs the intent with the same spend
+    /// key. Deposits and rollovers are sent with no key.
+    pub fn new(action: Action, nonce: u64, spend_key: Option<[u64; 4]>) -> Intent {
+        let mut intent = Intent { action, nonce, signature: None };
+        intent.signature = spend_key.map(|spend_key| Signature::sign(spend_key, &intent.hash().0));
+        intent
+    }
+
+    /// Identifies the intent in the mempool, and is what its signature covers.
+    pub fn hash(&self) -> B256 {
+        let bytes = bincode::serialize(&self.action).expect("failed to serialize action");
+        keccak256([b"zk-state-machine intent".as_slice(), &bytes, &self.nonce.to_le_bytes()].concat())
+    }
+
+    /// Checks the intent's signature and returns the registry key of the account it acts for, none
+    /// for an action that needs no spend key.
+    pub fn signer(&self, pp: &PublicParams) -> Result<Option<[u8; 32]>, String> {
+        let Some((auth, asset)) = self.action.authorization() else {
+            return Ok(None);
+        };
+        let signature = self.signature.as_ref().ok_or("The intent is not signed".to_string())?;
+        if view_key_of(&auth.spend_pkey) != auth.view_key {
+            return Err("The view key does not belong to the spend key".to_string());
+        }
+        if !signature.verify(&auth.spend_pkey, &self.hash().0) {
+            return Err("The intent is not signed by the account's spend key".to_string());
+        }
+        Ok(Some(account_key(&ElGamal::new(pp.g).from_skey(auth.view_key), asset)))
+    }
+}
+
+/// The slots an action writes and the state it pins, for telling which actions can share a batch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Footprint {
+    /// Slots whose ciphertext is written.
+    pub slots: BTreeSet<usize>,
+    /// Slots whose pending transfers are written.
+    pub pending: BTreeSet<usize>,
+    /// Shards whose phi is moved by a slot write.
+    pub shards: BTreeSet<usize>,
+    /// Shards with a slot whose pending transfers are written.
+    pub pending_shards: BTreeSet<usize>,
+    /// Shards whose phi must not move, for an attestation.
+    pub phis: BTreeSet<usize>,
+    /// Shards rolled over, which must be left with nothing pending.
+    pub rolled: BTreeSet<usize>,
+    /// Assets whose total supply is moved.
+    pub assets: BTreeSet<[u8; 20]>,
+    /// Assets whose total supply must not move, for an attestation.
+    pub supplies: BTreeSet<[u8; 20]>,
+    /// Whether the free list is pinned, by opening or closing an account.
+    pub free_list: bool,
+}
+
+impl Footprint {
+    fn write(&mut self, pp: &PublicParams, slot: usize) {
+        self.slots.insert(slot);
+        self.shards.insert(pp.locate(slot).0);
+    }
+
+    fn pend(&mut self, pp: &PublicParams, slot: usize) {
+        self.pending.insert(slot);
+        self.pending_shards.insert(pp.locate(slot).0);
+    }
+
+    /// Whether the two actions cannot both land when proven against the same ledger.
+    pub fn conflicts(&self, other: &Footprint) -> bool {
+        !self.slots.is_disjoint(&other.slots)
+            || !self.pending.is_disjoint(&other.pending)
+            || (self.free_list && other.free_list)
+            || !self.phis.is_disjoint(&other.shards)
+            || !self.shards.is_disjoint(&other.phis)
+            || !self.rolled.is_disjoint(&other.pending_shards)
+            || !self.pending_shards.is_disjoint(&other.rolled)
+            || !self.supplies.is_disjoint(&other.assets)
+            || !self.assets.is_disjoint(&other.supplies)
+    }
+
+    fn extend(&mut self, other: Footprint) {
+        self.slots.extend(other.slots);
+        self.pending.extend(other.pending);
+        self.shards.extend(other.shards);
+        self.pending_shards.extend(other.pending_shards);
+        self.phis.extend(other.phis);
+        self.rolled.extend(other.rolled);
+        self.assets.extend(other.assets);
+        self.supplies.extend(other.supplies);
+        self.free_list |= other.free_list;
+    }
+}
+
+/// Checks that `action` names accounts and shards of `pp`, and returns its footprint there without
+/// applying it.
+pub fn footprint(pp: &PublicParams, action: &Action) -> Result<Footprint, String> {
+    let mut footprint = Footprint::default();
+    let pkey = |auth: &Authorization| ElGamal::new(pp.g).from_skey(auth.view_key);
+    match action {
+        Action::Deposit(d) if pp.index_of.contains_key(&account_key(&d.pkey, &d.asset)) => {
+            footprint.pend(pp, pp.slot_of(&d.pkey, &d.asset)?);
+            footprint.assets.insert(d.asset);
+        },
+        Action::Deposit(d) => {
+            footprint.write(pp, pp.free.last().copied().unwrap_or(pp.idx));
+            footprint.free_list = true;
+            footprint.assets.insert(d.asset);
+        },
+        Action::Send(s) => {
+            if s.fee > 0 && s.relayer == [0u8; 20] {
+                return Err("A fee is paid to the zero address".to_string());
+            }
+            footprint.write(pp, pp.slot_of(&pkey(&s.auth_sender), &s.asset)?);
+            footprint.pend(pp, pp.slot_of(&s.pkey_receiver, &s.asset)?);
+            if s.fee > 0 {
+                footprint.assets.insert(s.asset);
+            }
+        },
+        Action::SendMany(s) => {
+            footprint.write(pp, pp.slot_of(&pkey(&s.auth_sender), &s.asset)?);
+            for receiver in s.receivers.iter() {
+                footprint.pend(pp, pp.slot_of(&receiver.pkey, &s.asset)?);
+            }
+        },
+        Action::Withdraw(w) => {
+            if w.fee > 0 && w.relayer == [0u8; 20] {
+                return Err("A fee is paid to the zero address".to_string());
+            }
+            footprint.write(pp, pp.slot_of(&pkey(&w.auth), &w.asset)?);
+            footprint.assets.insert(w.asset);
+        },
+        Action::Rotate(r) => {
+            let idx = pp.slot_of(&pkey(&r.auth), &r.asset)?;
+            footprint.write(pp, idx);
+            // A rekey moves the account to another key, which transfers in must be sent to
+            if r.rekey.is_some() {
+                footprint.pend(pp, idx);
+            }
+        },
+        Action::Close(c) => {
+            let idx = pp.slot_of(&pkey(&c.auth), &c.asset)?;
+            footprint.write(pp, idx);
+            footprint.pend(pp, idx);
+            footprint.free_list = true;
+        },
+        Action::Attest(a) => {
+            // The contract checks the attestation against the phi and the total supply
+            footprint.phis.insert(pp.locate(pp.slot_of(&pkey(&a.auth), &a.asset)?).0);
+            footprint.supplies.insert(a.asset);
+        },
+        Action::Rollover(r) => {
+            let shard = r.shard as usize;
+            if shard >= pp.shards() {
+                return Err(format!("Shard {} does not exist", shard));
+            }
+            for slot in (shard * pp.degree..(shard + 1) * pp.degree).filter(|slot| pp.has_pending(*slot)) {
+                footprint.write(pp, slot);
+                footprint.pend(pp, slot);
+            }
+            // The contract rejects a rollover that leaves anything pending, so no transfer into
+            // the shard may land first
+            footprint.rolled.insert(shard);
+        },
+    }
+    Ok(footprint)
+}
+
+/// Checks the spend authorization of `action` and applies it to `pp` and `phi` natively, as the
+/// program does, and returns its footprint. The lib functions check everything before they change
+/// the ledger, so an action that fails leaves both as they were.
+pub fn execute(pp: &mut PublicParams, phi: &mut Vec<G1Affine>, action: &Action) -> Result<Footprint, String> {
+    let footprint = footprint(pp, action)?;
+    // Unused by the actions that need no spend key
+    let skey = action.authorization().map(|(auth, asset)| auth.verify(pp, asset, action)).transpose()?.unwrap_or_default();
+    *phi = match action {
+        Action::Deposit(d) => deposit(pp, d.pkey, d.asset, d.random, d.amount, phi)?,
+        Action::Send(s) => send(pp, skey, s.pkey_receiver, s.asset, s.balance_sender, s.amount, s.fee, s.transfer_random, s.debit_random, phi)?,
+        Action::SendMany(s) => {
+            let receivers: Vec<_> = s.receivers.iter().map(|r| (r.pkey, r.amount, r.transfer_random)).collect();
+            send_many(pp, skey, s.asset, &receivers, s.balance_sender, s.debit_random, phi)?
+        },
+        Action::Withdraw(w) => withdraw(pp, skey, w.asset, w.balance, w.amount, w.fee, w.debit_random, phi, w.recipient)?,
+        Action::Rotate(r) => match &r.rekey {
+            Some(k) => rekey(pp, skey, r.asset, k.balance, k.new_pkey, k.new_random, phi)?.0,
+            None => rotate(pp, skey, r.asset, r.new_additive, phi)?.0,
+        },
+        Action::Close(c) => close(pp, skey, c.asset, phi)?.0,
+        Action::Attest(a) => {
+            attest(pp, skey, a.asset, a.balance, a.threshold, phi)?;
+            phi.clone()
+        },
+        Action::Rollover(r) => rollover(pp, r.shard as usize, phi)?,
+    };
+    Ok(footprint)
+}
+
+/// An intent waiting in the mempool.
+#[derive(Debug)]
+pub struct Queued {
+    pub intent: Intent,
+    pub hash: B256,
+    /// The account the intent acts for, none for an action that needs no spend key.
+    pub account: Option<[u8; 32]>,
+    /// The fee paid to the sequencer's relayer.
+    pub fee: u64,
+    arrival: u64,
+}
+
+/// Intents that can be proven against the same ledger and land in any order.
+pub struct Batch {
+    /// The ledger every action of the batch is proven against.
+    pub phi: Vec<G1Affine>,
+    pub pp: PublicParams,
+    pub intents: Vec<Queued>,
+    /// Intents taken out of the mempool because they no longer apply, with the reason.
+    pub dropped: Vec<(Queued, String)>,
+}
+
+impl Batch {
+    /// Proves every action of the batch, in order.
+    pub fn prove(&self, prover: &Prover) -> Result<Vec<ProofBundle>, String> {
+        self.intents.iter().map(|queued| prover.prove(&queued.intent.action, &self.phi, &self.pp)).collect()
+    }
+}
+
+pub struct Sequencer {
+    /// The ledger the next batch builds on, with the batches handed out applied.
+    phi: Vec<G1Affine>,
+    pp: PublicParams,
+    /// Only fees paid to this address count towards an intent's place in the mempool.
+    relayer: [u8; 20],
+    max_batch: usize,
+    mempool: Vec<Queued>,
+    arrivals: u64,
+    /// The nonce of the latest intent queued for each account.
+    nonces: HashMap<[u8; 32], u64>,
+}
+
+impl Sequencer {
+    pub fn new(ledger: &Ledger, relayer: [u8; 20], max_batch: usize) -> Sequencer {
+        Sequencer {
+            phi: ledger.phi.clone(),
+            pp: ledger.pp.clone(),
+            relayer,
+            max_batch,
+            mempool: Vec::new(),
+            arrivals: 0,
+            nonces: HashMap::new(),
+        }
+    }
+
+    /// Builds on `ledger` from now on, e.g. after a batch failed to land. Queued intents stay.
+    pub fn reset(&mut self, ledger: &Ledger) {
+        self.phi = ledger.phi.clone();
+        self.pp = ledger.pp.clone();
+    }
+
+    pub fn mempool(&self) -> &[Queued] {
+        &self.mempool
+    }
+
+    /// Checks that `intent` is signed by its account's spend key with a nonce fresh for the account,
+    /// and that its action names accounts of the ledger, and queues it. Whether the action applies
+    /// is only known once it is taken into a batch.
+    pub fn submit(&mut self, intent: Intent) -> Result<B256, String> {
+        let hash = intent.hash();
+        if self.mempool.iter().any(|queued| queued.hash == hash) {
+            return Err(format!("Intent {} is already queued", hash));
+        }
+        let account = intent.signer(&self.pp)?;
+        if let Some(last) = account.and_then(|account| self.nonces.get(&account)) {
+            if intent.nonce <= *last {
+                return Err(format!("Nonce {} is not above {}, the last one for the account", intent.nonce, last));
+            }
+        }
+        footprint(&self.pp, &intent.action)?;
+        let fee = match &intent.action {
+            Action::Send(s) if s.relayer == self.relayer => s.fee,
+            Action::Withdraw(w) if w.relayer == self.relayer => w.fee,
+            _ => 0,
+        };
+        if let Some(account) = account {
+            self.nonces.insert(account, intent.nonce);
+        }
+        self.mempool.push(Queued { intent, hash, account, fee, arrival: self.arrivals });
+        self.arrivals += 1;
+        Ok(hash)
+    }
+
+    /// Takes the next batch out of the mempool, highest fee first and in arrival order among equal
+    /// fees. Intents that conflict with one already taken wait for a later batch; intents that no
+    /// longer apply are dropped.
+    pub fn next_batch(&mut self) -> Batch {
+        self.mempool.sort_by_key(|queued| (Reverse(queued.fee), queued.arrival));
+        // The one working copy, which the batch is applied to as it is taken
+        let (mut pp, mut phi) = (self.pp.clone(), self.phi.clone());
+        let mut taken = Footprint::default();
+        let (mut intents, mut dropped, mut waiting) = (Vec::new(), Vec::new(), Vec::new());
+        for queued in std::mem::take(&mut self.mempool) {
+            if intents.len() == self.max_batch {
+                waiting.push(queued);
+                continue;
+            }
+            let footprint = match footprint(&self.pp, &queued.intent.action) {
+                Ok(footprint) => footprint,
+                Err(e) => {
+                    dropped.push((queued, e));
+                    continue;
+                },
+            };
+            if footprint.conflicts(&taken) {
+                waiting.push(queued);
+                continue;
+            }
+            // Actions that do not conflict commute, so this applies as it would to the ledger the
+            // batch is proven against, and only fails where it would there too
+            if let Err(e) = execute(&mut pp, &mut phi, &queued.intent.action) {
+                dropped.push((queued, e));
+                continue;
+            }
+            taken.extend(footprint);
+            intents.push(queued);
+        }
+        self.mempool = waiting;
+        Batch { phi: std::mem::replace(&mut self.phi, phi), pp: std::mem::replace(&mut self.pp, pp), intents, dropped }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use state_machine_lib::{view_key, Deposit, Rollover, Send, Withdraw, NATIVE_ASSET};
+
+    const RELAYER: [u8; 20] = [0xee; 20];
+
+    fn spend_key(i: u64) -> [u64; 4] {
+        [i, 7, 0, 0]
+    }
+
+    fn pkey(pp: &PublicParams, i: u64) -> sp1_bls12_381::Scalar {
+        ElGamal::new(pp.g).from_skey(view_key(spend_key(i)))
+    }
+
+    fn deposit_action(pp: &PublicParams, i: u64, amount: u64) -> Action {
+        Action::Deposit(Deposit { amount, pkey: pkey(pp, i), asset: NATIVE_ASSET, random: [i, amount, 0x51, 0] })
+    }
+
+    /// A ledger of degree 4 where account `i` holds `100 * i`, the fifth opening shard 1.
+    fn ledger(accounts: u64) -> Ledger {
+        let mut ledger = Ledger::new(4, None);
+        for i in 1..=accounts {
+            let action = deposit_action(&ledger.pp, i, 100 * i);
+            execute(&mut ledger.pp, &mut ledger.phi, &action).unwrap();
+        }
+        ledger
+    }
+
+    fn signed(mut action: Action, i: u64, pp: &PublicParams, nonce: u64) -> Intent {
+        action.sign(spend_key(i), pp).unwrap();
+        Intent::new(action, nonce, Some(spend_key(i)))
+    }
+
+    fn withdraw(pp: &PublicParams, i: u64, balance: u64, fee: u64, nonce: u64) -> Intent {
+        let action = Action::Withdraw(Withdraw {
+            balance,
+            amount: 10,
+            auth: Authorization::default(),
+            asset: NATIVE_ASSET,
+            recipient: [2; 20],
+            fee,
+            relayer: RELAYER,
+            debit_random: [i, nonce, 0x52, 0],
+        });
+        signed(action, i, pp, nonce)
+    }
+
+    fn send(pp: &PublicParams, from: u64, to: u64, nonce: u64) -> Intent {
+        let action = Action::Send(Send {
+            balance_sender: 100 * from,
+            amount: 10,
+            auth_sender: Authorization::default(),
+            pkey_receiver: pkey(pp, to),
+            asset: NATIVE_ASSET,
+            fee: 0,
+            relayer: [0; 20],
+            note_random: [from, nonce, 0x53, 0],
+            transfer_random: [from, nonce, 0x54, 0],
+            debit_random: [from, nonce, 0x55, 0],
+            memo: Vec::new(),
+        });
+        signed(action, from, pp, nonce)
+    }
+
+    fn account(pp: &PublicParams, i: u64) -> Option<[u8; 32]> {
+        Some(account_key(&pkey(pp, i), &NATIVE_ASSET))
+    }
+
+    fn batch_accounts(batch: &Batch) -> Vec<Option<[u8; 32]>> {
+        batch.intents.iter().map(|queued| queued.account).collect()
+    }
+
+    fn assert_same_ledger(a: &PublicParams, b: &PublicParams) {
+        assert_eq!((&a.t, &a.v, &a.pending_t, &a.pending_v), (&b.t, &b.v, &b.pending_t, &b.pending_v));
+        assert_eq!((&a.pkeys, &a.assets, &a.index_of), (&b.pkeys, &b.assets, &b.index_of));
+        assert_eq!((&a.free, a.idx, &a.supply), (&b.free, b.idx, &b.supply));
+    }
+
+    #[test]
+    fn a_nonce_is_only_used_once_per_account() {
+        let ledger = ledger(3);
+        let mut sequencer = Sequencer::new(&ledger, RELAYER, 8);
+        sequencer.submit(withdraw(&ledger.pp, 1, 100, 0, 1)).unwrap();
+        assert!(sequencer.submit(withdraw(&ledger.pp, 1, 100, 0, 1)).unwrap_err().contains("already queued"));
+        assert!(sequencer.submit(withdraw(&ledger.pp, 1, 100, 1, 1)).unwrap_err().contains("Nonce 1"));
+        sequencer.submit(withdraw(&ledger.pp, 1, 100, 1, 2)).unwrap();
+        // Anyone can deposit to a key, so a deposit leaves the account's nonces alone
+        sequencer.submit(Intent::new(deposit_action(&ledger.pp, 2, 1), u64::MAX, None)).unwrap();
+        sequencer.submit(withdraw(&ledger.pp, 2, 200, 0, 1)).unwrap();
+        // And another account's spend key cannot take the nonce of this one
+        let mut intent = withdraw(&ledger.pp, 3, 300, 0, u64::MAX);
+        intent.signature = Some(Signature::sign(spend_key(1), &intent.hash().0));
+        assert!(sequencer.submit(intent).unwrap_err().contains("not signed by"));
+        sequencer.submit(withdraw(&ledger.pp, 3, 300, 0, 1)).unwrap();
+        assert_eq!(sequencer.mempool().len(), 5);
+    }
+
+    #[test]
+    fn a_batch_takes_the_highest_fee_first_then_the_earliest() {
+        let ledger = ledger(3);
+        let mut sequencer = Sequencer::new(&ledger, RELAYER, 8);
+        sequencer.submit(withdraw(&ledger.pp, 1, 100, 1, 1)).unwrap();
+        sequencer.submit(withdraw(&ledger.pp, 2, 200, 5, 1)).unwrap();
+        sequencer.submit(withdraw(&ledger.pp, 3, 300, 1, 1)).unwrap();
+        let batch = sequencer.next_batch();
+        assert_eq!(batch_accounts(&batch), vec![account(&ledger.pp, 2), account(&ledger.pp, 1), account(&ledger.pp, 3)]);
+        assert_eq!(batch.intents.iter().map(|queued| queued.fee).collect::<Vec<_>>(), vec![5, 1, 1]);
+        assert!(batch.dropped.is_empty() && sequencer.mempool().is_empty());
+    }
+
+    #[test]
+    fn a_conflicting_intent_waits_for_the_next_batch() {
+        let ledger = ledger(3);
+        let mut sequencer = Sequencer::new(&ledger, RELAYER, 8);
+        // Both write the pending transfers of account 3
+        sequencer.submit(send(&ledger.pp, 1, 3, 1)).unwrap();
+        sequencer.submit(send(&ledger.pp, 2, 3, 1)).unwrap();
+        let batch = sequencer.next_batch();
+        assert_eq!(batch_accounts(&batch), vec![account(&ledger.pp, 1)]);
+        assert_eq!(sequencer.mempool().len(), 1);
+        let batch = sequencer.next_batch();
+        assert_eq!(batch_accounts(&batch), vec![account(&ledger.pp, 2)]);
+        assert!(sequencer.mempool().is_empty());
+    }
+
+    #[test]
+    fn a_rollover_waits_for_transfers_into_its_shard() {
+        let mut ledger = ledger(3);
+        let top_up = deposit_action(&ledger.pp, 1, 5);
+        execute(&mut ledger.pp, &mut ledger.phi, &top_up).unwrap();
+        let mut sequencer = Sequencer::new(&ledger, RELAYER, 8);
+        sequencer.submit(Intent::new(Action::Rollover(Rollover { shard: 0 }), 0, None)).unwrap();
+        // Account 3 has nothing pending, but the contract would reject the rollover after the send
+        sequencer.submit(send(&ledger.pp, 2, 3, 1)).unwrap();
+        assert_eq!(batch_accounts(&sequencer.next_batch()), vec![None]);
+        assert_eq!(batch_accounts(&sequencer.next_batch()), vec![account(&ledger.pp, 2)]);
+    }
+
+    #[test]
+    fn an_attestation_waits_for_supply_changes_in_its_asset() {
+        let ledger = ledger(5);
+        let mut sequencer = Sequencer::new(&ledger, RELAYER, 8);
+        let attest = Action::Attest(state_machine_lib::Attest {
+            balance: 100,
+            auth: Authorization::default(),
+            asset: NATIVE_ASSET,
+            threshold: 50,
+            nonce: [3; 32],
+        });
+        sequencer.submit(signed(attest, 1, &ledger.pp, 1)).unwrap();
+        // Account 5 is in shard 1, so only the total supply is shared
+        sequencer.submit(withdraw(&ledger.pp, 5, 500, 0, 1)).unwrap();
+        assert_eq!(batch_accounts(&sequencer.next_batch()), vec![account(&ledger.pp, 1)]);
+        assert_eq!(batch_accounts(&sequencer.next_batch()), vec![account(&ledger.pp, 5)]);
+    }
+
+    #[test]
+    fn a_failed_action_leaves_the_ledger_as_it_was() {
+        let ledger = ledger(3);
+        // A free list naming an open slot, which the deposit only finds after picking it
+        let (mut pp, mut phi) = (ledger.pp.clone(), ledger.phi.clone());
+        pp.free.push(0);
+        let before = pp.clone();
+        let action = deposit_action(&pp, 4, 40);
+        assert!(execute(&mut pp, &mut phi, &action).unwrap_err().contains("not empty"));
+        assert_same_ledger(&pp, &before);
+        assert_eq!(phi, ledger.phi);
+
+        let mut sequencer = Sequencer::new(&ledger, RELAYER, 8);
+        // Account 1's balance witness is wrong, so its withdrawal is dropped
+        sequencer.submit(withdraw(&ledger.pp, 1, 99, 0, 1)).unwrap();
+        sequencer.submit(withdraw(&ledger.pp, 2, 200, 0, 1)).unwrap();
+        let batch = sequencer.next_batch();
+        assert_eq!(batch_accounts(&batch), vec![account(&ledger.pp, 2)]);
+        assert_eq!(batch.dropped.len(), 1);
+        assert_same_ledger(&batch.pp, &ledger.pp);
+        let (mut pp, mut phi) = (ledger.pp.clone(), ledger.phi.clone());
+        execute(&mut pp, &mut phi, &batch.intents[0].intent.action).unwrap();
+        assert_same_ledger(&sequencer.pp, &pp);
+        assert_eq!(sequencer.phi, phi);
+    }
+}
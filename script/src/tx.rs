@@ -0,0 +1,202 @@
+//! Builds `StateMachine` calls and EIP-1559 transactions from proof bundles.
+
+use crate::contracts::StateMachine;
+use crate::rpc::RpcClient;
+use crate::ProofBundle;
+use alloy_consensus::{SignableTransaction, TxEip1559, TxEnvelope};
+use alloy_eips::eip2718::Encodable2718;
+use alloy_primitives::{Address, Bytes, Signature, TxKind, U256};
+use alloy_sol_types::SolCall;
+use k256::ecdsa::SigningKey;
+use state_machine_lib::{DecodedPublicValues, NATIVE_ASSET};
+use std::path::Path;
+
+/// The calldata and `msg.value` of a `StateMachine` call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateMachineCall {
+    pub data: Vec<u8>,
+    pub value: U256,
+}
+
+/// Builds the `StateMachine` call that submits the proof in `bundle`. ETH deposits carry the
+/// deposited amount as value, every other action is value-less; token deposits are pulled by the
+/// contract and need an ERC-20 approval first. Attestations are not submitted, they are checked
+/// off-chain by the party they are for.
+pub fn build_call(bundle: &ProofBundle) -> Result<StateMachineCall, String> {
+    if !bundle.system.is_onchain() {
+        return Err(format!("{:?} proofs cannot be verified on-chain", bundle.system));
+    }
+    let public_values = bundle.public_values_bytes()?;
+    let proof = bundle.proof_bytes()?;
+    let decoded = DecodedPublicValues::decode(bundle.action, &public_values)?;
+    let (public_values, proof_bytes): (Bytes, Bytes) = (public_values.into(), proof.into());
+    let call = match decoded {
+        DecodedPublicValues::Deposit(deposit) => StateMachineCall {
+            data: StateMachine::depositCall { _publicValues: public_values, _proofBytes: proof_bytes }.abi_encode(),
+            value: if deposit.asset == NATIVE_ASSET { U256::from(deposit.amount) } else { U256::ZERO },
+        },
+        DecodedPublicValues::Send(_) => StateMachineCall {
+            data: StateMachine::sendCall { _publicValues: public_values, _proofBytes: proof_bytes }.abi_encode(),
+            value: U256::ZERO,
+        },
+        DecodedPublicValues::SendMany(_) => StateMachineCall {
+            data: StateMachine::sendManyCall { _publicValues: public_values, _proofBytes: proof_bytes }.abi_encode(),
+            value: U256::ZERO,
+        },
+        DecodedPublicValues::Withdraw(_) => StateMachineCall {
+            data: StateMachine::withdrawCall { _publicValues: public_values, _proofBytes: proof_bytes }.abi_encode(),
+            value: U256::ZERO,
+        },
+        DecodedPublicValues::Rotate(_) => StateMachineCall {
+            data: StateMachine::rotateCall { _publicValues: public_values, _proofBytes: proof_bytes }.abi_encode(),
+            value: U256::ZERO,
+        },
+        DecodedPublicValues::Close(_) => StateMachineCall {
+            data: StateMachine::closeCall { _publicValues: public_values, _proofBytes: proof_bytes }.abi_encode(),
+            value: U256::ZERO,
+        },
+        DecodedPublicValues::Rollover(_) => StateMachineCall {
+            data: StateMachine::rolloverCall { _publicValues: public_values, _proofBytes: proof_bytes }.abi_encode(),
+            value: U256::ZERO,
+        },
+        DecodedPublicValues::Attest(_) => return Err("Attestations are checked off-chain, not submitted".to_string()),
+    };
+    Ok(call)
+}
+
+/// The chain-dependent fields of a transaction.
+#[derive(Debug, Clone, Copy)]
+pub struct TxParams {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub gas_limit: u128,
+    pub max_fee_per_gas: u128,
+    pub max_priority_fee_per_gas: u128,
+}
+
+pub fn build_transaction(call: &StateMachineCall, state_machine: Address, params: &TxParams) -> TxEip1559 {
+    TxEip1559 {
+        chain_id: params.chain_id,
+        nonce: params.nonce,
+        gas_limit: params.gas_limit,
+        max_fee_per_gas: params.max_fee_per_gas,
+        max_priority_fee_per_gas: params.max_priority_fee_per_gas,
+        to: TxKind::Call(state_machine),
+        value: call.value,
+        access_list: Default::default(),
+        input: call.data.clone().into(),
+    }
+}
+
+/// Signs `tx` and returns it EIP-2718 encoded, ready for `eth_sendRawTransaction`.
+pub fn sign_transaction(tx: TxEip1559, key: &SigningKey) -> Result<Vec<u8>, String> {
+    let hash = tx.signature_hash();
+    let signature: Signature = key
+        .sign_prehash_recoverable(hash.as_slice())
+        .map_err(|e| format!("Failed to sign transaction: {}", e))?
+        .into();
+    Ok(TxEnvelope::from(tx.into_signed(signature)).encoded_2718())
+}
+
+/// Signs the call that submits `bundle` with `key` and sends it to `state_machine` through `rpc`,
+/// with the chain id, nonce and fees read from the node. Returns the transaction hash.
+pub fn send(rpc: &RpcClient, bundle: &ProofBundle, state_machine: Address, key: &SigningKey, gas_limit: u128) -> Result<String, String> {
+    let call = build_call(bundle)?;
+    let max_priority_fee_per_gas = rpc.max_priority_fee_per_gas()?;
+    let params = TxParams {
+        chain_id: rpc.chain_id()?,
+        nonce: rpc.nonce(&signer_address(key).to_string())?,
+        gas_limit,
+        max_fee_per_gas: 2 * rpc.gas_price()? + max_priority_fee_per_gas,
+        max_priority_fee_per_gas,
+    };
+    let raw = sign_transaction(build_transaction(&call, state_machine, &params), key)?;
+    rpc.send_raw_transaction(&raw)
+}
+
+/// The address controlled by `key`.
+pub fn signer_address(key: &SigningKey) -> Address {
+    Address::from_public_key(key.verifying_key())
+}
+
+/// Decrypts an Ethereum v3 keystore holding the transaction signing key.
+pub fn load_signing_key(path: impl AsRef<Path>, password: &str) -> Result<SigningKey, String> {
+    let secret = eth_keystore::decrypt_key(path, password).map_err(|e| format!("Failed to decrypt keystore: {}", e))?;
+    SigningKey::from_slice(&secret).map_err(|e| format!("Invalid signing key: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProofSystem;
+    use alloy_consensus::Transaction;
+    use alloy_eips::eip2718::Decodable2718;
+    use state_machine_lib::testing::Ledger;
+    use state_machine_lib::ActionKind;
+
+    fn bundle(action: ActionKind, public_values: &[u8]) -> ProofBundle {
+        ProofBundle {
+            version: ProofBundle::VERSION,
+            action,
+            system: ProofSystem::Groth16,
+            vkey: format!("0x{}", "00".repeat(32)),
+            srs_fingerprint: format!("0x{}", "00".repeat(32)),
+            public_values: format!("0x{}", hex::encode(public_values)),
+            proof: "0xdeadbeef".to_string(),
+            sp1_proof: "0x".to_string(),
+        }
+    }
+
+    fn params() -> TxParams {
+        TxParams { chain_id: 31337, nonce: 7, gas_limit: 3_000_000, max_fee_per_gas: 2_000_000_000, max_priority_fee_per_gas: 1_000_000_000 }
+    }
+
+    #[test]
+    fn builds_the_call_of_every_action() {
+        let mut ledger = Ledger::new(4);
+        let mut calls = vec![(ActionKind::Deposit, ledger.deposit(1, 100), StateMachine::depositCall::SELECTOR, U256::from(100))];
+        ledger.deposit(2, 0);
+        ledger.deposit(3, 0);
+        calls.push((ActionKind::Send, ledger.send(1, 2, 100, 30, 1), StateMachine::sendCall::SELECTOR, U256::ZERO));
+        calls.push((ActionKind::SendMany, ledger.send_many(1, &[(2, 5), (3, 5)], 69), StateMachine::sendManyCall::SELECTOR, U256::ZERO));
+        calls.push((ActionKind::Withdraw, ledger.withdraw(1, 59, 9, 0), StateMachine::withdrawCall::SELECTOR, U256::ZERO));
+        calls.push((ActionKind::Rotate, ledger.rotate(1, None), StateMachine::rotateCall::SELECTOR, U256::ZERO));
+        calls.push((ActionKind::Rollover, ledger.rollover(0), StateMachine::rolloverCall::SELECTOR, U256::ZERO));
+        calls.push((ActionKind::Withdraw, ledger.withdraw(3, 5, 5, 0), StateMachine::withdrawCall::SELECTOR, U256::ZERO));
+        calls.push((ActionKind::Close, ledger.close(3), StateMachine::closeCall::SELECTOR, U256::ZERO));
+        for (action, public_values, selector, value) in calls {
+            let call = build_call(&bundle(action, &public_values)).unwrap();
+            assert_eq!((&call.data[..4], call.value), (&selector[..], value), "{:?}", action);
+            // Every call takes the same arguments, whatever its selector
+            let decoded = StateMachine::sendCall::abi_decode_raw(&call.data[4..], true).unwrap();
+            assert_eq!((decoded._publicValues.to_vec(), decoded._proofBytes.to_vec()), (public_values, vec![0xde, 0xad, 0xbe, 0xef]));
+        }
+    }
+
+    #[test]
+    fn does_not_build_a_call_for_an_attestation_or_an_offchain_proof() {
+        let mut ledger = Ledger::new(4);
+        let deposit = ledger.deposit(1, 100);
+        let rejected = build_call(&bundle(ActionKind::Attest, &ledger.attest(1, 100, 50)));
+        assert_eq!(rejected, Err("Attestations are checked off-chain, not submitted".to_string()));
+        let mut compressed = bundle(ActionKind::Deposit, &deposit);
+        compressed.system = ProofSystem::Compressed;
+        assert_eq!(build_call(&compressed), Err("Compressed proofs cannot be verified on-chain".to_string()));
+    }
+
+    #[test]
+    fn signs_a_transaction_its_signer_is_recovered_from() {
+        let key = SigningKey::from_slice(&[0x11; 32]).unwrap();
+        let state_machine = Address::repeat_byte(0x5a);
+        let call = StateMachineCall { data: vec![1, 2, 3, 4], value: U256::from(100) };
+        let tx = build_transaction(&call, state_machine, &params());
+        assert_eq!((tx.to, tx.value, tx.input.to_vec()), (TxKind::Call(state_machine), U256::from(100), vec![1, 2, 3, 4]));
+
+        let raw = sign_transaction(tx.clone(), &key).unwrap();
+        let TxEnvelope::Eip1559(signed) = TxEnvelope::decode_2718(&mut raw.as_slice()).unwrap() else { panic!("not an EIP-1559 transaction") };
+        assert_eq!(signed.tx(), &tx);
+        assert_eq!((signed.tx().chain_id(), signed.tx().nonce()), (Some(31337), 7));
+        assert_eq!(signed.recover_signer().unwrap(), signer_address(&key));
+        assert_ne!(signed.recover_signer().unwrap(), signer_address(&SigningKey::from_slice(&[0x22; 32]).unwrap()));
+    }
+}
@@ -0,0 +1,214 @@
+//! A local wallet: the ElGamal secrets of one account, encrypted at rest, plus what the holder
+//! last learned about the account from the ledger. An account holds a single asset, so a key
+//! holding several assets needs a wallet, and a mnemonic account, per asset.
+//!
+//! A wallet is a directory with two files. `keystore.json` is a [`Keystore`] holding the account's
+//! secrets; `wallet.json` holds the public [`WalletState`].
+
+use crate::indexer::{asset_to_hex, decode_asset, Ledger};
+use crate::keystore::{Keystore, KeystoreSecrets};
+use crate::seed::Seed;
+use serde::{Deserialize, Serialize};
+use sp1_bls12_381::Scalar;
+use state_machine_lib::public_values::{g1_to_hex, scalar_to_hex};
+use state_machine_lib::{account_key, scalar_to_exponent, view_key, ElGamal, PublicParams, ReceivedNote};
+use std::path::{Path, PathBuf};
+
+const KEYSTORE_FILE: &str = "keystore.json";
+const STATE_FILE: &str = "wallet.json";
+
+/// What the wallet knows about its account, as of the last sync.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WalletState {
+    pub pkey: String,
+    /// The token the account holds, empty for wallets created before assets were tracked, which
+    /// hold ETH.
+    #[serde(default)]
+    pub asset: String,
+    /// The account's slot in the ledger, once its deposit has been indexed.
+    pub index: Option<usize>,
+    /// The spendable balance.
+    pub balance: u64,
+    /// Transfers and top-ups received since the last rollover of the account's shard, which cannot
+    /// be spent until then.
+    #[serde(default)]
+    pub pending: u64,
+    /// The phi of the account's shard the balance was read at.
+    pub phi: Option<String>,
+    /// How many of the ledger's notes have been scanned.
+    #[serde(default)]
+    pub notes_seen: usize,
+}
+
+pub struct Wallet {
+    dir: PathBuf,
+    password: String,
+    pub secrets: KeystoreSecrets,
+    pub state: WalletState,
+    pub asset: [u8; 20],
+}
+
+/// Draws a uniformly random exponent.
+pub fn random_exponent() -> [u64; 4] {
+    let mut bytes = [0u8; 64];
+    rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut bytes);
+    scalar_to_exponent(&Scalar::from_bytes_wide(&bytes))
+}
+
+impl Wallet {
+    /// Creates a wallet for `account` of a mnemonic, holding `asset`. The account is found by the
+    /// next sync.
+    pub fn from_mnemonic(
+        dir: impl AsRef<Path>,
+        password: &str,
+        mnemonic: &str,
+        account: u32,
+        asset: [u8; 20],
+        pp: &PublicParams,
+    ) -> Result<Wallet, String> {
+        let seed = Seed::from_mnemonic(mnemonic)?;
+        Wallet::import(dir, password, seed.secrets(account), asset, pp)
+    }
+
+    /// Creates a wallet holding existing secrets for an account in `asset`. The account index and
+    /// balance are picked up by the next sync.
+    pub fn import(
+        dir: impl AsRef<Path>,
+        password: &str,
+        secrets: KeystoreSecrets,
+        asset: [u8; 20],
+        pp: &PublicParams,
+    ) -> Result<Wallet, String> {
+        if dir.as_ref().join(KEYSTORE_FILE).exists() {
+            return Err(format!("{} already holds a wallet", dir.as_ref().display()));
+        }
+        if secrets.spend_key.is_some_and(|spend_key| view_key(spend_key) != secrets.skey) {
+            return Err("The view key is not derived from the spend key".to_string());
+        }
+        let pkey = ElGamal::new(pp.g).from_skey(secrets.skey);
+        let wallet = Wallet {
+            dir: dir.as_ref().to_path_buf(),
+            password: password.to_string(),
+            secrets,
+            state: WalletState {
+                pkey: scalar_to_hex(&pkey),
+                asset: asset_to_hex(&asset),
+                index: None,
+                balance: 0,
+                pending: 0,
+                phi: None,
+                notes_seen: 0,
+            },
+            asset,
+        };
+        wallet.save()?;
+        Ok(wallet)
+    }
+
+    pub fn open(dir: impl AsRef<Path>, password: &str) -> Result<Wallet, String> {
+        let dir = dir.as_ref();
+        let keystore = Keystore::load(dir.join(KEYSTORE_FILE))?;
+        let secrets = keystore
+            .decrypt(password)
+            .map_err(|e| format!("Failed to unlock {}: {}", dir.join(KEYSTORE_FILE).display(), e))?;
+        let json = std::fs::read_to_string(dir.join(STATE_FILE))
+            .map_err(|e| format!("Failed to read {}: {}", dir.join(STATE_FILE).display(), e))?;
+        let state: WalletState = serde_json::from_str(&json).map_err(|e| format!("Malformed wallet state: {}", e))?;
+        if state.pkey != keystore.pkey {
+            return Err("The keystore does not belong to this wallet".to_string());
+        }
+        let asset = decode_asset(&state.asset)?;
+        Ok(Wallet { dir: dir.to_path_buf(), password: password.to_string(), secrets, state, asset })
+    }
+
+    /// Writes the state and re-encrypts the secrets.
+    pub fn save(&self) -> Result<(), String> {
+        self.keystore(&self.password)?.save(self.dir.join(KEYSTORE_FILE))?;
+        let json = serde_json::to_string_pretty(&self.state).map_err(|e| e.to_string())?;
+        std::fs::write(self.dir.join(STATE_FILE), json)
+            .map_err(|e| format!("Failed to write {}: {}", self.dir.join(STATE_FILE).display(), e))
+    }
+
+    /// The secrets sealed under `password`, e.g. to export them.
+    pub fn keystore(&self, password: &str) -> Result<Keystore, String> {
+        Keystore::encrypt(&self.secrets, &self.state.pkey, password)
+    }
+
+    pub fn pkey(&self, pp: &PublicParams) -> Scalar {
+        ElGamal::new(pp.g).from_skey(self.secrets.skey)
+    }
+
+    /// The spend key that signs every action but a deposit or a rollover.
+    pub fn spend_key(&self) -> Result<[u64; 4], String> {
+        self.secrets.spend_key.ok_or("This wallet can only view the account".to_string())
+    }
+
+    /// The secrets of a view-only copy of the wallet, without the spend key or the mnemonic it is
+    /// derived from.
+    pub fn view_only(&self) -> KeystoreSecrets {
+        KeystoreSecrets { spend_key: None, seed: None, pending_rekey: None, ..self.secrets.clone() }
+    }
+
+    /// The notes in `ledger` addressed to this wallet, decrypted, starting from the `from`-th note.
+    pub fn received_notes(&self, ledger: &Ledger, from: usize) -> Vec<ReceivedNote> {
+        let pkey = self.pkey(&ledger.pp);
+        ledger.notes[from.min(ledger.notes.len())..]
+            .iter()
+            .filter(|entry| entry.pkey_receiver == pkey && entry.asset == self.asset)
+            .filter_map(|entry| {
+                let (amount, memo) = entry.note.decrypt(self.secrets.skey).ok()?;
+                Some(ReceivedNote { sender: entry.pkey_sender, asset: entry.asset, amount, memo })
+            })
+            .collect()
+    }
+
+    /// Switches to the key pair of a pending rekey once the ledger shows the account under it. The
+    /// new secrets are not derived from the seed, so the mnemonic no longer recovers the account.
+    fn settle_rekey(&mut self, pp: &PublicParams) {
+        let Some(rekey) = self.secrets.pending_rekey.clone() else {
+            return;
+        };
+        let skey = view_key(rekey.spend_key);
+        let new_pkey = ElGamal::new(pp.g).from_skey(skey);
+        if pp.index_of.contains_key(&account_key(&new_pkey, &self.asset)) {
+            self.secrets = KeystoreSecrets {
+                skey,
+                spend_key: Some(rekey.spend_key),
+                random: rekey.random,
+                seed: None,
+                pending_rekey: None,
+            };
+            self.state.pkey = scalar_to_hex(&new_pkey);
+        }
+    }
+
+    /// Finds the account in `ledger`, settles a pending rekey if it has landed, and decrypts the
+    /// spendable balance and the amount pending. The last known balance, with and without what
+    /// was pending and the notes received since, is tried first, and otherwise every value up to
+    /// `max_balance`. Only the view key is needed, since rollovers and rotations change the
+    /// randomness of the account but not the key it is encrypted to.
+    pub fn sync(&mut self, ledger: &Ledger, max_balance: u64) -> Result<u64, String> {
+        let pp = &ledger.pp;
+        self.settle_rekey(pp);
+        let el_gamal = ElGamal::new(pp.g);
+        let idx = match pp.index_of.get(&account_key(&self.pkey(pp), &self.asset)) {
+            Some(idx) => *idx,
+            None => return Err("The account has not been deposited to yet".to_string()),
+        };
+        let (t, v) = (pp.t[idx], pp.v[idx]);
+        let received: u64 = self.received_notes(ledger, self.state.notes_seen).iter().map(|note| note.amount).sum();
+        // What was pending reaches the spendable balance once the shard is rolled over
+        let known = [self.state.balance, self.state.balance + self.state.pending, self.state.balance + self.state.pending + received];
+        let balance = match known.iter().find_map(|x| el_gamal.decrypt(self.secrets.skey, t, v, *x).ok()) {
+            Some(balance) => balance,
+            None => el_gamal.solve(self.secrets.skey, t, v, max_balance)?,
+        };
+        self.state.index = Some(idx);
+        self.state.balance = balance;
+        self.state.pending = pp.pending_amount(self.secrets.skey, idx, max_balance)?;
+        self.state.phi = Some(g1_to_hex(&ledger.phi[pp.locate(idx).0]));
+        self.state.notes_seen = ledger.notes.len();
+        Ok(balance)
+    }
+}
@@ -0,0 +1,373 @@
+//! Runs the deposit, send, withdraw, rollover, rotate, rekey, close, send-many and attest flow end-to-end against the contracts deployed
+//! in an in-memory EVM, with an auditor, checking the on-chain state after every action.
+//!
+//! The contracts have to be compiled first. The proofs are checked by `SP1MockVerifier`, so the
+//! test always proves with the mock prover:
+//! ```shell
+//! (cd ../contracts && forge build)
+//! cargo test --release --test evm_harness
+//! ```
+
+use hex::decode;
+use k256::ecdsa::SigningKey;
+use revm::primitives::{Address, B256, U256};
+use sp1_bls12_381::{G1Affine, Scalar};
+use sp1_sdk::{include_elf, HashableKey, ProverClient, SP1ProofWithPublicValues, SP1ProvingKey, SP1Stdin};
+use state_machine_lib::{
+    account_key, audit_transfers, close, deposit, rekey, rollover, rotate, scan_notes, send, send_many, withdraw, Action, ActionKind, Attest, Authorization, Close, DecodedPublicValues,
+    Deposit, ElGamal, PublicParams, Receiver, Rekey, Rollover, Rotate, Send, SendMany, Withdraw, KZG, NATIVE_ASSET, view_key,
+};
+use state_machine_lib::public_values::g1_to_evm;
+use state_machine_script::attestation::AttestationRequest;
+use state_machine_script::harness::EvmHarness;
+use state_machine_script::indexer::Ledger;
+use state_machine_script::tx::{build_call, build_transaction, sign_transaction, signer_address, TxParams};
+use state_machine_script::ProofBundle;
+use std::str::FromStr;
+
+/// The ELF (executable and linkable format) file for the Succinct RISC-V zkVM.
+const STATEMACHINE_ELF: &[u8] = include_elf!("state-machine-program");
+
+/// Blocks per epoch, in which each shard can be rolled over once.
+const EPOCH_LENGTH: u64 = 10;
+
+fn prove(
+    client: &sp1_sdk::EnvProver,
+    pk: &SP1ProvingKey,
+    action: &Action,
+    phi: &[G1Affine],
+    pp: &PublicParams,
+) -> SP1ProofWithPublicValues {
+    let mut stdin = SP1Stdin::new();
+    stdin.write(action);
+    stdin.write(&phi.to_vec());
+    stdin.write(pp);
+    client.prove(pk, &stdin).groth16().run().expect("failed to generate proof")
+}
+
+/// Signs `action` with `spend_key` against `pp`, as a wallet does before handing it to a prover.
+fn signed(mut action: Action, spend_key: [u64; 4], pp: &PublicParams) -> Action {
+    action.sign(spend_key, pp).expect("failed to sign action");
+    action
+}
+
+/// Checks the phis, the free list and every slot the contract stores against the ledger.
+fn assert_phi(harness: &mut EvmHarness, pp: &PublicParams, phi: &[G1Affine]) {
+    assert_eq!(harness.free_list_hash().unwrap(), B256::from(pp.free_list_hash()), "on-chain free list mismatch");
+    for (shard, phi) in phi.iter().enumerate() {
+        assert_eq!(harness.phi(shard as u64).unwrap(), g1_to_evm(phi), "on-chain phi mismatch");
+        let pending = (shard * pp.degree..(shard + 1) * pp.degree).filter(|slot| pp.has_pending(*slot)).count();
+        assert_eq!(harness.pending_slots(shard as u64).unwrap(), pending as u64, "on-chain pending slot count mismatch");
+    }
+    for slot in 0..pp.v.len() {
+        let (shard, index) = pp.locate(slot);
+        let ciphertext = (B256::from(pp.t[slot].to_bytes()), B256::from(pp.v[slot].to_bytes()));
+        assert_eq!(harness.ciphertext(shard as u64, index as u64).unwrap(), ciphertext, "on-chain ciphertext mismatch");
+        assert_eq!(harness.account(shard as u64, index as u64).unwrap(), B256::from(pp.account(slot)), "on-chain account mismatch");
+        let pending = (B256::from(pp.pending_t[slot].to_bytes()), B256::from(pp.pending_v[slot].to_bytes()));
+        assert_eq!(harness.pending_transfers(shard as u64, index as u64).unwrap(), pending, "on-chain pending transfers mismatch");
+    }
+}
+
+/// Checks that the contract holds exactly the total supply both sides have counted.
+fn assert_supply(harness: &mut EvmHarness, pp: &PublicParams, supply: u64) {
+    assert_eq!(harness.balance(harness.state_machine), U256::from(supply), "contract balance mismatch");
+    assert_eq!(pp.supply(&NATIVE_ASSET), supply, "total supply mismatch");
+    assert_eq!(harness.total_supply(NATIVE_ASSET).unwrap(), U256::from(supply), "on-chain total supply mismatch");
+    assert!(harness.solvent(NATIVE_ASSET).unwrap(), "contract is insolvent");
+}
+
+// Needs the `forge build` artifacts, and sets `SP1_PROVER`, so it only runs when asked for:
+// `cargo test --release --test evm_harness -- --ignored`
+#[test]
+#[ignore]
+fn end_to_end() {
+    // Setup the prover client.
+    std::env::set_var("SP1_PROVER", "mock");
+    let client = ProverClient::from_env();
+    let (pk, vk) = client.setup(STATEMACHINE_ELF);
+
+    let mut pp = PublicParams::setup(16);
+    let el_gamal = ElGamal::new(pp.g);
+    let sk_auditor = [21u64, 22, 23, 24];
+    pp = pp.with_auditor(el_gamal.from_skey(sk_auditor));
+    let kzg = KZG::new(pp.g1_lagrange_basis.clone());
+    let v = vec![Scalar::zero(); pp.degree];
+    let mut phi = vec![kzg.commit(v).unwrap()];
+
+    let vkey = B256::from_str(&vk.bytes32()).expect("invalid vkey");
+    let mut harness = EvmHarness::new(vkey, &phi[0], pp.auditor, EPOCH_LENGTH).expect("failed to deploy contracts");
+    assert_phi(&mut harness, &pp, &phi);
+
+    let spend_a = [1u64, 2, 3, 4];
+    let sk_a = view_key(spend_a);
+    let pk_a = el_gamal.from_skey(sk_a);
+    let spend_b = [5u64, 6, 7, 8];
+    let sk_b = view_key(spend_b);
+    let pk_b = el_gamal.from_skey(sk_b);
+    let (mut m_a, mut m_b) = (100u64, 200u64);
+    let (r_a, r_b) = ([0x1111u64, 0, 0, 0], [0x2222u64, 0, 0, 0]);
+    let key_a = SigningKey::from_slice(&[0x11; 32]).unwrap();
+    let (user_a, user_b, relayer) = (signer_address(&key_a), Address::repeat_byte(0xb), Address::repeat_byte(0xc));
+    harness.fund(user_a, U256::from(m_a + 1_000_000));
+    harness.fund(user_b, U256::from(m_b));
+
+    // User A deposits
+    let action = Action::Deposit(Deposit { pkey: pk_a, asset: NATIVE_ASSET, random: r_a, amount: m_a });
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    // Submitted as a signed transaction built from the proof bundle, like a wallet would.
+    let bundle = ProofBundle::new(ActionKind::Deposit, &proof, &vk, &pp);
+    let call = build_call(&bundle).expect("failed to build call");
+    assert_eq!(call.value, U256::from(m_a));
+    let params = TxParams {
+        chain_id: harness.chain_id(),
+        nonce: harness.nonce(user_a),
+        gas_limit: 1_000_000,
+        max_fee_per_gas: 1,
+        max_priority_fee_per_gas: 1,
+    };
+    let raw = sign_transaction(build_transaction(&call, harness.state_machine, &params), &key_a).unwrap();
+    harness.submit_raw_transaction(&raw).expect("deposit failed");
+    phi = deposit(&mut pp, pk_a, NATIVE_ASSET, r_a, m_a, &phi).unwrap();
+    assert_phi(&mut harness, &pp, &phi);
+    assert_supply(&mut harness, &pp, m_a);
+    assert_eq!(harness.nonce(user_a), 1);
+
+    // User B deposits
+    let action = Action::Deposit(Deposit { pkey: pk_b, asset: NATIVE_ASSET, random: r_b, amount: m_b });
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    harness.deposit(user_b, proof.public_values.as_slice(), &proof.bytes(), m_b).expect("deposit failed");
+    phi = deposit(&mut pp, pk_b, NATIVE_ASSET, r_b, m_b, &phi).unwrap();
+    assert_phi(&mut harness, &pp, &phi);
+    assert_supply(&mut harness, &pp, m_a + m_b);
+
+    // A's withdrawal is proven before anything is sent to A, and stays valid however much arrives
+    let (amount, fee) = (10u64, 2u64);
+    // User A proves a withdrawal to a fresh address, paying the relayer a fee
+    let recipient: [u8; 20] = decode("65f697a02d756Cf4BC3465c1cC60dB3a4AF19521").unwrap().try_into().unwrap();
+    let action = signed(Action::Withdraw(Withdraw { balance: m_a, amount, auth: Authorization::default(), asset: NATIVE_ASSET, recipient, fee, relayer: relayer.into(), debit_random: [0xeeeeu64, 0, 0, 0] }), spend_a, &pp);
+    let withdraw_proof = prove(&client, &pk, &action, &phi, &pp);
+
+    let (top_up, r_top_up) = (5u64, [0x9999u64, 0, 0, 0]);
+    // User B tops up User A
+    let action = Action::Deposit(Deposit { pkey: pk_a, asset: NATIVE_ASSET, random: r_top_up, amount: top_up });
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    let decoded = DecodedPublicValues::decode(ActionKind::Deposit, proof.public_values.as_slice()).unwrap();
+    assert!(matches!(decoded, DecodedPublicValues::Deposit(ref d) if d.top_up && d.index == 0 && d.writes.slots.is_empty()));
+    harness.fund(user_b, U256::from(m_b + top_up));
+    harness.deposit(user_b, proof.public_values.as_slice(), &proof.bytes(), top_up).expect("top-up failed");
+    let old_phi = phi.clone();
+    phi = deposit(&mut pp, pk_a, NATIVE_ASSET, r_top_up, top_up, &phi).unwrap();
+    let mut pending_a = top_up;
+    assert_eq!(phi, old_phi, "a top-up moved the phi");
+    assert_eq!(pp.idx, 2);
+    assert_eq!(pp.pending_amount(sk_a, 0, pending_a), Ok(pending_a));
+    assert_phi(&mut harness, &pp, &phi);
+    assert_supply(&mut harness, &pp, m_a + m_b + pending_a);
+
+    // User A's withdrawal lands after the top-up
+    let relayer_balance = harness.balance(relayer);
+    harness.withdraw(relayer, withdraw_proof.public_values.as_slice(), &withdraw_proof.bytes()).expect("withdraw failed");
+    phi = withdraw(&mut pp, sk_a, NATIVE_ASSET, m_a, amount, fee, [0xeeeeu64, 0, 0, 0], &phi, recipient).unwrap();
+    m_a -= amount + fee;
+    assert_phi(&mut harness, &pp, &phi);
+    assert_eq!(harness.balance(Address::from(recipient)), U256::from(amount));
+    assert_eq!(harness.balance(relayer), relayer_balance + U256::from(fee));
+    assert_supply(&mut harness, &pp, m_a + m_b + pending_a);
+
+    // A ledger passing User A's ETH slot off as a token account cannot withdraw the token
+    let token = [0x70u8; 20];
+    let mut forged = pp.clone();
+    forged.assets[0] = token;
+    forged.index_of.insert(account_key(&pk_a, &token), 0);
+    forged.supply.insert(token, m_a);
+    let action = signed(Action::Withdraw(Withdraw { balance: m_a, amount: 1, auth: Authorization::default(), asset: token, recipient, fee: 0, relayer: [0u8; 20], debit_random: [0x7070u64, 0, 0, 0] }), spend_a, &forged);
+    let proof = prove(&client, &pk, &action, &phi, &forged);
+    assert!(harness.withdraw(relayer, proof.public_values.as_slice(), &proof.bytes()).is_err(), "withdrawal from another asset's slot accepted");
+
+    let amount = 30u64;
+    // User B sends to User A
+    let action = signed(Action::Send(Send {
+        balance_sender: m_b,
+        amount,
+        auth_sender: Authorization::default(),
+        pkey_receiver: pk_a,
+        asset: NATIVE_ASSET,
+        fee: 0,
+        relayer: [0u8; 20],
+        note_random: [0x3333u64, 0, 0, 0],
+        transfer_random: [0xaaaau64, 0, 0, 0],
+        debit_random: [0xabcdu64, 0, 0, 0],
+        memo: b"rent".to_vec(),
+    }), spend_b, &pp);
+    let mut unaudited = pp.clone();
+    unaudited.auditor = None;
+    let proof = prove(&client, &pk, &action, &phi, &unaudited);
+    assert!(harness.send(relayer, proof.public_values.as_slice(), &proof.bytes()).is_err(), "send without escrow accepted");
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    harness.send(relayer, proof.public_values.as_slice(), &proof.bytes()).expect("send failed");
+    phi = send(&mut pp, sk_b, pk_a, NATIVE_ASSET, m_b, amount, 0, [0xaaaau64, 0, 0, 0], [0xabcdu64, 0, 0, 0], &phi).unwrap();
+    // User A only learns the amount from the note committed in the public values.
+    let decoded = DecodedPublicValues::decode(ActionKind::Send, proof.public_values.as_slice()).unwrap();
+    let received = scan_notes(pp.g, sk_a, [&decoded]);
+    assert_eq!(received.len(), 1, "note not found");
+    assert_eq!((received[0].amount, received[0].memo.as_slice()), (amount, &b"rent"[..]));
+    assert!(scan_notes(pp.g, sk_b, [&decoded]).is_empty());
+    // The auditor reads the amount too, but holds no spending key.
+    assert_eq!(audit_transfers(sk_auditor, [&decoded]).unwrap()[0].amount, amount);
+    m_b -= amount;
+    pending_a += received[0].amount;
+    assert_eq!(el_gamal.decrypt(sk_a, pp.t[0], pp.v[0], m_a), Ok(m_a), "a transfer in moved the spendable balance");
+    assert_phi(&mut harness, &pp, &phi);
+    assert_supply(&mut harness, &pp, m_a + m_b + pending_a);
+
+    // Anyone rolls shard 0 over, making what is pending for User A spendable
+    let action = Action::Rollover(Rollover { shard: 0 });
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    harness.rollover(relayer, proof.public_values.as_slice(), &proof.bytes()).expect("rollover failed");
+    phi = rollover(&mut pp, 0, &phi).unwrap();
+    m_a += pending_a;
+    assert_eq!(el_gamal.decrypt(sk_a, pp.t[0], pp.v[0], m_a), Ok(m_a));
+    assert_eq!(pp.pending_amount(sk_a, 0, m_a), Ok(0));
+    assert_phi(&mut harness, &pp, &phi);
+    assert_supply(&mut harness, &pp, m_a + m_b);
+
+    // User A rotates their secret while User B withdraws everything left, both against the same ledger
+    let new_additive = [1u64, 0, 0, 0];
+    let action = signed(Action::Rotate(Rotate { auth: Authorization::default(), asset: NATIVE_ASSET, new_additive, rekey: None }), spend_a, &pp);
+    let rotate_proof = prove(&client, &pk, &action, &phi, &pp);
+    let action = signed(Action::Withdraw(Withdraw { balance: m_b, amount: m_b, auth: Authorization::default(), asset: NATIVE_ASSET, recipient: user_b.into(), fee: 0, relayer: [0u8; 20], debit_random: [0xffffu64, 0, 0, 0] }), spend_b, &pp);
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    // They write different slots, so B's withdrawal landing first leaves A's rotation valid
+    harness.withdraw(relayer, proof.public_values.as_slice(), &proof.bytes()).expect("withdraw failed");
+    assert!(harness.withdraw(relayer, proof.public_values.as_slice(), &proof.bytes()).is_err(), "withdraw applied twice");
+    harness.rotate(relayer, rotate_proof.public_values.as_slice(), &rotate_proof.bytes()).expect("rotate failed");
+    let (next_phi, _) = rotate(&mut pp, sk_a, NATIVE_ASSET, new_additive, &phi).unwrap();
+    phi = withdraw(&mut pp, sk_b, NATIVE_ASSET, m_b, m_b, 0, [0xffffu64, 0, 0, 0], &next_phi, user_b.into()).unwrap();
+    m_b = 0;
+    assert_phi(&mut harness, &pp, &phi);
+    assert_supply(&mut harness, &pp, m_a + m_b);
+
+    // User B closes their account
+    let action = signed(Action::Close(Close { auth: Authorization::default(), asset: NATIVE_ASSET }), spend_b, &pp);
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    harness.close(relayer, proof.public_values.as_slice(), &proof.bytes()).expect("close failed");
+    let (next_phi, idx) = close(&mut pp, sk_b, NATIVE_ASSET, &phi).unwrap();
+    phi = next_phi;
+    assert_eq!(idx, 1);
+    assert_phi(&mut harness, &pp, &phi);
+    assert_eq!(harness.free_list_hash().unwrap(), B256::from(pp.free_list_hash()));
+    assert_supply(&mut harness, &pp, m_a + m_b);
+
+    let spend_c = [9u64, 10, 11, 12];
+    let sk_c = view_key(spend_c);
+    let pk_c = el_gamal.from_skey(sk_c);
+    let (m_c, r_c) = (50u64, [0x4444u64, 0, 0, 0]);
+    harness.fund(user_b, U256::from(m_c));
+    // User C deposits into the freed slot
+    let action = Action::Deposit(Deposit { pkey: pk_c, asset: NATIVE_ASSET, random: r_c, amount: m_c });
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    let decoded = DecodedPublicValues::decode(ActionKind::Deposit, proof.public_values.as_slice()).unwrap();
+    assert!(matches!(decoded, DecodedPublicValues::Deposit(ref d) if !d.top_up && d.index == 1));
+    harness.deposit(user_b, proof.public_values.as_slice(), &proof.bytes(), m_c).expect("deposit failed");
+    phi = deposit(&mut pp, pk_c, NATIVE_ASSET, r_c, m_c, &phi).unwrap();
+    assert_eq!(pp.idx, 2);
+    assert_phi(&mut harness, &pp, &phi);
+    assert_eq!(harness.free_list_hash().unwrap(), B256::from(pp.free_list_hash()));
+    assert_supply(&mut harness, &pp, m_a + m_c);
+
+    // User C moves their account to a new key pair, User D
+    let spend_d = [13u64, 14, 15, 16];
+    let sk_d = view_key(spend_d);
+    let pk_d = el_gamal.from_skey(sk_d);
+    let r_d = [0x5555u64, 0, 0, 0];
+    let rekey_inputs = Rekey { balance: m_c, new_pkey: pk_d, new_random: r_d };
+    let action = signed(Action::Rotate(Rotate { auth: Authorization::default(), asset: NATIVE_ASSET, new_additive: [0u64; 4], rekey: Some(rekey_inputs) }), spend_c, &pp);
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    harness.rotate(relayer, proof.public_values.as_slice(), &proof.bytes()).expect("rekey failed");
+    let (next_phi, idx) = rekey(&mut pp, sk_c, NATIVE_ASSET, m_c, pk_d, r_d, &phi).unwrap();
+    phi = next_phi;
+    assert_eq!(idx, 1);
+    assert_phi(&mut harness, &pp, &phi);
+    assert_eq!(el_gamal.decrypt(sk_d, pp.t[idx], pp.v[idx], m_c), Ok(m_c));
+    assert!(el_gamal.decrypt(sk_c, pp.t[idx], pp.v[idx], m_c).is_err());
+
+    let sk_e = view_key([17u64, 18, 19, 20]);
+    let pk_e = el_gamal.from_skey(sk_e);
+    let (mut m_e, r_e) = (1u64, [0x6666u64, 0, 0, 0]);
+    harness.fund(user_b, U256::from(m_e));
+    // User E deposits
+    let action = Action::Deposit(Deposit { pkey: pk_e, asset: NATIVE_ASSET, random: r_e, amount: m_e });
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    harness.deposit(user_b, proof.public_values.as_slice(), &proof.bytes(), m_e).expect("deposit failed");
+    phi = deposit(&mut pp, pk_e, NATIVE_ASSET, r_e, m_e, &phi).unwrap();
+    assert_phi(&mut harness, &pp, &phi);
+
+    let (amount_a, amount_e) = (5u64, 7u64);
+    // User D pays User A and User E in one proof
+    let receivers = vec![
+        Receiver { pkey: pk_a, amount: amount_a, note_random: [0x7777u64, 0, 0, 0], transfer_random: [0xbbbbu64, 0, 0, 0], memo: b"split".to_vec() },
+        Receiver { pkey: pk_e, amount: amount_e, note_random: [0x8888u64, 0, 0, 0], transfer_random: [0xccccu64, 0, 0, 0], memo: b"split".to_vec() },
+    ];
+    let targets: Vec<_> = receivers.iter().map(|r| (r.pkey, r.amount, r.transfer_random)).collect();
+    let action = signed(Action::SendMany(SendMany { balance_sender: m_c, auth_sender: Authorization::default(), asset: NATIVE_ASSET, receivers, debit_random: [0xddddu64, 0, 0, 0] }), spend_d, &pp);
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    harness.send_many(relayer, proof.public_values.as_slice(), &proof.bytes()).expect("send many failed");
+    phi = send_many(&mut pp, sk_d, NATIVE_ASSET, &targets, m_c, [0xddddu64, 0, 0, 0], &phi).unwrap();
+    let decoded = DecodedPublicValues::decode(ActionKind::SendMany, proof.public_values.as_slice()).unwrap();
+    let received_a = scan_notes(pp.g, sk_a, [&decoded]);
+    let received_e = scan_notes(pp.g, sk_e, [&decoded]);
+    assert_eq!((received_a.len(), received_e.len()), (1, 1), "notes not found");
+    assert_eq!((received_a[0].amount, received_e[0].amount), (amount_a, amount_e));
+    let audited: Vec<_> = audit_transfers(sk_auditor, [&decoded]).unwrap().iter().map(|t| t.amount).collect();
+    assert_eq!(audited, vec![amount_a, amount_e]);
+    let m_d = m_c - amount_a - amount_e;
+    assert_eq!(el_gamal.decrypt(sk_d, pp.t[1], pp.v[1], m_d), Ok(m_d));
+    assert_eq!((pp.pending_amount(sk_a, 0, amount_a), pp.pending_amount(sk_e, 2, amount_e)), (Ok(amount_a), Ok(amount_e)));
+    assert_phi(&mut harness, &pp, &phi);
+    assert_supply(&mut harness, &pp, m_a + m_d + m_e + amount_a + amount_e);
+
+    // A rollover has to clear every slot of the shard with pending transfers
+    let action = Action::Rollover(Rollover { shard: 0 });
+    let mut partial = pp.clone();
+    partial.pending_t[2] = Scalar::zero();
+    partial.pending_v[2] = Scalar::zero();
+    let proof = prove(&client, &pk, &action, &phi, &partial);
+    assert!(harness.rollover(relayer, proof.public_values.as_slice(), &proof.bytes()).is_err(), "partial rollover accepted");
+
+    // Shard 0 can only be rolled over again in the next epoch
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    assert!(harness.rollover(relayer, proof.public_values.as_slice(), &proof.bytes()).is_err(), "second rollover in an epoch accepted");
+    harness.advance_blocks(EPOCH_LENGTH);
+    harness.rollover(relayer, proof.public_values.as_slice(), &proof.bytes()).expect("rollover failed");
+    phi = rollover(&mut pp, 0, &phi).unwrap();
+    m_a += amount_a;
+    m_e += amount_e;
+    assert_eq!(el_gamal.decrypt(sk_e, pp.t[2], pp.v[2], m_e), Ok(m_e));
+    assert_phi(&mut harness, &pp, &phi);
+    assert_supply(&mut harness, &pp, m_a + m_d + m_e);
+
+    let threshold = m_a - 1;
+    // User A attests to holding at least the threshold
+    let nonce = [0x42u8; 32];
+    let action = signed(Action::Attest(Attest { balance: m_a, auth: Authorization::default(), asset: NATIVE_ASSET, threshold, nonce }), spend_a, &pp);
+    let proof = prove(&client, &pk, &action, &phi, &pp);
+    let bundle = ProofBundle::new(ActionKind::Attest, &proof, &vk, &pp);
+    assert!(build_call(&bundle).is_err(), "attestations are not submitted");
+    let ledger = Ledger { pp: pp.clone(), phi: phi.clone(), notes: Vec::new(), last_block: None };
+    let request = AttestationRequest { pkey: pk_a, asset: NATIVE_ASSET, threshold, nonce };
+    request.check(&bundle, &vk, &ledger).expect("attestation rejected");
+    let replayed = AttestationRequest { nonce: [0x43u8; 32], ..request.clone() };
+    assert!(replayed.check(&bundle, &vk, &ledger).is_err(), "attestation accepted for another nonce");
+    let higher = AttestationRequest { threshold: m_a + 1, ..request.clone() };
+    assert!(higher.check(&bundle, &vk, &ledger).is_err(), "attestation accepted for a higher threshold");
+    harness.check_attestation(proof.public_values.as_slice(), &proof.bytes()).expect("attestation rejected on-chain");
+    // An attestation made against another total supply is turned down on both sides
+    let mut inflated = pp.clone();
+    inflated.supply.insert(NATIVE_ASSET, pp.supply(&NATIVE_ASSET) + 1);
+    let proof = prove(&client, &pk, &action, &phi, &inflated);
+    let bundle = ProofBundle::new(ActionKind::Attest, &proof, &vk, &pp);
+    assert!(request.check(&bundle, &vk, &ledger).is_err(), "attestation accepted against another supply");
+    assert!(harness.check_attestation(proof.public_values.as_slice(), &proof.bytes()).is_err(), "attestation accepted on-chain against another supply");
+    assert_phi(&mut harness, &pp, &phi);
+}